@@ -1,3 +1,14 @@
+use std::env;
+use std::process::Command;
+
 fn main() {
     println!("cargo:rustc-link-lib=dylib=ffi");
+    // Part of the build id of plugins, as they must be built by the same rustc as the compiler
+    let rustc = env::var("RUSTC").unwrap_or("rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or("unknown rustc".to_string());
+    println!("cargo:rustc-env=KVASIR_RUSTC_VERSION={}", version);
 }
@@ -1,3 +1,4 @@
+# The core library requires a POSIX system, e.g. Linux or macOS, see the README
 clang -c -o pcg_c.o src/core/pcg-c-basic/pcg_basic.c
 clang -c -o core_c.o src/core/core.c
 rm -f libcore.a
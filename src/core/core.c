@@ -1,6 +1,26 @@
+/* The core library requires a POSIX system, e.g. Linux or macOS. It uses POSIX interfaces
+ * for regexes, sockets, directories, processes, and clocks
+ */
+#define _POSIX_C_SOURCE 200809L
+#define _XOPEN_SOURCE 700
+
+#include <ctype.h>
+#include <dirent.h>
+#include <errno.h>
 #include <inttypes.h>
+#include <netdb.h>
+#include <poll.h>
+#include <regex.h>
+#include <stdarg.h>
+#include <stdbool.h>
 #include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
 #include <time.h>
+#include <unistd.h>
 #include "pcg-c-basic/pcg_basic.h"
 
 typedef struct {
@@ -28,8 +48,9 @@ void print_uint64(uint64_t n) {
     printf("%" PRIu64 "\n", n);
 }
 
+/* Print `x` with enough digits to parse back to the same value */
 void print_float64(double x) {
-    printf("%f\n", x);
+    printf("%.17g\n", x);
 }
 
 void c_display(KvasirString s) {
@@ -39,3 +60,917 @@ void c_display(KvasirString s) {
 uint64_t _clock(void) {
     return (uint64_t)clock();
 }
+
+/* Exit with the error `msg`, reported together with the source location `location` of the
+ * `panic` that called this
+ */
+void panic_at(KvasirString location, KvasirString msg) {
+    fprintf(stderr, "Panicked at %.*s: %.*s\n", (int)location.len, location.data,
+            (int)msg.len, msg.data);
+    exit(1);
+}
+
+/* Exit with the failure of the `assert-eq` of source code `source` at the source location
+ * `location`, of the operands shown as `left` and `right`
+ */
+void assert_eq_failed_at(KvasirString location, KvasirString source, KvasirString left,
+                         KvasirString right) {
+    fprintf(stderr, "Panicked at %.*s: Assertion failed: %.*s\n  left: %.*s\n right: %.*s\n",
+            (int)location.len, location.data, (int)source.len, source.data, (int)left.len,
+            left.data, (int)right.len, right.data);
+    exit(1);
+}
+
+/* Slicing of strings
+ *
+ * Strings are UTF-8 encoded, and indexed by bytes. A slice shares the data of the string
+ * it's sliced from.
+ */
+
+/* Whether `i` is the index of the start of a character in `s`, or the end of `s` */
+static bool is_char_boundary(KvasirString s, uintptr_t i) {
+    /* Continuation bytes of UTF-8 encoded characters are of the form 0b10xxxxxx */
+    return i == s.len || (i < s.len && (s.data[i] & 0xc0) != 0x80);
+}
+
+uintptr_t string_length(KvasirString s) {
+    return s.len;
+}
+
+bool can_slice_string(KvasirString s, uintptr_t start, uintptr_t end) {
+    return start <= end && is_char_boundary(s, start) && is_char_boundary(s, end);
+}
+
+/* A newly allocated string of `a` followed by `b` */
+KvasirString concat_strings(KvasirString a, KvasirString b) {
+    uint8_t *data = malloc(a.len + b.len);
+    if (a.len > 0) {
+        memcpy(data, a.data, a.len);
+    }
+    if (b.len > 0) {
+        memcpy(data + a.len, b.data, b.len);
+    }
+    return (KvasirString){ .len = a.len + b.len, .data = data };
+}
+
+KvasirString slice_string(KvasirString s, uintptr_t start, uintptr_t end) {
+    if (!can_slice_string(s, start, end)) {
+        fprintf(stderr, "Invalid slice [%" PRIuPTR ", %" PRIuPTR ") of string of length %"
+                PRIuPTR "\n", start, end, s.len);
+        exit(1);
+    }
+    return (KvasirString){ .len = end - start, .data = s.data + start };
+}
+
+typedef struct {
+    KvasirString line;
+    KvasirString rest;
+} SplitLine;
+
+/* Split `s` at the first line break, into the line before it and the rest after it. If
+ * there's no line break, the line is all of `s`
+ */
+SplitLine split_line(KvasirString s) {
+    const uint8_t *nl = s.len > 0 ? memchr(s.data, '\n', s.len) : NULL;
+    uintptr_t line_len = nl != NULL ? (uintptr_t)(nl - s.data) : s.len;
+    uintptr_t rest_start = nl != NULL ? line_len + 1 : s.len;
+    return (SplitLine){
+        .line = { .len = line_len, .data = s.data },
+        .rest = { .len = s.len - rest_start, .data = s.data + rest_start },
+    };
+}
+
+/* Bytes
+ *
+ * Byte strings are laid out like strings, but are not necessarily valid UTF-8
+ */
+
+static uint8_t hex_digit_value(uint8_t c) {
+    return isdigit(c) ? c - '0' : tolower(c) - 'a' + 10;
+}
+
+/* Returns the bytes of the hexadecimal digits `digits`, allocated on the heap, and never freed
+ *
+ * Exits with an error if `digits` is not an even number of hexadecimal digits
+ */
+KvasirString hex_to_bytes(KvasirString digits) {
+    for (uintptr_t i = 0; i < digits.len; i++) {
+        if (!isxdigit(digits.data[i]) || digits.len % 2 != 0) {
+            fprintf(stderr, "Invalid hexadecimal byte string `%.*s`\n",
+                    (int)digits.len, digits.data);
+            exit(1);
+        }
+    }
+    uintptr_t len = digits.len / 2;
+    uint8_t *data = malloc(len);
+    for (uintptr_t i = 0; i < len; i++) {
+        data[i] = hex_digit_value(digits.data[2 * i]) << 4
+                  | hex_digit_value(digits.data[2 * i + 1]);
+    }
+    return (KvasirString){ .len = len, .data = data };
+}
+
+uint8_t byte_at(KvasirString bytes, uintptr_t i) {
+    if (i >= bytes.len) {
+        fprintf(stderr, "Index %" PRIuPTR " out of bounds of bytes of length %" PRIuPTR "\n",
+                i, bytes.len);
+        exit(1);
+    }
+    return bytes.data[i];
+}
+
+/* Whether `s` is valid UTF-8, without overlong encodings or surrogates */
+bool is_utf8(KvasirString s) {
+    uintptr_t i = 0;
+    while (i < s.len) {
+        uint8_t c = s.data[i];
+        uintptr_t n_cont;
+        uint32_t code, min;
+        if (c < 0x80) {
+            i++;
+            continue;
+        } else if ((c & 0xe0) == 0xc0) {
+            n_cont = 1, code = c & 0x1f, min = 0x80;
+        } else if ((c & 0xf0) == 0xe0) {
+            n_cont = 2, code = c & 0x0f, min = 0x800;
+        } else if ((c & 0xf8) == 0xf0) {
+            n_cont = 3, code = c & 0x07, min = 0x10000;
+        } else {
+            return false;
+        }
+        if (n_cont >= s.len - i) {
+            return false;
+        }
+        for (uintptr_t j = 1; j <= n_cont; j++) {
+            if ((s.data[i + j] & 0xc0) != 0x80) {
+                return false;
+            }
+            code = code << 6 | (s.data[i + j] & 0x3f);
+        }
+        if (code < min || code > 0x10ffff || (code >= 0xd800 && code <= 0xdfff)) {
+            return false;
+        }
+        i += n_cont + 1;
+    }
+    return true;
+}
+
+/* Formatting and parsing of numbers
+ *
+ * Strings returned by the `show_*` functions are allocated on the heap, and never freed.
+ * Parsing accepts the same formats as `strtoll` and `strtod`, except that leading
+ * whitespace is not accepted, and the whole string must be consumed.
+ */
+
+static KvasirString show_(const char *fmt, ...) {
+    va_list args, args2;
+    va_start(args, fmt);
+    va_copy(args2, args);
+    int len = vsnprintf(NULL, 0, fmt, args);
+    char *data = malloc(len + 1);
+    vsnprintf(data, len + 1, fmt, args2);
+    va_end(args2);
+    va_end(args);
+    return (KvasirString){ .len = len, .data = (const uint8_t *)data };
+}
+
+KvasirString show_int64(int64_t n) {
+    return show_("%" PRIi64, n);
+}
+
+KvasirString show_uint64(uint64_t n) {
+    return show_("%" PRIu64, n);
+}
+
+/* Show `x` with enough digits to parse back to the same value */
+KvasirString show_float64(double x) {
+    return show_("%.17g", x);
+}
+
+/* Copy `s` into `buf` of size `size` as a null terminated string
+ *
+ * Returns false if `s` does not fit, is empty, or starts with whitespace
+ */
+static bool to_c_number_str(KvasirString s, char *buf, size_t size) {
+    if (s.len == 0 || s.len >= size || isspace(s.data[0])) {
+        return false;
+    }
+    memcpy(buf, s.data, s.len);
+    buf[s.len] = '\0';
+    return true;
+}
+
+static bool parse_int64_(KvasirString s, int64_t *n) {
+    char buf[32], *end;
+    if (!to_c_number_str(s, buf, sizeof buf)) {
+        return false;
+    }
+    errno = 0;
+    *n = strtoll(buf, &end, 10);
+    return errno == 0 && *end == '\0';
+}
+
+static bool parse_float64_(KvasirString s, double *x) {
+    char buf[512], *end;
+    if (!to_c_number_str(s, buf, sizeof buf)) {
+        return false;
+    }
+    *x = strtod(buf, &end);
+    return *end == '\0';
+}
+
+bool can_parse_int64(KvasirString s) {
+    int64_t n;
+    return parse_int64_(s, &n);
+}
+
+int64_t parse_int64(KvasirString s) {
+    int64_t n;
+    return parse_int64_(s, &n) ? n : 0;
+}
+
+bool can_parse_float64(KvasirString s) {
+    double x;
+    return parse_float64_(s, &x);
+}
+
+double parse_float64(KvasirString s) {
+    double x;
+    return parse_float64_(s, &x) ? x : 0.0;
+}
+
+/* Regular expressions
+ *
+ * POSIX extended regular expressions. A compiled regex is allocated on the heap, and never
+ * freed, as are the strings of captured groups.
+ */
+
+static char *to_c_str(KvasirString s);
+
+bool is_valid_regex(KvasirString pattern) {
+    char *c_pattern = to_c_str(pattern);
+    regex_t re;
+    int err = regcomp(&re, c_pattern, REG_EXTENDED);
+    free(c_pattern);
+    if (err == 0) {
+        regfree(&re);
+    }
+    return err == 0;
+}
+
+/* Exits with an error if `pattern` is not a valid regex */
+regex_t *compile_regex(KvasirString pattern) {
+    char *c_pattern = to_c_str(pattern);
+    regex_t *re = malloc(sizeof(regex_t));
+    int err = regcomp(re, c_pattern, REG_EXTENDED);
+    if (err != 0) {
+        char msg[256];
+        regerror(err, re, msg, sizeof(msg));
+        fprintf(stderr, "Invalid regex `%s`, %s\n", c_pattern, msg);
+        exit(1);
+    }
+    free(c_pattern);
+    return re;
+}
+
+/* Match `re` against `s`, filling in the bounds of the first `n_groups` groups */
+static bool regex_exec(const regex_t *re, KvasirString s, size_t n_groups, regmatch_t *groups) {
+    char *c_s = to_c_str(s);
+    bool matched = regexec(re, c_s, n_groups, groups, 0) == 0;
+    free(c_s);
+    return matched;
+}
+
+bool regex_is_match(const regex_t *re, KvasirString s) {
+    return regex_exec(re, s, 0, NULL);
+}
+
+/* The number of capture groups of `re`, not counting the whole match */
+uintptr_t regex_group_count(const regex_t *re) {
+    return re->re_nsub;
+}
+
+/* Whether `re` matches `s`, and group `n` took part in the match, where group 0 is the
+ * whole match
+ */
+bool regex_has_group(const regex_t *re, KvasirString s, uintptr_t n) {
+    if (n > re->re_nsub) {
+        return false;
+    }
+    regmatch_t *groups = malloc((n + 1) * sizeof(regmatch_t));
+    bool has = regex_exec(re, s, n + 1, groups) && groups[n].rm_so >= 0;
+    free(groups);
+    return has;
+}
+
+/* The text of group `n` of the match of `re` in `s`. Exits with an error if there's none */
+KvasirString regex_group(const regex_t *re, KvasirString s, uintptr_t n) {
+    if (!regex_has_group(re, s, n)) {
+        fprintf(stderr, "No group %" PRIuPTR " in match of regex\n", n);
+        exit(1);
+    }
+    regmatch_t *groups = malloc((n + 1) * sizeof(regmatch_t));
+    regex_exec(re, s, n + 1, groups);
+    uintptr_t len = groups[n].rm_eo - groups[n].rm_so;
+    uint8_t *data = malloc(len > 0 ? len : 1);
+    memcpy(data, s.data + groups[n].rm_so, len);
+    free(groups);
+    return (KvasirString){ .len = len, .data = data };
+}
+
+/* Lines and files
+ *
+ * Whether an operation failed is queried afterwards with `io_failed`. Strings returned by
+ * `read_line` and `read_file` are allocated on the heap, and never freed.
+ */
+
+static bool io_failed_ = false;
+
+bool io_failed(void) {
+    return io_failed_;
+}
+
+/* Returns a null terminated copy of `s` */
+static char *to_c_str(KvasirString s) {
+    char *c_str = malloc(s.len + 1);
+    memcpy(c_str, s.data, s.len);
+    c_str[s.len] = '\0';
+    return c_str;
+}
+
+KvasirString read_line(void) {
+    char *line = NULL;
+    size_t cap = 0;
+    ssize_t len = getline(&line, &cap, stdin);
+    io_failed_ = len < 0;
+    if (len < 0) {
+        free(line);
+        return (KvasirString){ .len = 0, .data = NULL };
+    }
+    if (len > 0 && line[len - 1] == '\n') {
+        len--;
+    }
+    return (KvasirString){ .len = len, .data = (const uint8_t *)line };
+}
+
+KvasirString read_file(KvasirString path) {
+    char *c_path = to_c_str(path);
+    FILE *f = fopen(c_path, "rb");
+    free(c_path);
+    KvasirString contents = { .len = 0, .data = NULL };
+    io_failed_ = true;
+    if (f == NULL) {
+        return contents;
+    }
+    if (fseek(f, 0, SEEK_END) == 0) {
+        long len = ftell(f);
+        uint8_t *data = malloc(len > 0 ? len : 1);
+        rewind(f);
+        if (len >= 0 && fread(data, 1, len, f) == (size_t)len) {
+            contents.len = len;
+            contents.data = data;
+            io_failed_ = false;
+        } else {
+            free(data);
+        }
+    }
+    fclose(f);
+    return contents;
+}
+
+void write_file(KvasirString path, KvasirString contents) {
+    char *c_path = to_c_str(path);
+    FILE *f = fopen(c_path, "wb");
+    free(c_path);
+    if (f == NULL) {
+        io_failed_ = true;
+        return;
+    }
+    bool written = fwrite(contents.data, 1, contents.len, f) == contents.len;
+    io_failed_ = (fclose(f) != 0) || !written;
+}
+
+/* Command line arguments and environment variables
+ *
+ * The arguments are handed over by the generated `main` before anything else is run
+ */
+
+static int program_argc = 0;
+static char **program_argv = NULL;
+
+void init_program_args(int argc, char **argv) {
+    program_argc = argc;
+    program_argv = argv;
+}
+
+uint64_t program_arg_count(void) {
+    return (uint64_t)program_argc;
+}
+
+/* Returns the `n`th argument, or the empty string if there are not that many */
+KvasirString program_arg(uint64_t n) {
+    if (n >= (uint64_t)program_argc) {
+        return (KvasirString){ .len = 0, .data = NULL };
+    }
+    const char *arg = program_argv[n];
+    return (KvasirString){ .len = strlen(arg), .data = (const uint8_t *)arg };
+}
+
+bool has_env(KvasirString name) {
+    char *c_name = to_c_str(name);
+    bool has = getenv(c_name) != NULL;
+    free(c_name);
+    return has;
+}
+
+/* Returns the value of the environment variable `name`, or the empty string if not set */
+KvasirString get_env(KvasirString name) {
+    char *c_name = to_c_str(name);
+    const char *val = getenv(c_name);
+    free(c_name);
+    if (val == NULL) {
+        return (KvasirString){ .len = 0, .data = NULL };
+    }
+    return (KvasirString){ .len = strlen(val), .data = (const uint8_t *)val };
+}
+
+/* Logging
+ *
+ * Messages are written to stderr if their level is enabled by the environment variable
+ * `KVASIR_LOG`. It's a comma separated list of directives, each either a level, e.g. `info`,
+ * which sets the default level, or `TARGET=LEVEL`, which sets the level of messages of
+ * `TARGET`. The default level is `warn`.
+ */
+
+static const char *log_level_names[] = { "off", "error", "warn", "info", "debug" };
+
+/* Returns the level named by the `len` characters of `s`, or -1 if none is */
+static int32_t parse_log_level(const char *s, size_t len) {
+    for (int32_t level = 0; level < 5; level++) {
+        if (strlen(log_level_names[level]) == len && strncmp(s, log_level_names[level], len) == 0) {
+            return level;
+        }
+    }
+    return -1;
+}
+
+/* The most detailed level of messages of `target` that is enabled */
+static int32_t max_log_level(KvasirString target) {
+    const char *spec = getenv("KVASIR_LOG");
+    int32_t default_level = 2, target_level = -1;
+    for (const char *p = spec; p != NULL && *p != '\0';) {
+        size_t len = strcspn(p, ",");
+        const char *eq = memchr(p, '=', len);
+        if (eq == NULL) {
+            int32_t level = parse_log_level(p, len);
+            default_level = level >= 0 ? level : default_level;
+        } else if ((size_t)(eq - p) == target.len && memcmp(p, target.data, target.len) == 0) {
+            target_level = parse_log_level(eq + 1, len - target.len - 1);
+        }
+        p += len + (p[len] == ',');
+    }
+    return target_level >= 0 ? target_level : default_level;
+}
+
+/* Log `msg` of `target`, which may be empty, at `level`, from 1 for errors to 4 for debug */
+void log_message(int32_t level, KvasirString target, KvasirString msg) {
+    if (level < 1 || level > max_log_level(target)) {
+        return;
+    }
+    if (target.len > 0) {
+        fprintf(stderr, "[%s %.*s] %.*s\n", log_level_names[level], (int)target.len,
+                target.data, (int)msg.len, msg.data);
+    } else {
+        fprintf(stderr, "[%s] %.*s\n", log_level_names[level], (int)msg.len, msg.data);
+    }
+}
+
+/* Paths and the filesystem
+ *
+ * Strings returned are allocated on the heap, and never freed
+ */
+
+/* `path` appended to `dir`, with a separating `/`. If `path` is absolute, just `path` */
+KvasirString join_path(KvasirString dir, KvasirString path) {
+    if (dir.len == 0 || (path.len > 0 && path.data[0] == '/')) {
+        return path;
+    }
+    bool sep = dir.data[dir.len - 1] != '/';
+    uintptr_t len = dir.len + sep + path.len;
+    uint8_t *data = malloc(len > 0 ? len : 1);
+    memcpy(data, dir.data, dir.len);
+    if (sep) {
+        data[dir.len] = '/';
+    }
+    memcpy(data + dir.len + sep, path.data, path.len);
+    return (KvasirString){ .len = len, .data = data };
+}
+
+bool path_exists(KvasirString path) {
+    char *c_path = to_c_str(path);
+    struct stat st;
+    bool exists = stat(c_path, &st) == 0;
+    free(c_path);
+    return exists;
+}
+
+bool is_dir(KvasirString path) {
+    char *c_path = to_c_str(path);
+    struct stat st;
+    bool dir = stat(c_path, &st) == 0 && S_ISDIR(st.st_mode);
+    free(c_path);
+    return dir;
+}
+
+static int compare_c_strs(const void *a, const void *b) {
+    return strcmp(*(char *const *)a, *(char *const *)b);
+}
+
+/* The names of the entries of the directory `path`, except `.` and `..`, in sorted order.
+ * Each name is followed by a line break
+ */
+KvasirString list_dir(KvasirString path) {
+    char *c_path = to_c_str(path);
+    DIR *dir = opendir(c_path);
+    free(c_path);
+    KvasirString listing = { .len = 0, .data = NULL };
+    io_failed_ = dir == NULL;
+    if (dir == NULL) {
+        return listing;
+    }
+    char **names = NULL;
+    size_t n_names = 0, cap = 0, len = 0;
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL) {
+        if (strcmp(entry->d_name, ".") == 0 || strcmp(entry->d_name, "..") == 0) {
+            continue;
+        }
+        if (n_names == cap) {
+            cap = cap * 2 + 16;
+            names = realloc(names, cap * sizeof(char *));
+        }
+        names[n_names++] = strdup(entry->d_name);
+        len += strlen(entry->d_name) + 1;
+    }
+    closedir(dir);
+    qsort(names, n_names, sizeof(char *), compare_c_strs);
+    uint8_t *data = malloc(len > 0 ? len : 1);
+    size_t i = 0;
+    for (size_t j = 0; j < n_names; j++) {
+        size_t name_len = strlen(names[j]);
+        memcpy(data + i, names[j], name_len);
+        data[i + name_len] = '\n';
+        i += name_len + 1;
+        free(names[j]);
+    }
+    free(names);
+    return (KvasirString){ .len = len, .data = data };
+}
+
+void create_dir(KvasirString path) {
+    char *c_path = to_c_str(path);
+    io_failed_ = mkdir(c_path, 0777) != 0;
+    free(c_path);
+}
+
+/* The absolute path of `path`, with all symbolic links, `.`, and `..` resolved */
+KvasirString canonicalize_path(KvasirString path) {
+    char *c_path = to_c_str(path);
+    char *resolved = realpath(c_path, NULL);
+    free(c_path);
+    io_failed_ = resolved == NULL;
+    if (resolved == NULL) {
+        return (KvasirString){ .len = 0, .data = NULL };
+    }
+    return (KvasirString){ .len = strlen(resolved), .data = (uint8_t *)resolved };
+}
+
+/* Processes
+ *
+ * Commands are run by `/bin/sh -c`, so that they may use pipes, redirections, and assignments
+ * of environment variables, e.g. `CC=clang make`. Whether a command could be run is queried
+ * afterwards with `io_failed`. The output of a command is allocated on the heap, and never
+ * freed.
+ */
+
+typedef struct {
+    int32_t status;
+    KvasirString out;
+    KvasirString err;
+} CommandOutput;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+    size_t cap;
+} Buffer;
+
+/* Read what's available from `fd` into `buf`. Returns false at end of file, or on error */
+static bool read_available(int fd, Buffer *buf) {
+    if (buf->cap - buf->len < 4096) {
+        buf->cap = buf->cap * 2 + 4096;
+        buf->data = realloc(buf->data, buf->cap);
+    }
+    ssize_t n = read(fd, buf->data + buf->len, buf->cap - buf->len);
+    if (n > 0) {
+        buf->len += n;
+    }
+    return n > 0 || (n < 0 && errno == EINTR);
+}
+
+/* Run the shell command `command` in the directory `dir`, or the current directory if `dir`
+ * is empty, and wait for it to finish
+ *
+ * Returns the exit status of the command, or 128 plus the number of the signal that
+ * terminated it, together with the standard output and error of the command.
+ */
+CommandOutput run_command(KvasirString dir, KvasirString command) {
+    CommandOutput output = { .status = -1, .out = { 0, NULL }, .err = { 0, NULL } };
+    io_failed_ = true;
+    char *c_dir = to_c_str(dir);
+    bool dir_ok = dir.len == 0 || access(c_dir, X_OK) == 0;
+    int out_pipe[2], err_pipe[2];
+    if (!dir_ok || pipe(out_pipe) != 0) {
+        free(c_dir);
+        return output;
+    }
+    if (pipe(err_pipe) != 0) {
+        free(c_dir);
+        close(out_pipe[0]);
+        close(out_pipe[1]);
+        return output;
+    }
+    char *c_command = to_c_str(command);
+    pid_t pid = fork();
+    if (pid == 0) {
+        dup2(out_pipe[1], STDOUT_FILENO);
+        dup2(err_pipe[1], STDERR_FILENO);
+        close(out_pipe[0]);
+        close(out_pipe[1]);
+        close(err_pipe[0]);
+        close(err_pipe[1]);
+        if (dir.len > 0 && chdir(c_dir) != 0) {
+            _exit(127);
+        }
+        execl("/bin/sh", "sh", "-c", c_command, (char *)NULL);
+        _exit(127);
+    }
+    free(c_dir);
+    free(c_command);
+    close(out_pipe[1]);
+    close(err_pipe[1]);
+    Buffer out = { NULL, 0, 0 }, err = { NULL, 0, 0 };
+    struct pollfd fds[2] = {
+        { .fd = pid > 0 ? out_pipe[0] : -1, .events = POLLIN },
+        { .fd = pid > 0 ? err_pipe[0] : -1, .events = POLLIN },
+    };
+    /* Read both outputs as they come, so that the command doesn't block on a full pipe */
+    while (fds[0].fd >= 0 || fds[1].fd >= 0) {
+        if (poll(fds, 2, -1) < 0 && errno != EINTR) {
+            break;
+        }
+        for (int i = 0; i < 2; i++) {
+            if (fds[i].fd >= 0 && fds[i].revents != 0
+                && !read_available(fds[i].fd, i == 0 ? &out : &err)) {
+                /* Negative descriptors are ignored by `poll` */
+                fds[i].fd = -1;
+            }
+        }
+    }
+    close(out_pipe[0]);
+    close(err_pipe[0]);
+    int wstatus;
+    if (pid < 0 || waitpid(pid, &wstatus, 0) < 0) {
+        free(out.data);
+        free(err.data);
+        return output;
+    }
+    io_failed_ = false;
+    output.status = WIFEXITED(wstatus) ? WEXITSTATUS(wstatus) : 128 + WTERMSIG(wstatus);
+    output.out = (KvasirString){ .len = out.len, .data = out.data };
+    output.err = (KvasirString){ .len = err.len, .data = err.data };
+    return output;
+}
+
+/* Networking
+ *
+ * Connections are identified by their file descriptors. Whether an operation failed is
+ * queried afterwards with `io_failed`. Strings returned are allocated on the heap, and never
+ * freed.
+ */
+
+/* Connect to `port` of `host` over TCP. Returns the connection, or -1 on failure
+ *
+ * The port is taken as 32 bits, as LLVM doesn't extend narrower integer arguments the way the
+ * C ABI expects. Ports out of the 16 bit range fail.
+ */
+int32_t tcp_connect(KvasirString host, uint32_t port) {
+    if (port > UINT16_MAX) {
+        io_failed_ = true;
+        return -1;
+    }
+    char *c_host = to_c_str(host);
+    char c_port[8];
+    snprintf(c_port, sizeof(c_port), "%" PRIu32, port);
+    struct addrinfo hints = { .ai_family = AF_UNSPEC, .ai_socktype = SOCK_STREAM };
+    struct addrinfo *addrs;
+    int fd = -1;
+    if (getaddrinfo(c_host, c_port, &hints, &addrs) == 0) {
+        for (struct addrinfo *a = addrs; a != NULL && fd < 0; a = a->ai_next) {
+            fd = socket(a->ai_family, a->ai_socktype, a->ai_protocol);
+            if (fd >= 0 && connect(fd, a->ai_addr, a->ai_addrlen) != 0) {
+                close(fd);
+                fd = -1;
+            }
+        }
+        freeaddrinfo(addrs);
+    }
+    free(c_host);
+    io_failed_ = fd < 0;
+    return fd;
+}
+
+/* Read what's available from the connection `conn`. The empty string at end of stream */
+KvasirString tcp_read(int32_t conn) {
+    uint8_t *data = malloc(4096);
+    ssize_t n;
+    do {
+        n = read(conn, data, 4096);
+    } while (n < 0 && errno == EINTR);
+    io_failed_ = n < 0;
+    return (KvasirString){ .len = n > 0 ? n : 0, .data = data };
+}
+
+void tcp_write(int32_t conn, KvasirString data) {
+    uintptr_t written = 0;
+    while (written < data.len) {
+        ssize_t n = write(conn, data.data + written, data.len - written);
+        if (n < 0 && errno != EINTR) {
+            io_failed_ = true;
+            return;
+        }
+        written += n > 0 ? n : 0;
+    }
+    io_failed_ = false;
+}
+
+void tcp_close(int32_t conn) {
+    io_failed_ = close(conn) != 0;
+}
+
+static int32_t http_status_ = -1;
+
+/* The status code of the response of the last successful `http_get`
+ *
+ * Queried separately, as a struct of the status and the body would be returned differently
+ * by the generated code and by C
+ */
+int32_t http_status(void) {
+    return http_status_;
+}
+
+/* Perform a GET request of `url` over HTTP/1.0, so that the body is neither chunked nor
+ * kept alive. Only plain `http://` URLs are supported, TLS is not.
+ *
+ * Returns the body of the response
+ */
+KvasirString http_get(KvasirString url) {
+    KvasirString response = { .len = 0, .data = NULL };
+    io_failed_ = true;
+    const char *scheme = "http://";
+    size_t scheme_len = strlen(scheme);
+    if (url.len < scheme_len || memcmp(url.data, scheme, scheme_len) != 0) {
+        return response;
+    }
+    char *c_url = to_c_str(url);
+    char *authority = c_url + scheme_len;
+    char *path = strchr(authority, '/');
+    size_t authority_len = path != NULL ? (size_t)(path - authority) : strlen(authority);
+    char *host = strndup(authority, authority_len);
+    char *colon = strchr(host, ':');
+    long port = 80;
+    if (colon != NULL) {
+        *colon = '\0';
+        char *end;
+        port = strtol(colon + 1, &end, 10);
+        if (*end != '\0' || port <= 0 || port > UINT16_MAX) {
+            port = -1;
+        }
+    }
+    int32_t conn = port > 0 ? tcp_connect((KvasirString){ strlen(host), (uint8_t *)host }, port)
+                            : -1;
+    if (conn < 0) {
+        free(host);
+        free(c_url);
+        io_failed_ = true;
+        return response;
+    }
+    size_t req_len = strlen(path != NULL ? path : "/") + strlen(host) + 64;
+    char *req = malloc(req_len);
+    snprintf(req, req_len, "GET %s HTTP/1.0\r\nHost: %s\r\nConnection: close\r\n\r\n",
+             path != NULL ? path : "/", host);
+    tcp_write(conn, (KvasirString){ strlen(req), (uint8_t *)req });
+    bool write_failed = io_failed_;
+    free(req);
+    free(host);
+    free(c_url);
+    Buffer buf = { NULL, 0, 0 };
+    while (!write_failed && read_available(conn, &buf)) {
+    }
+    close(conn);
+    /* Null terminated, for `sscanf` */
+    buf.data = realloc(buf.data, buf.len + 1);
+    buf.data[buf.len] = '\0';
+    /* The status line is `HTTP/1.x CODE REASON`, and the body follows the first empty line */
+    int status;
+    const uint8_t *body = NULL;
+    for (size_t i = 0; i + 4 <= buf.len && body == NULL; i++) {
+        if (memcmp(buf.data + i, "\r\n\r\n", 4) == 0) {
+            body = buf.data + i + 4;
+        }
+    }
+    if (write_failed || body == NULL || sscanf((char *)buf.data, "HTTP/1.%*d %d", &status) != 1) {
+        free(buf.data);
+        io_failed_ = true;
+        return response;
+    }
+    io_failed_ = false;
+    http_status_ = status;
+    return (KvasirString){ .len = buf.data + buf.len - body, .data = body };
+}
+
+/* Time */
+
+/* Milliseconds since the Unix epoch */
+uint64_t now_millis(void) {
+    struct timespec ts;
+    clock_gettime(CLOCK_REALTIME, &ts);
+    return (uint64_t)ts.tv_sec * 1000 + (uint64_t)ts.tv_nsec / 1000000;
+}
+
+/* Nanoseconds since some unspecified point in time. Only meaningful as a difference */
+uint64_t monotonic_nanos(void) {
+    struct timespec ts;
+    clock_gettime(CLOCK_MONOTONIC, &ts);
+    return (uint64_t)ts.tv_sec * 1000000000 + (uint64_t)ts.tv_nsec;
+}
+
+/* Dates and times
+ *
+ * Timestamps are milliseconds since the Unix epoch, in UTC. Dates are of the proleptic
+ * Gregorian calendar, converted to and from days after
+ * http://howardhinnant.github.io/date_algorithms.html
+ */
+
+/* The number of days since the Unix epoch of the date `year`-`month`-`day` */
+int64_t days_from_civil(int64_t year, int64_t month, int64_t day) {
+    year -= month <= 2;
+    int64_t era = (year >= 0 ? year : year - 399) / 400;
+    int64_t yoe = year - era * 400;
+    int64_t doy = (153 * (month > 2 ? month - 3 : month + 9) + 2) / 5 + day - 1;
+    int64_t doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146097 + doe - 719468;
+}
+
+static void civil_from_days(int64_t days, int64_t *year, int64_t *month, int64_t *day) {
+    days += 719468;
+    int64_t era = (days >= 0 ? days : days - 146096) / 146097;
+    int64_t doe = days - era * 146097;
+    int64_t yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    int64_t doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    int64_t mp = (5 * doy + 2) / 153;
+    *day = doy - (153 * mp + 2) / 5 + 1;
+    *month = mp < 10 ? mp + 3 : mp - 9;
+    *year = yoe + era * 400 + (*month <= 2);
+}
+
+/* Format the timestamp `millis` in ISO 8601, e.g. `2018-03-01T12:30:00.000Z` */
+KvasirString format_timestamp(int64_t millis) {
+    int64_t days = (millis >= 0 ? millis : millis - 86399999) / 86400000;
+    int64_t ms = millis - days * 86400000;
+    int64_t year, month, day;
+    civil_from_days(days, &year, &month, &day);
+    char *s = malloc(64);
+    int len = snprintf(s, 64,
+                       "%04" PRId64 "-%02" PRId64 "-%02" PRId64
+                       "T%02" PRId64 ":%02" PRId64 ":%02" PRId64 ".%03" PRId64 "Z",
+                       year, month, day, ms / 3600000, ms / 60000 % 60, ms / 1000 % 60,
+                       ms % 1000);
+    return (KvasirString){ .len = len, .data = (uint8_t *)s };
+}
+
+/* Pure pseudo random number generation */
+
+typedef struct {
+    uint64_t value;
+    uint64_t state;
+} RandomNext;
+
+/* One step of the SplitMix64 generator. Returns the random number and the next state */
+RandomNext splitmix64(uint64_t state) {
+    state += 0x9e3779b97f4a7c15;
+    uint64_t z = state;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111eb;
+    return (RandomNext){ .value = z ^ (z >> 31), .state = state };
+}
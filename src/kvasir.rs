@@ -0,0 +1,19 @@
+//! The Kvasir compiler as a library
+//!
+//! The driver in `main.rs` is a thin layer over this crate. Programs that embed the compiler,
+//! and compiler plugins, see `lib::plugin`, depend on it to reach the AST and the passes.
+
+#![feature(non_ascii_idents, box_syntax, box_patterns, conservative_impl_trait)]
+
+#[macro_use]
+extern crate lazy_static;
+extern crate bitflags;
+extern crate term;
+extern crate llvm_sys;
+extern crate itertools;
+extern crate libc;
+extern crate cbox;
+#[macro_use]
+extern crate maplit;
+
+pub mod lib;
@@ -1,5 +1,6 @@
 use lib::front::{error_exit, exit, SrcPos};
 use lib::front::ast::{self, Expr};
+use lib::interp::{self, ConstEnv};
 use llvm_sys;
 use llvm_sys::prelude::*;
 use llvm_sys::target::LLVMTargetDataRef;
@@ -9,6 +10,8 @@ use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::str::FromStr;
 use std::iter::once;
 use super::llvm::*;
+use super::mangle::{global_symbol, mangle_ident};
+use super::target::{NumRepr, TargetConfig};
 use self::CodegenErr::*;
 
 type Instantiations<'src> = BTreeSet<(Vec<ast::Type<'src>>, ast::Type<'src>)>;
@@ -39,6 +42,24 @@ fn type_rc_generic<'ctx>(ctx: &'ctx Context) -> &'ctx Type {
     type_rc(ctx, Type::get::<u8>(ctx))
 }
 
+/// Returns the symbol name of the function of the instance `inst` of `binding`
+///
+/// Global definitions are given stable names, as described in `mangle`, while local
+/// definitions are only named for readability, and left to LLVM to make unique
+fn symbol_name(binding: &ast::Binding, inst: &[ast::Type], global: bool) -> String {
+    if global {
+        let module = binding
+            .pos
+            .filename()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        global_symbol(&module, binding.ident.s, inst)
+    } else {
+        format!("__lambda_{}", mangle_ident(binding.ident.s))
+    }
+}
+
 /// Returns the unit set of the single element `x`
 fn set_of<T: cmp::Ord>(x: T) -> BTreeSet<T> {
     once(x).collect()
@@ -96,6 +117,7 @@ fn free_vars_in_expr<'src>(e: &ast::Expr<'src>) -> FreeVarInsts<'src> {
         Car(box ref c) => free_vars_in_expr(&c.expr),
         Cdr(box ref c) => free_vars_in_expr(&c.expr),
         Cast(ref c) => free_vars_in_expr(&c.expr),
+        SizeOf(_) | AlignOf(_) => FreeVarInsts::new(),
     }
 }
 
@@ -219,9 +241,16 @@ pub struct CodeGenerator<'ctx> {
     current_func: RefCell<Option<&'ctx Function>>,
     current_block: RefCell<Option<&'ctx BasicBlock>>,
     named_types: NamedTypes<'ctx>,
+    /// Widths of numeric types and such
+    target: &'ctx TargetConfig,
 }
 impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
-    pub fn new(ctx: &'ctx Context, builder: &'ctx Builder, module: &'ctx Module) -> Self {
+    pub fn new(
+        ctx: &'ctx Context,
+        builder: &'ctx Builder,
+        module: &'ctx Module,
+        target: &'ctx TargetConfig,
+    ) -> Self {
         let named_types = NamedTypes {
             real_world: StructType::new_named(ctx, "RealWorld", &[], false),
             nil: StructType::new_named(ctx, "Nil", &[], false),
@@ -233,6 +262,7 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             current_func: RefCell::new(None),
             current_block: RefCell::new(None),
             named_types: named_types,
+            target: target,
         }
     }
 
@@ -255,16 +285,19 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
     }
 
     fn ptr_size_bits(&self) -> usize {
-        self.target_data().get_pointer_size()
+        self.target.ptr_width
     }
 
     fn gen_int_ptr_type(&self) -> &'ctx Type {
-        match self.ptr_size_bits() * 8 {
-            8 => Type::get::<i8>(self.ctx),
-            16 => Type::get::<i16>(self.ctx),
-            32 => Type::get::<i32>(self.ctx),
-            64 => Type::get::<i64>(self.ctx),
-            e => panic!("ICE: Platform has unsupported pointer size of {} bit", e),
+        IntegerType::new(self.ctx, self.ptr_size_bits())
+    }
+
+    fn gen_num_repr_type(&self, repr: NumRepr) -> &'ctx Type {
+        match repr {
+            NumRepr::Int(w) | NumRepr::UInt(w) => IntegerType::new(self.ctx, w),
+            NumRepr::Float(32) => Type::get::<f32>(self.ctx),
+            NumRepr::Float(64) => Type::get::<f64>(self.ctx),
+            NumRepr::Float(w) => panic!("ICE: Unsupported float width of {} bit", w),
         }
     }
 
@@ -275,26 +308,20 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         )
     }
 
+    // TODO: Data types with a C-compatible layout, for passing them over FFI, are not
+    //       supported yet. `repr` attributes and explicit discriminants are parsed, but
+    //       rejected by `assert_no_data_type_layouts`. When data types are generated, lay out
+    //       the tag according to `AdtDef::repr`, and use the discriminants as tag values
     fn gen_type(&self, typ: &'ast ast::Type<'src>) -> &'ctx Type {
         match *typ {
             ast::Type::Var(ref tv) if tv.constrs.len() == 1 && tv.constrs.contains("Num") => {
-                Type::get::<isize>(self.ctx)
+                self.gen_int_ptr_type()
             }
             ast::Type::Var { .. } => panic!("Type was Unknown at compile time"),
-            ast::Type::Const("Int8", _) => Type::get::<i8>(self.ctx),
-            ast::Type::Const("Int16", _) => Type::get::<i16>(self.ctx),
-            ast::Type::Const("Int32", _) => Type::get::<i32>(self.ctx),
-            ast::Type::Const("Int64", _) => Type::get::<i64>(self.ctx),
-            ast::Type::Const("IntPtr", _) | ast::Type::Const("UIntPtr", _) => {
-                self.gen_int_ptr_type()
+            ast::Type::Const(s, _) if self.target.num_repr(s).is_some() => {
+                self.gen_num_repr_type(self.target.num_repr(s).unwrap())
             }
-            ast::Type::Const("UInt8", _) => Type::get::<u8>(self.ctx),
-            ast::Type::Const("UInt16", _) => Type::get::<u16>(self.ctx),
-            ast::Type::Const("UInt32", _) => Type::get::<u32>(self.ctx),
-            ast::Type::Const("UInt64", _) => Type::get::<u64>(self.ctx),
             ast::Type::Const("Bool", _) => Type::get::<bool>(self.ctx),
-            ast::Type::Const("Float32", _) => Type::get::<f32>(self.ctx),
-            ast::Type::Const("Float64", _) => Type::get::<f64>(self.ctx),
             ast::Type::Const("Nil", _) => self.named_types.nil,
             ast::Type::Const("RealWorld", _) => self.named_types.real_world,
             ast::Type::App(box ast::TypeFunc::Const(s), ref ts) => match s {
@@ -513,29 +540,79 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             .unwrap_or_else(|_| pos.error_exit(CodegenErr::num_parse_err(typ)))
     }
 
+    /// Generate an integer literal of bit width `width` of the target
+    fn gen_int_lit(&self, num: &ast::NumLit, width: usize, signed: bool) -> &'ctx Value {
+        let in_range = if signed {
+            num.lit.parse::<i64>().ok().and_then(|n| {
+                if width >= 64 || (n >= -(1 << (width - 1)) && n < 1 << (width - 1)) {
+                    Some(n as u64)
+                } else {
+                    None
+                }
+            })
+        } else {
+            num.lit
+                .parse::<u64>()
+                .ok()
+                .and_then(|n| if width >= 64 || n < 1 << width { Some(n) } else { None })
+        };
+        let bits = in_range
+            .unwrap_or_else(|| num.pos.error_exit(CodegenErr::num_parse_err(&num.typ)));
+        self.gen_int_const(bits, width, signed)
+    }
+
+    /// Generate an integer constant of bit width `width` from the bits of `n`
+    fn gen_int_const(&self, n: u64, width: usize, signed: bool) -> &'ctx Value {
+        unsafe {
+            llvm_sys::core::LLVMConstInt(
+                IntegerType::new(self.ctx, width).into(),
+                n,
+                signed as i32,
+            )
+        }.into()
+    }
+
     fn gen_num(&self, num: &ast::NumLit) -> &'ctx Value {
-        let parser = match num.typ {
-            // If it's an arbitrary number, default to isize (Int)
+        let repr = match num.typ {
+            // If it's an arbitrary number, default to a pointer sized integer
             ast::Type::Var(ref tv) if tv.constrs.len() == 1 && tv.constrs.contains("Num") => {
-                CodeGenerator::parse_gen_lit::<isize>
+                NumRepr::Int(self.ptr_size_bits())
+            }
+            ast::Type::Const("Bool", _) => {
+                return self.parse_gen_lit::<bool>(&num.lit, &num.typ, &num.pos)
+            }
+            ast::Type::Const(s, _) if self.target.num_repr(s).is_some() => {
+                self.target.num_repr(s).unwrap()
             }
-            ast::Type::Const("Int8", _) => CodeGenerator::parse_gen_lit::<i8>,
-            ast::Type::Const("Int16", _) => CodeGenerator::parse_gen_lit::<i16>,
-            ast::Type::Const("Int32", _) => CodeGenerator::parse_gen_lit::<i32>,
-            ast::Type::Const("Int64", _) => CodeGenerator::parse_gen_lit::<i64>,
-            ast::Type::Const("IntPtr", _) => CodeGenerator::parse_gen_lit::<isize>,
-            ast::Type::Const("UInt8", _) => CodeGenerator::parse_gen_lit::<u8>,
-            ast::Type::Const("UInt16", _) => CodeGenerator::parse_gen_lit::<u16>,
-            ast::Type::Const("UInt32", _) => CodeGenerator::parse_gen_lit::<u32>,
-            ast::Type::Const("UInt64", _) => CodeGenerator::parse_gen_lit::<u64>,
-            ast::Type::Const("UIntPtr", _) => CodeGenerator::parse_gen_lit::<usize>,
-            ast::Type::Const("Bool", _) => CodeGenerator::parse_gen_lit::<bool>,
-            ast::Type::Const("Float32", _) => CodeGenerator::parse_gen_lit::<f32>,
-            ast::Type::Const("Float64", _) => CodeGenerator::parse_gen_lit::<f64>,
             _ => num.pos
                 .error_exit(ICE("type of numeric literal is not numeric".into())),
         };
-        parser(self, &num.lit, &num.typ, &num.pos)
+        match repr {
+            NumRepr::Int(w) => self.gen_int_lit(num, w, true),
+            NumRepr::UInt(w) => self.gen_int_lit(num, w, false),
+            NumRepr::Float(32) => self.parse_gen_lit::<f32>(&num.lit, &num.typ, &num.pos),
+            NumRepr::Float(_) => self.parse_gen_lit::<f64>(&num.lit, &num.typ, &num.pos),
+        }
+    }
+
+    /// Generate the value of a global constant, as evaluated at compile time, of type `typ`
+    fn gen_const(&self, v: &interp::Value, typ: &ast::Type, pos: &SrcPos) -> &'ctx Value {
+        let repr = typ.get_const().and_then(|s| self.target.num_repr(s));
+        match (v, repr) {
+            (&interp::Value::Bool(b), _) => b.compile(self.ctx),
+            (&interp::Value::Int(n), Some(NumRepr::Int(w))) => {
+                self.gen_int_const(n as u64, w, true)
+            }
+            (&interp::Value::Int(n), Some(NumRepr::UInt(w))) => {
+                self.gen_int_const(n as u64, w, false)
+            }
+            (&interp::Value::Float(x), Some(NumRepr::Float(32))) => (x as f32).compile(self.ctx),
+            (&interp::Value::Float(x), Some(NumRepr::Float(_))) => x.compile(self.ctx),
+            _ => pos.error_exit(ICE(format!(
+                "constant `{}` does not match its type `{}`",
+                v, typ
+            ))),
+        }
     }
 
     fn gen_str(&self, lit: &'ast ast::StrLit<'src>) -> &'ctx Value {
@@ -557,17 +634,15 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
 
         let inst = var.typ.get_inst_args().unwrap_or(&[]);
         let type_canon = var.typ.canonicalize();
+        let pos = &var.ident.pos;
         match env.get(var.ident.s, inst) {
             // NOTE: Ugly hack to fix generic codegen for some binops
             _ if arithm_binops.contains(var.ident.s) => {
                 let maybe_op_typ = type_canon.get_cons_binop().map(|t| t.num_to_int64());
-                let op_typ = maybe_op_typ
-                    .unwrap_or_else(|| panic!("ICE: binop has bad type {}", type_canon));
-                assert!(
-                    op_typ.is_int() || op_typ.is_uint() || op_typ.is_float(),
-                    "ICE: binop has bad type {}",
-                    type_canon
-                );
+                let op_typ = match maybe_op_typ {
+                    Some(ref t) if t.is_int() || t.is_uint() || t.is_float() => t.clone(),
+                    _ => pos.error_exit(ICE(format!("binop has bad type {}", type_canon))),
+                };
                 let typ = ast::Type::new_binop(op_typ.clone());
                 let f = format!("{}-{}", var.ident.s, op_typ.get_const().unwrap());
                 let mut var2 = var.clone();
@@ -579,14 +654,13 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
                 let maybe_op_typ = type_canon
                     .get_cons_relational_binop()
                     .map(|t| t.num_to_int64());
-                let op_typ = maybe_op_typ.unwrap_or_else(|| {
-                    panic!("ICE: binary relational op has bad type {}", type_canon)
-                });
-                assert!(
-                    op_typ.is_int() || op_typ.is_uint() || op_typ.is_float(),
-                    "ICE: relational binop has bad type {}",
-                    type_canon
-                );
+                let op_typ = match maybe_op_typ {
+                    Some(ref t) if t.is_int() || t.is_uint() || t.is_float() => t.clone(),
+                    _ => pos.error_exit(ICE(format!(
+                        "relational binop has bad type {}",
+                        type_canon
+                    ))),
+                };
                 let typ = ast::Type::new_relational_binop(op_typ.clone());
                 let f = format!("{}-{}", var.ident.s, op_typ.get_const().unwrap());
                 let mut var2 = var.clone();
@@ -595,11 +669,9 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
                 self.gen_variable(env, &var2)
             }
             _ if logic_binops.contains(var.ident.s) => {
-                assert!(
-                    type_canon.is_cons_logic_binop(),
-                    "ICE: relational binop has bad type {}",
-                    type_canon
-                );
+                if !type_canon.is_cons_logic_binop() {
+                    pos.error_exit(ICE(format!("logic binop has bad type {}", type_canon)))
+                }
                 let typ = ast::Type::new_logic_binop();
                 let f = format!("{}", var.ident.s);
                 let mut var2 = var.clone();
@@ -614,13 +686,13 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             ),
             Some(Var::Val(v)) => v,
             // Undefined variables are caught during type check/inference
-            None => panic!(
-                "ICE: Undefined variable at codegen: {} inst `{:?}`\ninsts of {}: {:#?}",
+            None => pos.error_exit(ICE(format!(
+                "Undefined variable at codegen: {} inst `{:?}`\ninsts of {}: {:#?}",
                 var.ident.s,
                 inst,
                 var.ident.s,
                 env.get_var_insts(var.ident.s)
-            ),
+            ))),
         }
     }
 
@@ -708,11 +780,7 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         lam: &'ast ast::Lambda<'src>,
         name: Option<&str>,
     ) -> &'ctx Function {
-        let lambda_name = match name {
-            Some(name) => format!("__lambda_{}", name),
-            None => format!("lambda"),
-        };
-        let func = self.gen_func_decl(lambda_name, &lam.typ);
+        let func = self.gen_func_decl(name.unwrap_or("lambda").to_string(), &lam.typ);
         let parent_func = mem::replace(&mut *self.current_func.borrow_mut(), Some(func));
         let entry = func.append("entry");
         let parent_block = mem::replace(&mut *self.current_block.borrow_mut(), Some(entry));
@@ -932,7 +1000,12 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
     ///
     /// Assumes that the variable bindings in `bs` are in reverse topologically order
     /// for the relation: "depends on".
-    fn gen_bindings(&self, env: &mut Env<'src, 'ctx>, bindings: &[&'ast ast::Binding<'src>]) {
+    fn gen_bindings(
+        &self,
+        env: &mut Env<'src, 'ctx>,
+        bindings: &[&'ast ast::Binding<'src>],
+        global: bool,
+    ) {
         // To solve the problem of recursive references in closure captures, e.g. two mutually
         // recursive functions that need to capture each other: First create closures where
         // captures are left as allocated, but undefined space. Second, fill in captures
@@ -940,23 +1013,26 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
 
         let empty_vec = vec![];
         // Flatten with regards to mono insts
-        let mut bindings_insts: Vec<(_, &Vec<ast::Type>, _)> = Vec::new();
+        let mut bindings_insts: Vec<(_, &Vec<ast::Type>, _, String)> = Vec::new();
         for binding in bindings {
             env.push_var(binding.ident.s, BTreeMap::new());
-            if binding.typ.is_monomorphic() {
-                bindings_insts.push((binding.ident.s, &empty_vec, &binding.val));
+            let insts = if binding.typ.is_monomorphic() {
+                vec![(&empty_vec, &binding.val)]
             } else {
-                for (inst_ts, val_inst) in &binding.mono_insts {
-                    bindings_insts.push((binding.ident.s, &inst_ts, val_inst));
-                }
+                binding.mono_insts.iter().collect()
+            };
+            for (inst_ts, val_inst) in insts {
+                let symbol = symbol_name(binding, inst_ts, global);
+                bindings_insts.push((binding.ident.s, inst_ts, val_inst, symbol));
             }
         }
 
         let mut lambdas_free_vars = VecDeque::new();
-        for &(name, inst, val) in &bindings_insts {
+        for &(name, inst, val, ref symbol) in &bindings_insts {
             match *val {
                 ast::Expr::Lambda(ref lam) => {
-                    let (closure, free_vars) = self.gen_lambda_no_capture(env, lam, Some(name));
+                    let (closure, free_vars) =
+                        self.gen_lambda_no_capture(env, lam, Some(symbol.as_str()));
                     env.add_inst(name, inst.clone(), closure);
                     lambdas_free_vars.push_back(free_vars);
                 }
@@ -964,7 +1040,7 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             }
         }
 
-        for &(name, inst, val) in &bindings_insts {
+        for &(name, inst, val, ref symbol) in &bindings_insts {
             match val {
                 &ast::Expr::Lambda(ref lam) => {
                     let closure = env.get_var(name, &inst).expect("ICE: variable dissapeared");
@@ -972,7 +1048,7 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
                     self.closure_capture_env(env, closure, lam, free_vars);
                 }
                 expr => {
-                    let var = self.gen_expr(env, expr, Some(name));
+                    let var = self.gen_expr(env, expr, Some(symbol.as_str()));
                     var.set_name(name);
                     env.add_inst(name, inst.clone(), var);
                 }
@@ -983,7 +1059,7 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
     /// Generate LLVM IR for a `let` special form
     fn gen_let(&self, env: &mut Env<'src, 'ctx>, l: &'ast ast::Let<'src>) -> &'ctx Value {
         let bindings = l.bindings.bindings().rev().collect::<Vec<_>>();
-        self.gen_bindings(env, &bindings);
+        self.gen_bindings(env, &bindings, false);
         let v = self.gen_expr(env, &l.body, None);
         for b in bindings {
             env.pop(b.ident.s);
@@ -1018,6 +1094,10 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         let to_type = &c.typ;
         let to_type_ll = self.gen_type(to_type);
         let from_expr = self.gen_expr(env, &c.expr, None);
+        // E.g. the constructor of a newtype, after erasure to the underlying type
+        if from_type == to_type {
+            return from_expr;
+        }
         let res = if let Some(from_size) = from_type.int_size(ptr_size) {
             // Casting from signed integer
             if let Some(to_size) = to_type.int_size(ptr_size).or(to_type.uint_size(ptr_size)) {
@@ -1077,6 +1157,20 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         })
     }
 
+    fn gen_uint_ptr_const(&self, n: u64) -> &'ctx Value {
+        unsafe { llvm_sys::core::LLVMConstInt(self.gen_int_ptr_type().into(), n, 0) }.into()
+    }
+
+    fn gen_size_of(&self, s: &'ast ast::SizeOf<'src>) -> &'ctx Value {
+        let typ = self.gen_type(&s.queried);
+        self.gen_uint_ptr_const(self.target_data().abi_size_of(typ))
+    }
+
+    fn gen_align_of(&self, a: &'ast ast::AlignOf<'src>) -> &'ctx Value {
+        let typ = self.gen_type(&a.queried);
+        self.gen_uint_ptr_const(self.target_data().alignment_of(typ) as u64)
+    }
+
     /// Generate llvm code for an expression and return its llvm Value.
     fn gen_expr(
         &self,
@@ -1101,6 +1195,8 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             Expr::Car(ref c) => self.gen_car(env, c),
             Expr::Cdr(ref c) => self.gen_cdr(env, c),
             Expr::Cast(ref c) => self.gen_cast(env, c),
+            Expr::SizeOf(ref s) => self.gen_size_of(s),
+            Expr::AlignOf(ref a) => self.gen_align_of(a),
         }
     }
 
@@ -1155,6 +1251,8 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
             }
         }
 
+        self.assert_no_data_type_layouts(&ast.datas);
+
         let mut env = Env::new();
 
         // Generate core functions
@@ -1163,17 +1261,37 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         // Generate extern declarations
         self.gen_extern_decls(&mut env, &ast.externs);
 
-        // Create wrapping, entry-point `main` function
-        let main_type = FunctionType::new(Type::get::<i32>(self.ctx), &[self.named_types.nil]);
+        // Create wrapping, entry-point `main` function, which hands the command line arguments
+        // over to the core library
+        let argc_type = Type::get::<i32>(self.ctx);
+        let argv_type = PointerType::new(type_generic_ptr(self.ctx));
+        let main_type = FunctionType::new(Type::get::<i32>(self.ctx), &[argc_type, argv_type]);
         let main_wrapper = self.module.add_function("main", &main_type);
+        let init_args_type = FunctionType::new(Type::get::<()>(self.ctx), &[argc_type, argv_type]);
+        let init_args = self.module.add_function("init_program_args", &init_args_type);
         let entry = main_wrapper.append("entry");
         self.builder.position_at_end(entry);
+        self.builder
+            .build_call(init_args, &[&*main_wrapper[0], &*main_wrapper[1]]);
         *self.current_func.borrow_mut() = Some(main_wrapper);
         *self.current_block.borrow_mut() = Some(entry);
 
-        // Generate global definitions
-        let global_bindings = ast.globals.bindings().rev().collect::<Vec<_>>();
-        self.gen_bindings(&mut env, &global_bindings);
+        // Generate global constants, as evaluated at compile time
+        let consts = ConstEnv::new(ast);
+        let mut global_bindings = Vec::new();
+        for binding in ast.globals.bindings().rev() {
+            match consts.get(binding.ident.s) {
+                Some(v) => {
+                    let c = self.gen_const(v, &binding.typ, &binding.pos);
+                    env.push_var(binding.ident.s, BTreeMap::new());
+                    env.add_inst(binding.ident.s, vec![], c);
+                }
+                None => global_bindings.push(binding),
+            }
+        }
+
+        // Generate the rest of the global definitions
+        self.gen_bindings(&mut env, &global_bindings, true);
 
         // Call user defined `main`
         let user_main = env.get_var("main", &[])
@@ -1185,4 +1303,25 @@ impl<'src: 'ast, 'ast, 'ctx> CodeGenerator<'ctx> {
         );
         self.builder.build_ret(0i32.compile(self.ctx));
     }
+
+    /// Exit with an error if a data type of `datas` asks for a specific layout, with a `repr`
+    /// attribute or explicit discriminants, as layouts of data types are not yet generated
+    fn assert_no_data_type_layouts(&self, datas: &BTreeMap<&str, ast::AdtDef>) {
+        for def in datas.values() {
+            if def.repr.is_some() {
+                def.pos.error_exit(format!(
+                    "The `repr` attribute of data type `{}` is not yet supported by codegen",
+                    def.name.s
+                ))
+            }
+            for variant in &def.variants {
+                if variant.discriminant.is_some() {
+                    variant.pos.error_exit(format!(
+                        "The explicit discriminant of variant `{}` is not yet supported by codegen",
+                        variant.name.s
+                    ))
+                }
+            }
+        }
+    }
 }
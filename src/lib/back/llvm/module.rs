@@ -205,6 +205,12 @@ impl Module {
         unsafe { core::LLVMSetTarget(self.into(), c_target.as_ptr()) }
     }
 
+    /// Set the data layout of this module to the layout string given.
+    pub fn set_data_layout(&self, layout: &str) {
+        let c_layout = CString::new(layout).unwrap();
+        unsafe { core::LLVMSetDataLayout(self.into(), c_layout.as_ptr()) }
+    }
+
     /// Verify that the module is safe to run, returning a string detailing the error
     /// when an error occurs.
     pub fn verify(&self) -> Result<(), CBox<str>> {
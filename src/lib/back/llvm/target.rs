@@ -35,6 +35,10 @@ impl TargetData {
     pub fn size_of(&self, ty: &Type) -> u64 {
         unsafe { target::LLVMStoreSizeOfType(self.into(), ty.into()) }
     }
+    /// Returns the ABI size of the type given in bytes, including tail padding.
+    pub fn abi_size_of(&self, ty: &Type) -> u64 {
+        unsafe { target::LLVMABISizeOfType(self.into(), ty.into()) }
+    }
     /// Returns the alignment of the type given in bytes.
     pub fn alignment_of(&self, ty: &Type) -> usize {
         unsafe { target::LLVMABIAlignmentOfType(self.into(), ty.into()) as usize }
@@ -0,0 +1,138 @@
+//! Mangling of the symbol names of global definitions
+//!
+//! Each monomorphic instance of a global definition is given a symbol name that only depends
+//! on the module it's defined in, its name, and the types it's instantiated with, so that
+//! the names are stable between compilations, and unique within a program.
+//!
+//! # Scheme
+//!
+//! ```text
+//! symbol     ::= "_KV" "N" identifier identifier "E" instance?
+//! instance   ::= "I" type+ "E"
+//! identifier ::= <decimal length> <escaped name>
+//! type       ::= identifier                      ; a type constant, e.g. `Int32`
+//!              | "F" type type                   ; a function, `(-> A B)`
+//!              | "C" type type                   ; a pair, `(Cons A B)`
+//!              | "P" type                        ; a pointer, `(Ptr A)`
+//!              | "A" identifier type* "E"        ; any other type application
+//! ```
+//!
+//! The first identifier of a symbol is the module, i.e. the file stem of the source file,
+//! and the second is the name of the definition. Names are escaped with `mangle_ident`.
+//! E.g. `map` of module `list`, instantiated with `Int32` and `Bool`, is
+//! `_KVN4list3mapEI5Int324BoolE`.
+
+use lib::front::ast::{Type, TypeFunc};
+
+/// Returns `name` with every character that is not valid in a C identifier escaped
+///
+/// User defined operators, like `<*>`, are ordinary identifiers in Kvasir, but would not
+/// give valid symbol names to link against from C. An underscore is escaped as `__`, and any
+/// other non-alphanumeric character as `_` followed by its hex code point and another `_`,
+/// e.g. `<*>` becomes `_3c__2a__3e_`. This keeps the mapping one-to-one.
+pub fn mangle_ident(name: &str) -> String {
+    let mut mangled = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '_' => mangled.push_str("__"),
+            _ if c.is_ascii_alphanumeric() => mangled.push(c),
+            _ => mangled.push_str(&format!("_{:x}_", c as u32)),
+        }
+    }
+    mangled
+}
+
+/// Append the length prefixed, escaped identifier `name` to `out`
+fn push_identifier(name: &str, out: &mut String) {
+    let escaped = mangle_ident(name);
+    out.push_str(&escaped.len().to_string());
+    out.push_str(&escaped)
+}
+
+/// Append the mangling of the monomorphic type `t` to `out`
+fn push_type(t: &Type, out: &mut String) {
+    match *t {
+        Type::Const(name, _) => push_identifier(name, out),
+        Type::App(box TypeFunc::Const(f), ref args) => {
+            match (f, args.len()) {
+                ("->", 2) => out.push('F'),
+                ("Cons", 2) => out.push('C'),
+                ("Ptr", 1) => out.push('P'),
+                _ => {
+                    out.push('A');
+                    push_identifier(f, out);
+                    for arg in args {
+                        push_type(arg, out)
+                    }
+                    return out.push('E');
+                }
+            }
+            for arg in args {
+                push_type(arg, out)
+            }
+        }
+        _ => panic!("ICE: Mangling non-monomorphic type `{}`", t),
+    }
+}
+
+/// Returns the symbol name of the instance of the global definition `name` in `module`,
+/// that is instantiated with the types `inst`
+pub fn global_symbol(module: &str, name: &str, inst: &[Type]) -> String {
+    let mut symbol = "_KVN".to_string();
+    push_identifier(module, &mut symbol);
+    push_identifier(name, &mut symbol);
+    symbol.push('E');
+    if !inst.is_empty() {
+        symbol.push('I');
+        for t in inst {
+            push_type(t, &mut symbol)
+        }
+        symbol.push('E');
+    }
+    symbol
+}
+
+#[cfg(test)]
+mod test {
+    use lib::front::ast::Type;
+    use std::collections::BTreeSet;
+    use super::{global_symbol, mangle_ident};
+
+    #[test]
+    fn test_mangle_ident() {
+        assert_eq!(mangle_ident("map"), "map");
+        assert_eq!(mangle_ident("_"), "__");
+        assert_eq!(mangle_ident("<*>"), "_3c__2a__3e_");
+        assert_eq!(mangle_ident("valid-regex?"), "valid_2d_regex_3f_");
+        assert_eq!(mangle_ident("λ"), "_3bb_");
+    }
+
+    #[test]
+    fn test_mangle_ident_is_one_to_one() {
+        // Names that would collide if underscores or escapes were not themselves escaped
+        let names = [
+            "_", "__", "_5f_", "a_b", "a-b", "a_2d_b", "a__2d__b", "<*>", "_3c__2a__3e_", "λ",
+        ];
+        let mangled = names
+            .iter()
+            .map(|name| mangle_ident(name))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(mangled.len(), names.len());
+    }
+
+    #[test]
+    fn test_global_symbol() {
+        let int32 = Type::Const("Int32", None);
+        let uint8 = Type::Const("UInt8", None);
+        let bool_t = Type::Const("Bool", None);
+        assert_eq!(global_symbol("main", "main", &[]), "_KVN4main4mainE");
+        assert_eq!(
+            global_symbol("list", "map", &[int32.clone(), bool_t]),
+            "_KVN4list3mapEI5Int324BoolE"
+        );
+        assert_eq!(
+            global_symbol("m", "<*>", &[Type::new_func(int32, Type::new_ptr(uint8))]),
+            "_KVN1m12_3c__2a__3e_EIF5Int32P5UInt8E"
+        );
+    }
+}
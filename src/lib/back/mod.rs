@@ -1,8 +1,9 @@
 use self::llvm::{Context, Builder, Module};
 use self::codegen::*;
-use Emission;
+use self::target::TargetConfig;
 use lib::CanonPathBuf;
 use lib::front::ast;
+use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::process::Command;
@@ -10,19 +11,61 @@ use std::env::current_dir;
 
 mod llvm;
 mod codegen;
+mod mangle;
+pub mod target;
+
+/// Enum of the different output formats of the compiler
+pub enum Emission {
+    /// Human readable LLVM assembly language code
+    LlvmAsm,
+    /// LLVM bitcode
+    LlvmBc,
+    /// Linkable object code
+    Obj,
+    /// An executable binary
+    Exe,
+    /// A JSON index of all symbol definitions and references
+    Symbols,
+    /// DOT graphs of the calls between global definitions, and of module imports
+    CallGraph,
+    /// The inferred type signatures of all global definitions
+    Types,
+    /// The interface of the module, i.e. its exported types and type signatures
+    Interface,
+}
+impl<S: AsRef<str> + fmt::Display> From<S> for Emission {
+    fn from(s: S) -> Emission {
+        match s.as_ref() {
+            "llvm-ir" => Emission::LlvmAsm,
+            "llvm-bc" => Emission::LlvmBc,
+            "obj" => Emission::Obj,
+            "exe" => Emission::Exe,
+            "symbols" => Emission::Symbols,
+            "callgraph" => Emission::CallGraph,
+            "types" => Emission::Types,
+            "interface" => Emission::Interface,
+            _ => panic!("Unknown emission type `{}`", s),
+        }
+    }
+}
 
 pub fn compile(
     ast: &ast::Ast,
     out_filename: CanonPathBuf,
     explicit_filename: bool,
     emission: Emission,
+    target: &TargetConfig,
     link_libs: &[String],
     lib_paths: &[String],
+    sanitizers: &[String],
 ) {
     let context = Context::new();
     let builder = Builder::new(&context);
     let module = Module::new("main", &context);
-    let mut codegenerator = CodeGenerator::new(&context, &builder, &module);
+    module.set_target(&target.triple);
+    // Set before code generation, as the sizes and alignments of types depend on it
+    module.set_data_layout(&target.llvm_data_layout());
+    let mut codegenerator = CodeGenerator::new(&context, &builder, &module, target);
 
     codegenerator.gen_executable(&ast);
 
@@ -96,6 +139,7 @@ pub fn compile(
                 &[
                     "-o",
                     &out_filename.path().to_string_lossy(),
+                    &format!("--target={}", target.triple),
                 ],
             );
             // Add current dir to link dir paths by default
@@ -114,6 +158,13 @@ pub fn compile(
             for lib in link_libs {
                 clang.args(&["-l", lib]);
             }
+            // TODO: Instrument the generated code as well, by marking the functions with
+            //       the sanitizer attributes, e.g. `sanitize_address`, and running the
+            //       sanitizer passes before `llc`. Currently only the runtime is linked, which
+            //       catches allocator misuse and leaks, but not out of bounds accesses.
+            for sanitizer in sanitizers {
+                clang.arg(format!("-fsanitize={}", sanitizer));
+            }
 
             let output = clang.output().unwrap_or_else(|e| {
                 panic!("Failed to execute linking process: `{:?}`\n{}", clang, e)
@@ -128,5 +179,8 @@ pub fn compile(
                 );
             }
         }
+        Emission::Symbols | Emission::CallGraph | Emission::Types | Emission::Interface => {
+            unreachable!("ICE: Front-end emission passed to backend")
+        }
     }
 }
@@ -0,0 +1,137 @@
+//! Configuration of the compilation target
+//!
+//! Maps the numeric type names of Kvasir, e.g. `Int32` and `UIntPtr`, to machine
+//! representations of a specific width, given a target triple. The mapping is independent
+//! of any specific backend, so that all backends may share the same type layer.
+
+use lib::front::error_exit;
+use llvm_sys::core::LLVMDisposeMessage;
+use llvm_sys::target::{LLVMCopyStringRepOfTargetData, LLVMDisposeTargetData,
+                       LLVM_InitializeAllTargetInfos, LLVM_InitializeAllTargetMCs,
+                       LLVM_InitializeAllTargets};
+use llvm_sys::target_machine::{LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetDataLayout,
+                               LLVMCreateTargetMachine, LLVMDisposeTargetMachine,
+                               LLVMGetDefaultTargetTriple, LLVMGetTargetFromTriple,
+                               LLVMRelocMode};
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+/// The machine representation of a numeric type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumRepr {
+    /// A signed integer of the given bit width
+    Int(usize),
+    /// An unsigned integer of the given bit width
+    UInt(usize),
+    /// An IEEE 754 floating point number of the given bit width
+    Float(usize),
+}
+
+/// Returns the pointer width in bits of the architecture `arch`, if known
+fn arch_ptr_width(arch: &str) -> Option<usize> {
+    match arch {
+        "x86_64" | "aarch64" | "powerpc64" | "powerpc64le" | "mips64" | "mips64el"
+        | "riscv64" | "s390x" | "sparc64" | "wasm64" => Some(64),
+        "i386" | "i586" | "i686" | "arm" | "mips" | "mipsel" | "powerpc" | "riscv32"
+        | "sparc" | "wasm32" => Some(32),
+        "avr" | "msp430" => Some(16),
+        _ if arch.starts_with("armv") || arch.starts_with("thumbv") => Some(32),
+        _ => None,
+    }
+}
+
+/// The configuration of the target that code is compiled for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetConfig {
+    /// The target triple, e.g. `x86_64-unknown-linux-gnu`
+    pub triple: String,
+    /// The width of pointers, and thereby `IntPtr` and `UIntPtr`, in bits
+    pub ptr_width: usize,
+}
+
+impl TargetConfig {
+    /// Returns the configuration of the target described by `triple`
+    ///
+    /// Exits with an error if the architecture of the triple is unknown
+    pub fn from_triple(triple: &str) -> Self {
+        let arch = triple.split('-').next().unwrap_or("");
+        let ptr_width = arch_ptr_width(arch).unwrap_or_else(|| {
+            error_exit(format!(
+                "Unknown architecture `{}` in target triple `{}`",
+                arch, triple
+            ))
+        });
+        TargetConfig {
+            triple: triple.to_string(),
+            ptr_width,
+        }
+    }
+
+    /// Returns the configuration of the host machine
+    pub fn host() -> Self {
+        let triple = unsafe {
+            let c_triple = LLVMGetDefaultTargetTriple();
+            let triple = CStr::from_ptr(c_triple).to_string_lossy().into_owned();
+            LLVMDisposeMessage(c_triple);
+            triple
+        };
+        TargetConfig::from_triple(&triple)
+    }
+
+    /// Returns the LLVM data layout string of this target, which decides the sizes and
+    /// alignments of types
+    ///
+    /// Exits with an error if LLVM doesn't support the target
+    pub fn llvm_data_layout(&self) -> String {
+        let c_triple = CString::new(self.triple.as_str()).expect("ICE: Nul in target triple");
+        unsafe {
+            LLVM_InitializeAllTargetInfos();
+            LLVM_InitializeAllTargets();
+            LLVM_InitializeAllTargetMCs();
+            let mut target = ptr::null_mut();
+            let mut err = ptr::null_mut();
+            if LLVMGetTargetFromTriple(c_triple.as_ptr(), &mut target, &mut err) != 0 {
+                let msg = CStr::from_ptr(err).to_string_lossy().into_owned();
+                LLVMDisposeMessage(err);
+                error_exit(format!("Unsupported target `{}`, {}", self.triple, msg))
+            }
+            let machine = LLVMCreateTargetMachine(
+                target,
+                c_triple.as_ptr(),
+                b"\0".as_ptr() as *const _,
+                b"\0".as_ptr() as *const _,
+                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+                LLVMRelocMode::LLVMRelocPIC,
+                LLVMCodeModel::LLVMCodeModelDefault,
+            );
+            let data = LLVMCreateTargetDataLayout(machine);
+            let c_layout = LLVMCopyStringRepOfTargetData(data);
+            let layout = CStr::from_ptr(c_layout).to_string_lossy().into_owned();
+            LLVMDisposeMessage(c_layout);
+            LLVMDisposeTargetData(data);
+            LLVMDisposeTargetMachine(machine);
+            layout
+        }
+    }
+
+    /// Returns the machine representation of the numeric type `type_name` on this target
+    ///
+    /// Returns `None` if `type_name` is not the name of a numeric type
+    pub fn num_repr(&self, type_name: &str) -> Option<NumRepr> {
+        match type_name {
+            "Int8" => Some(NumRepr::Int(8)),
+            "Int16" => Some(NumRepr::Int(16)),
+            "Int32" => Some(NumRepr::Int(32)),
+            "Int64" => Some(NumRepr::Int(64)),
+            "IntPtr" => Some(NumRepr::Int(self.ptr_width)),
+            "UInt8" => Some(NumRepr::UInt(8)),
+            "UInt16" => Some(NumRepr::UInt(16)),
+            "UInt32" => Some(NumRepr::UInt(32)),
+            "UInt64" => Some(NumRepr::UInt(64)),
+            "UIntPtr" => Some(NumRepr::UInt(self.ptr_width)),
+            "Float32" => Some(NumRepr::Float(32)),
+            "Float64" => Some(NumRepr::Float(64)),
+            _ => None,
+        }
+    }
+}
@@ -12,6 +12,7 @@ lazy_static!{
         Type::Const("UIntPtr", None),
         Type::new_ptr(Type::Const("UInt8", None)));
     pub static ref TYPE_REALWORLD: Type<'static> = Type::Const("RealWorld", None);
+    pub static ref TYPE_UINTPTR: Type<'static> = Type::Const("UIntPtr", None);
 }
 
 /// A polytype
@@ -445,6 +446,16 @@ impl<'src> fmt::Display for Ident<'src> {
     }
 }
 
+/// A type signature of a global that is defined elsewhere, e.g. `(: inc (-> Int64 Int64))`
+///
+/// Module interfaces declare the globals of the module with signatures instead of definitions.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Signature<'src> {
+    pub ident: Ident<'src>,
+    pub typ: Type<'src>,
+    pub pos: SrcPos<'src>,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct ExternDecl<'src> {
     pub ident: Ident<'src>,
@@ -677,6 +688,9 @@ pub struct TypeAscript<'src> {
     pub typ: Type<'src>,
     pub expr: Expr<'src>,
     pub pos: SrcPos<'src>,
+    /// If the ascription is not from source, but from the translation of a special form,
+    /// the reason that the special form requires the type
+    pub reason: Option<&'static str>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -709,6 +723,26 @@ pub struct Cast<'src> {
     pub pos: SrcPos<'src>,
 }
 
+/// A query of the size in bytes of a type, as laid out on the target
+///
+/// Resolved during code generation. The expression is of type `UIntPtr`. As the layout is
+/// only known to the backend, it's not a constant expression, and can't be interpreted
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SizeOf<'src> {
+    pub queried: Type<'src>,
+    pub pos: SrcPos<'src>,
+}
+
+/// A query of the alignment in bytes of a type, as laid out on the target
+///
+/// Resolved during code generation. The expression is of type `UIntPtr`. As the layout is
+/// only known to the backend, it's not a constant expression, and can't be interpreted
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AlignOf<'src> {
+    pub queried: Type<'src>,
+    pub pos: SrcPos<'src>,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Expr<'src> {
     Nil(Nil<'src>),
@@ -725,6 +759,8 @@ pub enum Expr<'src> {
     Car(Box<Car<'src>>),
     Cdr(Box<Cdr<'src>>),
     Cast(Box<Cast<'src>>),
+    SizeOf(Box<SizeOf<'src>>),
+    AlignOf(Box<AlignOf<'src>>),
 }
 
 impl<'src> Expr<'src> {
@@ -744,6 +780,8 @@ impl<'src> Expr<'src> {
             Expr::Car(ref c) => &c.pos,
             Expr::Cdr(ref c) => &c.pos,
             Expr::Cast(ref c) => &c.pos,
+            Expr::SizeOf(ref s) => &s.pos,
+            Expr::AlignOf(ref a) => &a.pos,
         }
     }
 
@@ -770,6 +808,7 @@ impl<'src> Expr<'src> {
             Expr::Car(ref c) => &c.typ,
             Expr::Cdr(ref c) => &c.typ,
             Expr::Cast(ref c) => &c.typ,
+            Expr::SizeOf(_) | Expr::AlignOf(_) => &TYPE_UINTPTR,
         }
     }
 
@@ -808,17 +847,70 @@ impl<'src> Expr<'src> {
 pub struct AdtVariant<'src> {
     pub name: Ident<'src>,
     pub members: Vec<Type<'src>>,
+    /// Explicit discriminant, e.g. `(= Red 1)`. Only allowed for variants without members.
+    /// Not yet supported by codegen
+    pub discriminant: Option<i64>,
     pub pos: SrcPos<'src>,
 }
 
+/// Memory representation of an algebraic data type, as given by a `repr` attribute
+///
+/// Not yet supported by codegen, which rejects data types with a `repr` attribute
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdtRepr<'src> {
+    /// `(repr C)`. Laid out like the corresponding C enum or tagged union
+    C,
+    /// `(repr T)` for some integer type `T`. The discriminant is of type `T`
+    Int(Type<'src>),
+}
+
+// TODO: Type parameters of data types, e.g. `(data (Tagged T) (Tagged UInt64))`.
+//       Parameters that are not used in any member are phantom, and only serve as
+//       type-level tags. As they don't affect the representation, a phantom parameter only
+//       has to be accounted for during type checking, and may be erased before codegen.
+//       Data types without members in any variant should be zero-sized, and not take up
+//       any space in aggregates.
 /// Algebraic Data Type definition
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct AdtDef<'src> {
     pub name: Ident<'src>,
+    pub repr: Option<AdtRepr<'src>>,
     pub variants: Vec<AdtVariant<'src>>,
     pub pos: SrcPos<'src>,
 }
 
+impl<'src> AdtDef<'src> {
+    /// Returns the discriminants of the variants, in order
+    ///
+    /// Like in C, a variant without an explicit discriminant gets the discriminant
+    /// of the previous variant plus one, or zero if it's the first variant
+    pub fn discriminants(&self) -> Vec<i64> {
+        let mut next = 0;
+        self.variants
+            .iter()
+            .map(|v| {
+                let d = v.discriminant.unwrap_or(next);
+                next = d.wrapping_add(1);
+                d
+            })
+            .collect()
+    }
+}
+
+/// Newtype definition
+///
+/// A type that is distinct from its underlying type during type checking,
+/// but has the same representation. Constructed with the generated function of the
+/// same name, e.g. `(Meters 1.0)`, and unwrapped with a cast to the underlying type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewtypeDef<'src> {
+    pub name: Ident<'src>,
+    pub underlying: Type<'src>,
+    /// Constraints that the newtype derives from the underlying type, e.g. `Num`
+    pub derives: BTreeSet<&'src str>,
+    pub pos: SrcPos<'src>,
+}
+
 /// A module of definitions and declarations of functions and variables
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Ast<'src> {
@@ -826,6 +918,11 @@ pub struct Ast<'src> {
     ///
     /// May include declarations of external both functions and variables
     pub externs: BTreeMap<&'src str, ExternDecl<'src>>,
+    /// Type signatures of globals, as found in module interfaces
+    ///
+    /// TODO: Check signatures against the definitions of the same globals, and let an imported
+    ///       interface provide its signatures to type checking.
+    pub signatures: BTreeMap<&'src str, Signature<'src>>,
     /// Global variable definitions
     ///
     /// May include both top-level functions and global variables.
@@ -834,4 +931,20 @@ pub struct Ast<'src> {
     pub globals: TopologicallyOrderedDependencyGroups<'src>,
     /// Algebraic Data Type definitions
     pub datas: BTreeMap<&'src str, AdtDef<'src>>,
+    /// Newtype definitions
+    pub newtypes: BTreeMap<&'src str, NewtypeDef<'src>>,
+    /// Docstrings of global definitions
+    pub docs: BTreeMap<&'src str, String>,
+    /// Global definitions marked as total, whose termination is checked
+    pub totals: BTreeSet<&'src str>,
+    /// Features required by `(feature NAME)` items, and the position of the first item of each
+    pub features: BTreeMap<&'src str, SrcPos<'src>>,
+    /// Type holes, `?`, in type annotations, and their positions
+    ///
+    /// A type hole is inferred like `_`, but the inferred type is reported
+    pub type_holes: Vec<(TVar<'src>, SrcPos<'src>)>,
+    /// Import dependencies between modules
+    ///
+    /// Maps the path of each parsed module to the paths of the modules it imports
+    pub module_deps: BTreeMap<path::PathBuf, BTreeSet<path::PathBuf>>,
 }
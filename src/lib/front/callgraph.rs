@@ -0,0 +1,71 @@
+//! Emission of call graphs and module dependency graphs in the Graphviz DOT format
+//!
+//! The call graph has an edge from each global definition to every global definition
+//! and external declaration that it refers to. Locals shadowing globals are taken into
+//! account. The module dependency graph has an edge from each module to every module it imports.
+
+use super::ast::*;
+use super::dependency_graph::sibling_refs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Quote `s` as a DOT identifier
+fn dot_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn module_label(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns, for each global definition, the set of globals and externs it refers to
+pub fn global_refs<'src>(ast: &Ast<'src>) -> BTreeMap<&'src str, BTreeSet<&'src str>> {
+    let mut names = ast.globals
+        .ids()
+        .chain(ast.externs.keys().cloned())
+        .collect::<BTreeSet<_>>();
+    ast.globals
+        .bindings()
+        .map(|b| (b.ident.s, sibling_refs(&b.val, &mut names)))
+        .collect()
+}
+
+/// Write the call graph of the global definitions of `ast`,
+/// and the import graph of its modules, as DOT digraphs to `w`
+pub fn write_callgraph_dot<W: Write>(ast: &Ast, w: &mut W) -> io::Result<()> {
+    writeln!(w, "digraph calls {{")?;
+    for ext in ast.externs.keys() {
+        writeln!(w, "    {} [shape=box];", dot_id(ext))?;
+    }
+    for (caller, callees) in global_refs(ast) {
+        writeln!(w, "    {};", dot_id(caller))?;
+        for callee in callees {
+            writeln!(w, "    {} -> {};", dot_id(caller), dot_id(callee))?;
+        }
+    }
+    writeln!(w, "}}")?;
+    writeln!(w, "")?;
+    writeln!(w, "digraph modules {{")?;
+    for (module, imports) in &ast.module_deps {
+        let module_s = module.to_string_lossy();
+        writeln!(
+            w,
+            "    {} [label={}];",
+            dot_id(&module_s),
+            dot_id(&module_label(module))
+        )?;
+        for import in imports {
+            writeln!(
+                w,
+                "    {} -> {};",
+                dot_id(&module_s),
+                dot_id(&import.to_string_lossy())
+            )?;
+        }
+    }
+    writeln!(w, "}}")
+}
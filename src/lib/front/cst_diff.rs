@@ -0,0 +1,252 @@
+//! Structural diffing of Concrete Syntax Trees
+//!
+//! Produces an edit script that transforms one sequence of syntax trees into another,
+//! e.g. two versions of a file. Trees are compared by structure only, so moving code
+//! around without otherwise changing it does not produce any edits within it.
+
+use super::SrcPos;
+use super::lex::{CST, DIAG_MAX_DEPTH, DIAG_MAX_WIDTH};
+use std::cmp::max;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+
+/// An edit of a syntax tree
+#[derive(Debug)]
+pub enum Edit<'c, 's: 'c> {
+    /// Insertion of the tree `new` into the list at `parent`, or at
+    /// the top level if `None`, before the old element at `index`
+    Insert {
+        parent: Option<&'c SrcPos<'s>>,
+        index: usize,
+        new: &'c CST<'s>,
+    },
+    /// Deletion of a tree
+    Delete(&'c CST<'s>),
+    /// Replacement of an old tree with a new one
+    Replace { old: &'c CST<'s>, new: &'c CST<'s> },
+}
+
+/// Returns whether `a` and `b` are structurally equal, disregarding positions
+pub fn cst_eq(a: &CST, b: &CST) -> bool {
+    match (a, b) {
+        (&CST::Ident(x, _), &CST::Ident(y, _)) | (&CST::Num(x, _), &CST::Num(y, _)) => x == y,
+        (&CST::Str(ref x, _), &CST::Str(ref y, _)) => x == y,
+        (&CST::SExpr(ref xs, _), &CST::SExpr(ref ys, _))
+        | (&CST::Braces(ref xs, _), &CST::Braces(ref ys, _)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| cst_eq(x, y))
+        }
+        _ => false,
+    }
+}
+
+/// Feed the structure of `cst` into `state`, disregarding positions
+///
+/// Consistent with `cst_eq`, i.e. trees that are structurally equal hash equally.
+pub fn cst_hash<H: Hasher>(cst: &CST, state: &mut H) {
+    let hash_seq = |tag, xs: &[CST], state: &mut H| {
+        state.write_u8(tag);
+        state.write_usize(xs.len());
+        for x in xs {
+            cst_hash(x, state)
+        }
+    };
+    match *cst {
+        CST::SExpr(ref xs, _) => hash_seq(0, xs, state),
+        CST::Braces(ref xs, _) => hash_seq(1, xs, state),
+        CST::Ident(x, _) => {
+            state.write_u8(2);
+            x.hash(state)
+        }
+        CST::Num(x, _) => {
+            state.write_u8(3);
+            x.hash(state)
+        }
+        CST::Str(ref x, _) => {
+            state.write_u8(4);
+            x.hash(state)
+        }
+    }
+}
+
+/// A syntax tree that is hashed and compared by structure only, disregarding positions
+///
+/// `CST` itself compares positions as well. Wrap a tree in `Structural` to use it as the key
+/// of a `HashMap` or `HashSet` that should treat equal code at different places as the same.
+#[derive(Debug, Clone, Copy)]
+pub struct Structural<'c, 's: 'c>(pub &'c CST<'s>);
+
+impl<'c, 's> PartialEq for Structural<'c, 's> {
+    fn eq(&self, other: &Self) -> bool {
+        cst_eq(self.0, other.0)
+    }
+}
+
+impl<'c, 's> Eq for Structural<'c, 's> {}
+
+impl<'c, 's> Hash for Structural<'c, 's> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        cst_hash(self.0, state)
+    }
+}
+
+/// Returns the table of lengths of longest common subsequences of the suffixes of `olds`
+/// and `news`
+fn lcs_table(olds: &[CST], news: &[CST]) -> Vec<Vec<usize>> {
+    let mut table = vec![vec![0; news.len() + 1]; olds.len() + 1];
+    for i in (0..olds.len()).rev() {
+        for j in (0..news.len()).rev() {
+            table[i][j] = if cst_eq(&olds[i], &news[j]) {
+                table[i + 1][j + 1] + 1
+            } else {
+                max(table[i + 1][j], table[i][j + 1])
+            };
+        }
+    }
+    table
+}
+
+/// Add the edits of changing the tree `old` into the tree `new`
+///
+/// Two s-expressions are diffed recursively, while other trees are replaced
+fn diff_cst<'c, 's>(old: &'c CST<'s>, new: &'c CST<'s>, edits: &mut Vec<Edit<'c, 's>>) {
+    match (old, new) {
+        (&CST::SExpr(ref olds, ref pos), &CST::SExpr(ref news, _))
+        | (&CST::Braces(ref olds, ref pos), &CST::Braces(ref news, _)) => {
+            diff_seq(Some(pos), olds, news, edits)
+        }
+        _ => edits.push(Edit::Replace { old, new }),
+    }
+}
+
+/// Add the edits of changing the sequence of trees `olds` into `news`
+///
+/// Elements are aligned by a longest common subsequence. Unaligned elements at the same
+/// place in the old and new sequences are diffed pairwise, while the rest are inserted
+/// or deleted.
+fn diff_seq<'c, 's>(
+    parent: Option<&'c SrcPos<'s>>,
+    olds: &'c [CST<'s>],
+    news: &'c [CST<'s>],
+    edits: &mut Vec<Edit<'c, 's>>,
+) {
+    let table = lcs_table(olds, news);
+    let (mut i, mut j) = (0, 0);
+    while i < olds.len() || j < news.len() {
+        if i < olds.len() && j < news.len() && cst_eq(&olds[i], &news[j]) {
+            i += 1;
+            j += 1;
+        } else if i < olds.len() && j < news.len() && table[i + 1][j + 1] == table[i][j] {
+            diff_cst(&olds[i], &news[j], edits);
+            i += 1;
+            j += 1;
+        } else if j < news.len() && (i == olds.len() || table[i][j + 1] >= table[i + 1][j]) {
+            edits.push(Edit::Insert {
+                parent,
+                index: i,
+                new: &news[j],
+            });
+            j += 1;
+        } else {
+            edits.push(Edit::Delete(&olds[i]));
+            i += 1;
+        }
+    }
+}
+
+/// Returns an edit script that transforms the trees `olds` into `news`
+pub fn diff_csts<'c, 's>(olds: &'c [CST<'s>], news: &'c [CST<'s>]) -> Vec<Edit<'c, 's>> {
+    let mut edits = Vec::new();
+    diff_seq(None, olds, news, &mut edits);
+    edits
+}
+
+/// Write the edit script `edits` in a human readable format to `w`
+///
+/// Large trees are elided, to keep the script readable
+pub fn write_edit_script<W: Write>(edits: &[Edit], w: &mut W) -> io::Result<()> {
+    let elided = |c: &CST| c.elided(DIAG_MAX_DEPTH, DIAG_MAX_WIDTH);
+    for edit in edits {
+        match *edit {
+            Edit::Insert { new, .. } => {
+                new.pos().write_note(w, format!("Inserted `{}`", elided(new)))
+            }
+            Edit::Delete(old) => old.pos().write_note(w, format!("Deleted `{}`", elided(old))),
+            Edit::Replace { old, new } => old.pos().write_note(
+                w,
+                format!("Replaced `{}` with `{}`", elided(old), elided(new)),
+            ),
+        }
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use lib::front::lex::lex_src;
+    use std::path::Path;
+    use super::{diff_csts, Edit};
+
+    /// Returns the edit script of changing the source `old` into `new`, one line per edit
+    ///
+    /// The parent of an insertion is given by its offset in `old`
+    fn script(old: &'static str, new: &'static str) -> Vec<String> {
+        let olds = lex_src(Path::new("old.kvs"), old);
+        let news = lex_src(Path::new("new.kvs"), new);
+        let edits = diff_csts(&olds, &news);
+        let lines = edits
+            .iter()
+            .map(|edit| match *edit {
+                Edit::Insert { parent, index, new } => format!(
+                    "insert {} into {:?} at {}",
+                    new,
+                    parent.map(|p| p.start),
+                    index
+                ),
+                Edit::Delete(old) => format!("delete {}", old),
+                Edit::Replace { old, new } => format!("replace {} with {}", old, new),
+            })
+            .collect();
+        lines
+    }
+
+    #[test]
+    fn test_diff_disregards_positions() {
+        assert_eq!(script("(f x) (g)", "(f x) (g)"), Vec::<String>::new());
+        assert_eq!(script("(f  x)\n(g)", "(f x) (g)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_diff_top_level() {
+        assert_eq!(
+            script("", "(a) (b)"),
+            vec!["insert (a) into None at 0", "insert (b) into None at 0"]
+        );
+        assert_eq!(script("(a) (b)", ""), vec!["delete (a)", "delete (b)"]);
+        assert_eq!(script("(a) (c)", "(a) (b) (c)"), vec!["insert (b) into None at 1"]);
+    }
+
+    #[test]
+    fn test_diff_moved_tree_is_not_diffed_within() {
+        assert_eq!(
+            script("(a) (b (c d))", "(b (c d)) (a)"),
+            vec!["insert (b (c d)) into None at 0", "delete (b (c d))"]
+        );
+    }
+
+    #[test]
+    fn test_diff_recurses_into_lists() {
+        assert_eq!(
+            script("(define (f x) (+ x 1))", "(define (f x) (+ x 2))"),
+            vec!["replace 1 with 2"]
+        );
+        assert_eq!(script("(g (f a b))", "(g (f a c b))"), vec!["insert c into Some(3) at 2"]);
+        assert_eq!(script("(g (f a c b))", "(g (f a b))"), vec!["delete c"]);
+        assert_eq!(script("{a b}", "{a c}"), vec!["replace b with c"]);
+    }
+
+    #[test]
+    fn test_diff_replaces_trees_of_different_kinds() {
+        assert_eq!(script("(a)", "{a}"), vec!["replace (a) with {a}"]);
+        assert_eq!(script("(f (x))", "(f x)"), vec!["replace (x) with x"]);
+    }
+}
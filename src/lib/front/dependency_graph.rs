@@ -11,7 +11,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use super::ast::*;
 
 /// Returns a set of all siblings being referred to in this expression
-fn sibling_refs<'src>(e: &Expr<'src>, siblings: &mut BTreeSet<&'src str>) -> BTreeSet<&'src str> {
+pub fn sibling_refs<'src>(e: &Expr<'src>, siblings: &mut BTreeSet<&'src str>) -> BTreeSet<&'src str> {
     use self::Expr::*;
     match *e {
         Variable(ref v) => {
@@ -61,7 +61,7 @@ fn sibling_refs<'src>(e: &Expr<'src>, siblings: &mut BTreeSet<&'src str>) -> BTr
         Cdr(ref c) => sibling_refs(&c.expr, siblings),
         TypeAscript(ref a) => sibling_refs(&a.expr, siblings),
         Cast(ref c) => sibling_refs(&c.expr, siblings),
-        Nil(_) | NumLit(_) | StrLit(_) | Bool(_) => BTreeSet::new(),
+        Nil(_) | NumLit(_) | StrLit(_) | Bool(_) | SizeOf(_) | AlignOf(_) => BTreeSet::new(),
     }
 }
 
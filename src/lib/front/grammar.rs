@@ -0,0 +1,206 @@
+//! The surface grammar of Kvasir, as data
+//!
+//! Describes the syntax accepted by the lexer and parser as a set of production rules.
+//! The rules are written in the EBNF notation of the W3C XML specification, which is also
+//! accepted by railroad diagram generators, so documentation and editor grammars can be
+//! generated from here instead of being maintained by hand.
+//!
+//! The rules must be kept in sync with `parse`, e.g. when a new special form is added.
+//! A test checks that every keyword that the parser dispatches on occurs in the rules.
+
+use std::io::{self, Write};
+
+/// A production rule of the grammar
+pub struct Rule {
+    /// The name of the nonterminal defined by the rule
+    pub name: &'static str,
+    /// A short description of the rule
+    pub doc: &'static str,
+    /// The alternatives of the rule, each a sequence of terminals and nonterminals in EBNF
+    pub alternatives: &'static [&'static str],
+}
+
+/// The production rules of the grammar, starting with the start symbol `program`
+pub const GRAMMAR: &'static [Rule] = &[
+    Rule {
+        name: "program",
+        doc: "A source file, a sequence of top level items",
+        alternatives: &["item*"],
+    },
+    Rule {
+        name: "item",
+        doc: "A top level definition or declaration",
+        alternatives: &[
+            "'(' 'import' ident ')'",
+            "'(' 'extern' ident type ')'",
+            "'(' ':' ident type ')'",
+            "'(' 'total'? 'define' pattern string? expr ')'",
+            "'(' 'total'? 'define:' pattern string? type expr ')'",
+            "'(' 'feature' ident ')'",
+            "'(' 'data' ident ( '(' 'repr' ident ')' )? variant+ ')'",
+            "'(' 'newtype' ident type ( '(' 'deriving' ident* ')' )? ')'",
+            "'(' ( 'infixl' | 'infixr' ) num ident+ ')'",
+        ],
+    },
+    Rule {
+        name: "variant",
+        doc: "A variant of an algebraic data type, with members or an explicit discriminant",
+        alternatives: &["ident", "'(' ident type+ ')'", "'(' '=' ident num ')'"],
+    },
+    Rule {
+        name: "pattern",
+        doc: "The pattern of a binding, a variable or a function with parameters",
+        alternatives: &["ident", "'(' ident ident+ ')'"],
+    },
+    Rule {
+        name: "binding",
+        doc: "A binding of a `let` form",
+        alternatives: &["'(' pattern expr ')'"],
+    },
+    Rule {
+        name: "type",
+        doc: "A type. Identifiers starting with a lower case letter are type variables",
+        alternatives: &[
+            "ident",
+            "'_'",
+            "'?'",
+            "'Nil'",
+            "'(' ':' ident ident* ')'",
+            "'(' '->' type+ type ')'",
+            "'(' 'Cons' type type ')'",
+            "'(' 'Ptr' type ')'",
+            "'(' 'Lazy' type ')'",
+        ],
+    },
+    Rule {
+        name: "expr",
+        doc: "An expression",
+        alternatives: &[
+            "num",
+            "string",
+            "'#x' string",
+            "ident",
+            "'nil'",
+            "'true'",
+            "'false'",
+            "\"'\" datum",
+            "'(' ')'",
+            "'(' 'if' expr expr expr ')'",
+            "'(' 'lambda' '(' ident* ')' expr ')'",
+            "'(' 'let' '(' binding* ')' expr ')'",
+            "'(' ':' expr type ')'",
+            "'(' 'cons' expr expr ')'",
+            "'(' 'car' expr ')'",
+            "'(' 'cdr' expr ')'",
+            "'(' 'cast' expr type ')'",
+            "'(' 'size-of' type ')'",
+            "'(' 'align-of' type ')'",
+            "'(' 'quote' datum ')'",
+            "'(' 'cond' ( '(' expr expr ')' )* '(' 'else' expr ')' ')'",
+            "'(' 'delay' expr ')'",
+            "'(' 'force' expr ')'",
+            "'(' 'catch' expr '(' ident expr ')' ')'",
+            "'(' 'panic' expr expr* ')'",
+            "'(' 'unreachable' ')'",
+            "'(' 'assert' expr ')'",
+            "'(' 'assert-eq' expr expr ')'",
+            "'(' 'fn*' ( '(' '(' fn-star-pattern* ')' expr ')' )+ ')'",
+            "'(' 'partial' expr expr+ ')'",
+            "'(' 'infix' expr ( ident expr )* ')'",
+            "'(' ( '->' | '->>' ) expr expr* ')'",
+            "'(' 'as->' expr ident expr* ')'",
+            "'(' expr expr+ ')'",
+        ],
+    },
+    Rule {
+        name: "fn-star-pattern",
+        doc: "A pattern of a clause of a `fn*` form",
+        alternatives: &["ident", "num", "'true'", "'false'"],
+    },
+    Rule {
+        name: "datum",
+        doc: "A syntax tree as data, as quoted by `quote`",
+        alternatives: &["ident", "num", "string", "'(' datum* ')'"],
+    },
+];
+
+/// Write the grammar to `w` in EBNF, one rule per line, each preceded by its description
+pub fn write_ebnf<W: Write>(w: &mut W) -> io::Result<()> {
+    for rule in GRAMMAR {
+        writeln!(w, "/* {} */", rule.doc)?;
+        let indent = rule.name.len() + 3;
+        for (i, alt) in rule.alternatives.iter().enumerate() {
+            if i == 0 {
+                writeln!(w, "{} ::= {}", rule.name, alt)?;
+            } else {
+                writeln!(w, "{:width$}| {}", "", alt, width = indent)?;
+            }
+        }
+        writeln!(w, "")?;
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::GRAMMAR;
+
+    /// The source of the parser, which is searched for the keywords it dispatches on
+    const PARSE_SRC: &'static str = include_str!("parse.rs");
+
+    /// Returns the source of the method `name` of the parser, up to the next method
+    fn method_src(name: &str) -> &'static str {
+        let start = ["(", "<"]
+            .iter()
+            .filter_map(|delim| PARSE_SRC.find(&format!("    fn {}{}", name, delim)))
+            .next()
+            .unwrap_or_else(|| panic!("Method `{}` not found in the parser", name));
+        let rest = &PARSE_SRC[start + 1..];
+        &rest[..rest.find("\n    fn ").unwrap_or(rest.len())]
+    }
+
+    /// Returns the keywords of the match arms of the form `"KEYWORD" | ... =>` in `src`
+    fn str_arms(src: &'static str) -> Vec<&'static str> {
+        src.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with('"') && line.contains(" => "))
+            .flat_map(|line| line[..line.find(" => ").unwrap()].split(" | "))
+            .map(|pattern| pattern.trim_matches('"'))
+            .collect()
+    }
+
+    /// Returns the keywords of the match arms of the form `CST::Ident("KEYWORD", _)` in `src`
+    fn ident_arms(src: &'static str) -> Vec<&'static str> {
+        src.split("CST::Ident(\"")
+            .skip(1)
+            .filter_map(|rest| rest.find("\", _)").map(|end| &rest[..end]))
+            .collect()
+    }
+
+    fn in_grammar(keyword: &str) -> bool {
+        let terminal = format!("'{}'", keyword);
+        GRAMMAR
+            .iter()
+            .any(|rule| rule.alternatives.iter().any(|alt| alt.contains(&terminal)))
+    }
+
+    #[test]
+    fn test_grammar_has_all_keywords_of_parser() {
+        let top_level = str_arms(method_src("_get_top_level_csts"));
+        let types = str_arms(method_src("parse_type_sexpr"))
+            .into_iter()
+            .chain(str_arms(method_src("parse_type_ident")))
+            .collect::<Vec<_>>();
+        let forms = ident_arms(method_src("parse_expr"));
+        assert!(top_level.contains(&"define") && top_level.contains(&":"));
+        assert!(types.contains(&"Lazy") && types.contains(&"Nil"));
+        assert!(forms.contains(&"delay") && forms.contains(&"as->"));
+        let missing = top_level
+            .into_iter()
+            .chain(types)
+            .chain(forms)
+            .filter(|keyword| !in_grammar(keyword))
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "Keywords missing in GRAMMAR: {:?}", missing);
+    }
+}
@@ -20,6 +20,7 @@ use lib::front::monomorphization::*;
 use lib::front::substitution::*;
 use std::collections::{HashMap, BTreeMap, HashSet};
 use std::fmt::{self, Display};
+use std::io;
 use std::iter::{once, FromIterator};
 use itertools::{zip, Itertools};
 
@@ -34,6 +35,14 @@ enum InferenceErr<'src> {
         sub_found: Type<'src>,
     },
     ArmsDiffer(Type<'src>, Type<'src>),
+    /// Infinite type. The type variable occurs in the type it must be equal to,
+    /// as in self application
+    InfiniteType {
+        expected: Type<'src>,
+        found: Type<'src>,
+        var: Type<'src>,
+        typ: Type<'src>,
+    },
 }
 
 impl<'src> Display for InferenceErr<'src> {
@@ -72,6 +81,50 @@ impl<'src> Display for InferenceErr<'src> {
                     a
                 )
             }
+            InfiniteType {
+                ref expected,
+                ref found,
+                ref var,
+                ref typ,
+            } => {
+                write!(
+                    f,
+                    "Infinite type: Expected `{}`, found `{}`\n\
+                        Cannot construct the infinite type `{} = {}`",
+                    expected,
+                    found,
+                    var,
+                    typ
+                )
+            }
+        }
+    }
+}
+
+/// The origin of the expected type of an expression, for use in diagnostics
+#[derive(Clone)]
+enum Expectation<'src> {
+    /// Nothing in particular, e.g. a fresh type variable
+    Unknown,
+    /// A type ascription
+    Annotation(SrcPos<'src>),
+    /// The type of the consequent of an `if`
+    Branch(SrcPos<'src>),
+    /// A requirement of a special form, and the reason for it
+    SpecialForm(SrcPos<'src>, &'static str),
+}
+
+impl<'src> Expectation<'src> {
+    fn print_note(&self) {
+        match *self {
+            Expectation::Unknown => (),
+            Expectation::Annotation(ref pos) => {
+                pos.print_note("Expected type is given by this type ascription")
+            }
+            Expectation::Branch(ref pos) => {
+                pos.print_note("Expected type is given by the type of the consequent")
+            }
+            Expectation::SpecialForm(ref pos, reason) => pos.print_note(reason),
         }
     }
 }
@@ -91,6 +144,20 @@ fn type_mis_sub<'src>(
     sub_expected: &Type<'src>,
     sub_found: &Type<'src>,
 ) -> InferenceErr<'src> {
+    let occurring = match (sub_expected, sub_found) {
+        (&Type::Var(ref tv), t) | (t, &Type::Var(ref tv)) if occurs_in(tv.id, t, type_var_map) => {
+            Some((Type::Var(tv.clone()), t))
+        }
+        _ => None,
+    };
+    if let Some((var, typ)) = occurring {
+        return InfiniteType {
+            expected: subst(expected, type_var_map),
+            found: subst(found, type_var_map),
+            var: var,
+            typ: subst(typ, type_var_map),
+        };
+    }
     TypeMisSub {
         expected: subst(expected, type_var_map),
         found: subst(found, type_var_map),
@@ -172,7 +239,12 @@ fn wrap_vars_types_in_apps_<'src>(
         Expr::Cast(ref mut c) => {
             wrap_vars_types_in_apps_(&mut c.expr, vars, app_args);
         }
-        Expr::Nil(_) | Expr::NumLit(_) | Expr::StrLit(_) | Expr::Bool(_) => (),
+        Expr::Nil(_)
+        | Expr::NumLit(_)
+        | Expr::StrLit(_)
+        | Expr::Bool(_)
+        | Expr::SizeOf(_)
+        | Expr::AlignOf(_) => (),
     }
 }
 
@@ -195,10 +267,12 @@ fn wrap_vars_types_in_apps<'src>(
 }
 
 /// The definition of a type name
-enum TypeDef {
+enum TypeDef<'src> {
     /// It's a core type that can be handled by the code generation backend. E.g. the numeric
     /// types `Int32`, `Float64`, etc.
     Core,
+    /// A newtype, and the constraints it derives from its underlying type
+    Newtype(BTreeSet<&'src str>),
     // TODO: Type alias
     // TODO: Algebraic datatype
 }
@@ -215,7 +289,9 @@ struct Inferrer<'a, 'src: 'a> {
     /// A map of core types and used defined types
     ///
     /// Numeric types, cons, (TODO) type aliases, (TODO) data type definitions
-    type_defs: HashMap<&'src str, TypeDef>,
+    type_defs: HashMap<&'src str, TypeDef<'src>>,
+    /// A stack of the origins of the expected types of the expressions being inferred
+    expectations: Vec<Expectation<'src>>,
 }
 
 impl<'a, 'src: 'a> Inferrer<'a, 'src> {
@@ -246,9 +322,65 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
                 "Nil" => Core,
                 "RealWorld" => Core,
             },
+            expectations: Vec::new(),
+        }
+    }
+
+    /// Add the newtype definitions `newtypes` to the type definitions
+    ///
+    /// Checks that the underlying types of the newtypes fulfill the derived constraints
+    fn add_newtypes(&mut self, newtypes: &BTreeMap<&'src str, NewtypeDef<'src>>) {
+        for nt in newtypes.values() {
+            self.type_defs
+                .insert(nt.name.s, TypeDef::Newtype(nt.derives.clone()));
+        }
+        for nt in newtypes.values() {
+            erase_newtypes_in_type(&nt.underlying, newtypes, &mut vec![nt.name.s]);
+            if !self.fulfills_constraints(&nt.underlying, &nt.derives) {
+                nt.pos.error_exit(format!(
+                    "Newtype `{}` can not derive the constraints `{}` of its underlying type `{}`",
+                    nt.name.s,
+                    nt.derives.iter().join(" "),
+                    nt.underlying
+                ))
+            }
+        }
+    }
+
+    /// Returns whether `t` fulfills the constraints `cs`,
+    /// taking derived constraints of newtypes into account
+    fn fulfills_constraints(&self, t: &Type<'src>, cs: &BTreeSet<&str>) -> bool {
+        match *t {
+            Type::Const(s, _) => match self.type_defs.get(s) {
+                Some(&TypeDef::Newtype(ref derives)) => cs.iter().all(|c| derives.contains(c)),
+                _ => t.fulfills_constraints(cs),
+            },
+            _ => t.fulfills_constraints(cs),
         }
     }
 
+    /// Infer the type of `expr`, where `expected_type` originates from `origin`
+    fn infer_expr_expecting(
+        &mut self,
+        expr: &mut Expr<'src>,
+        expected_type: &Type<'src>,
+        origin: Expectation<'src>,
+    ) -> Type<'src> {
+        self.expectations.push(origin);
+        let t = self.infer_expr(expr, expected_type);
+        self.expectations.pop();
+        t
+    }
+
+    /// Print the type error `err` at `pos`, followed by the origin of the expected type, and exit
+    fn mismatch_exit<E: Display>(&self, pos: &SrcPos<'src>, err: E) -> ! {
+        pos.print_error(err);
+        if let Some(origin) = self.expectations.last() {
+            origin.print_note()
+        }
+        exit()
+    }
+
     fn push_var(&mut self, id: &'src str, t: Type<'src>) {
         self.var_env.entry(id).or_insert(Vec::new()).push(t)
     }
@@ -390,11 +522,13 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
                 self.unify(&t, x)
             }
             (&Var(ref t), &Var(ref u)) => self.unify_vars(t, u),
+            // Binding the variable would create a circular type. Leave the variable unbound and
+            // let the caller report the infinite type
             (&Var(ref tv), _) if occurs_in(tv.id, b, &self.type_var_map) => {
-                panic!("ICE: unify: `{}` occurs in `{}`", tv.id, b);
+                Err((a.clone(), b.clone()))
             }
             (&Var(ref tv), _) if tv.explicit.is_some() => Err((a.clone(), b.clone())),
-            (&Var(ref tv), _) if b.fulfills_constraints(&tv.constrs) => {
+            (&Var(ref tv), _) if self.fulfills_constraints(b, &tv.constrs) => {
                 self.type_var_map.insert(tv.id, b.clone());
                 Ok(b.clone())
             }
@@ -439,7 +573,8 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     fn infer_nil(&mut self, nil: &mut Nil<'src>, expected_type: &Type<'src>) -> Type<'src> {
         self.unify(expected_type, &TYPE_NIL).unwrap_or_else(
             |(e, f)| {
-                nil.pos.error_exit(type_mis(&mut self.type_var_map, &e, &f))
+                let err = type_mis(&mut self.type_var_map, &e, &f);
+                self.mismatch_exit(&nil.pos, err)
             },
         )
     }
@@ -448,7 +583,8 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     fn infer_str_lit(&mut self, lit: &mut StrLit<'src>, expected_type: &Type<'src>) -> Type<'src> {
         self.unify(expected_type, &TYPE_STRING).unwrap_or_else(
             |(e, f)| {
-                lit.pos.error_exit(type_mis(&mut self.type_var_map, &e, &f))
+                let err = type_mis(&mut self.type_var_map, &e, &f);
+                self.mismatch_exit(&lit.pos, err)
             },
         )
     }
@@ -457,7 +593,22 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     fn infer_bool(&mut self, b: &mut Bool<'src>, expected_type: &Type<'src>) -> Type<'src> {
         self.unify(expected_type, &TYPE_BOOL).unwrap_or_else(
             |(e, f)| {
-                b.pos.error_exit(type_mis(&mut self.type_var_map, &e, &f))
+                let err = type_mis(&mut self.type_var_map, &e, &f);
+                self.mismatch_exit(&b.pos, err)
+            },
+        )
+    }
+
+    /// Infer the type of a size or alignment query, which is always `UIntPtr`
+    fn infer_layout_query(
+        &mut self,
+        pos: &SrcPos<'src>,
+        expected_type: &Type<'src>,
+    ) -> Type<'src> {
+        self.unify(expected_type, &TYPE_UINTPTR).unwrap_or_else(
+            |(e, f)| {
+                let err = type_mis(&mut self.type_var_map, &e, &f);
+                self.mismatch_exit(pos, err)
             },
         )
     }
@@ -478,10 +629,13 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
             explicit: None,
         });
         let num_type = self.unify(expected_type, &tv_num).unwrap_or_else(|_| {
-            lit.pos.error_exit(format!(
-                "Type mismatch. Expected `{}`, found numeric literal",
-                expected_type
-            ))
+            self.mismatch_exit(
+                &lit.pos,
+                format!(
+                    "Type mismatch. Expected `{}`, found numeric literal",
+                    expected_type
+                ),
+            )
         });
         lit.typ = num_type;
         &lit.typ
@@ -506,24 +660,28 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
             // in the resulting type of the application.
             var.typ = self.instantiate(&typ);
             let unif = self.unify(expected_type, &var.typ).unwrap_or_else(|_| {
-                var.ident.pos.error_exit(format!(
-                    "Variable of type `{}` cannot be instantiated to expected type `{}`",
-                    typ,
-                    expected_type
-                ))
+                self.mismatch_exit(
+                    &var.ident.pos,
+                    format!(
+                        "Variable of type `{}` cannot be instantiated to expected type `{}`",
+                        typ,
+                        expected_type
+                    ),
+                )
             });
             unif
         } else if let Some(ext) = self.externs.get(var.ident.s) {
             // An extern. Check that type of extern is unifiable with expected type
             var.typ = self.unify(expected_type, &ext.typ).unwrap_or_else(
                 |(e, f)| {
-                    var.ident.pos.error_exit(type_mis_sub(
+                    let err = type_mis_sub(
                         &mut self.type_var_map,
                         expected_type,
                         &ext.typ,
                         &e,
                         &f,
-                    ))
+                    );
+                    self.mismatch_exit(&var.ident.pos, err)
                 },
             );
             var.typ.clone()
@@ -548,32 +706,41 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     ) -> &'c Type<'src> {
         let expected_func_type =
             Type::new_func(self.type_var_gen.gen_tv(), self.type_var_gen.gen_tv());
-        let func_type = self.infer_expr(&mut app.func, &expected_func_type);
+        let func_type =
+            self.infer_expr_expecting(&mut app.func, &expected_func_type, Expectation::Unknown);
         let expected_arg_type = self.type_var_gen.gen_tv();
-        let arg_type = self.infer_expr(&mut app.arg, &expected_arg_type);
+        let arg_type =
+            self.infer_expr_expecting(&mut app.arg, &expected_arg_type, Expectation::Unknown);
         let (func_param_type, func_ret_type) = func_type.get_func().expect(
             "ICE: func_type was not func type in infer_app",
         );
         self.unify(func_param_type, &arg_type).unwrap_or_else(
             |(e, f)| {
-                app.arg.pos().error_exit(type_mis_sub(
+                let err = type_mis_sub(
                     &mut self.type_var_map,
                     func_param_type,
                     &arg_type,
                     &e,
                     &f,
-                ))
+                );
+                app.arg.pos().print_error(&err);
+                app.func.pos().print_note(format!(
+                    "Expected type is given by the parameter of this function of type `{}`",
+                    subst(&func_type, &mut self.type_var_map)
+                ));
+                exit()
             },
         );
         let ret_unification = self.unify(expected_type, func_ret_type).unwrap_or_else(
             |(e, f)| {
-                app.pos.error_exit(type_mis_sub(
+                let err = type_mis_sub(
                     &mut self.type_var_map,
                     expected_type,
                     func_ret_type,
                     &e,
                     &f,
-                ))
+                );
+                self.mismatch_exit(&app.pos, err)
             },
         );
         app.typ = ret_unification;
@@ -587,12 +754,24 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     ) -> &'i Type<'src> {
         self.infer_expr(&mut cond.predicate, &TYPE_BOOL);
         let consequent_type = self.infer_expr(&mut cond.consequent, expected_typ);
-        let alternative_type = self.infer_expr(&mut cond.alternative, expected_typ);
+        // Unless given by something else, the expected type of the alternative
+        // is determined by the consequent
+        let alternative_origin = match self.expectations.last() {
+            Some(&Expectation::Annotation(ref pos)) => Expectation::Annotation(pos.clone()),
+            _ => Expectation::Branch(cond.consequent.pos().clone()),
+        };
+        let alternative_type =
+            self.infer_expr_expecting(&mut cond.alternative, expected_typ, alternative_origin);
         cond.typ = self.unify(&consequent_type, &alternative_type)
             .unwrap_or_else(|_| {
-                cond.pos.error_exit(
-                    ArmsDiffer(consequent_type, alternative_type),
-                )
+                cond.pos.print_error(ArmsDiffer(consequent_type.clone(), alternative_type.clone()));
+                cond.consequent
+                    .pos()
+                    .print_note(format!("Consequent is of type `{}`", consequent_type));
+                cond.alternative
+                    .pos()
+                    .print_note(format!("Alternative is of type `{}`", alternative_type));
+                exit()
             });
         &cond.typ
     }
@@ -611,11 +790,8 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
         let func_type = Type::new_func(param_type.clone(), body_type);
         let (expected_param_type, expected_body_type) = self.unify(expected_type, &func_type)
             .unwrap_or_else(|_| {
-                lam.pos.error_exit(type_mis(
-                    &mut self.type_var_map,
-                    expected_type,
-                    &func_type,
-                ))
+                let err = type_mis(&mut self.type_var_map, expected_type, &func_type);
+                self.mismatch_exit(&lam.pos, err)
             })
             .get_func()
             .map(|(p, b)| (p.clone(), b.clone()))
@@ -638,7 +814,7 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
         // Only allow recursion for functions. Stuff like `let a = a + 1`
         // can't be compiled without laziness.
         if binding.val.first_non_type_ascr_is_lambda() {
-            self.infer_expr(&mut binding.val, &binding.typ);
+            self.infer_expr_expecting(&mut binding.val, &binding.typ, Expectation::Unknown);
         } else {
             let refs_s = if bindings_ids.len() == 1 {
                 "itself".to_string()
@@ -663,7 +839,7 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
     fn infer_recursion_group(&mut self, group: &mut Group<'src>) {
         match *group {
             Group::Uncircular(id, ref mut binding) => {
-                self.infer_expr(&mut binding.val, &binding.typ);
+                self.infer_expr_expecting(&mut binding.val, &binding.typ, Expectation::Unknown);
                 binding.typ = self.generalize(&binding.typ);
                 self.push_var(id, binding.typ.clone());
             }
@@ -747,12 +923,19 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
         expected_type: &Type<'src>,
     ) -> Type<'src> {
         let ascr_pos = expr.pos().clone();
+        let origin = match *expr {
+            Expr::TypeAscript(ref ascr) if ascr.reason.is_some() => {
+                Expectation::SpecialForm(ascr_pos.clone(), ascr.reason.unwrap())
+            }
+            _ => Expectation::Annotation(ascr_pos.clone()),
+        };
         match expr.remove_type_ascription() {
             Some(ascribed) => {
                 let expected_type2 = self.unify(expected_type, &ascribed).unwrap_or_else(|_| {
-                    ascr_pos.error_exit(type_mis(&mut self.type_var_map, expected_type, &ascribed))
+                    let err = type_mis(&mut self.type_var_map, expected_type, &ascribed);
+                    self.mismatch_exit(&ascr_pos, err)
                 });
-                self.infer_expr(expr, &expected_type2)
+                self.infer_expr_expecting(expr, &expected_type2, origin)
             }
             None => panic!("ICE: infer_type_ascript called for non-ascription expr"),
         }
@@ -767,11 +950,8 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
             Type::new_cons(self.type_var_gen.gen_tv(), self.type_var_gen.gen_tv());
         let expected_type2 = self.unify(expected_type, &arbitrary_cons_type)
             .unwrap_or_else(|_| {
-                cons.pos.error_exit(type_mis(
-                    &mut self.type_var_map,
-                    expected_type,
-                    &arbitrary_cons_type,
-                ))
+                let err = type_mis(&mut self.type_var_map, expected_type, &arbitrary_cons_type);
+                self.mismatch_exit(&cons.pos, err)
             });
         let (expected_car_type, expected_cdr_type) = expected_type2.get_cons().expect(
             "ICE: expected type not cons in infer_cons ",
@@ -818,13 +998,10 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
         expected_type: &Type<'src>,
     ) -> &'c Type<'src> {
         let expected_from = self.type_var_gen.gen_tv();
-        self.infer_expr(&mut cast.expr, &expected_from);
+        self.infer_expr_expecting(&mut cast.expr, &expected_from, Expectation::Unknown);
         cast.typ = self.unify(expected_type, &cast.typ).unwrap_or_else(|_| {
-            cast.pos.error_exit(type_mis(
-                &mut self.type_var_map,
-                expected_type,
-                &cast.typ,
-            ))
+            let err = type_mis(&mut self.type_var_map, expected_type, &cast.typ);
+            self.mismatch_exit(&cast.pos, err)
         });
         &cast.typ
     }
@@ -845,7 +1022,9 @@ impl<'a, 'src: 'a> Inferrer<'a, 'src> {
             Expr::Cons(ref mut cons) => self.infer_cons(cons, expected_type).clone(),
             Expr::Car(ref mut c) => self.infer_car(c, expected_type).clone(),
             Expr::Cdr(ref mut c) => self.infer_cdr(c, expected_type).clone(),
-            Expr::Cast(ref mut c) => self.infer_cast(c, expected_type).clone(),            
+            Expr::Cast(ref mut c) => self.infer_cast(c, expected_type).clone(),
+            Expr::SizeOf(ref s) => self.infer_layout_query(&s.pos, expected_type),
+            Expr::AlignOf(ref a) => self.infer_layout_query(&a.pos, expected_type),
         }
     }
 }
@@ -860,9 +1039,72 @@ fn assert_externs_monomorphic(externs: &BTreeMap<&str, ExternDecl>) {
     }
 }
 
-pub fn infer_types(ast: &mut Ast, type_var_generator: &mut TypeVarGen) {
+/// Returns `t` with all newtypes replaced by their underlying types
+///
+/// `visiting` is the chain of newtypes currently being erased, used to detect
+/// newtypes that are defined in terms of themselves
+fn erase_newtypes_in_type<'src>(
+    t: &Type<'src>,
+    newtypes: &BTreeMap<&'src str, NewtypeDef<'src>>,
+    visiting: &mut Vec<&'src str>,
+) -> Type<'src> {
+    let erase_poly = |p: &Poly<'src>, visiting: &mut Vec<&'src str>| Poly {
+        params: p.params.clone(),
+        body: erase_newtypes_in_type(&p.body, newtypes, visiting),
+    };
+    match *t {
+        Type::Const(s, _) => match newtypes.get(s) {
+            Some(nt) => {
+                if visiting.contains(&s) {
+                    nt.pos
+                        .error_exit(format!("Newtype `{}` is defined in terms of itself", s))
+                }
+                visiting.push(s);
+                let erased = erase_newtypes_in_type(&nt.underlying, newtypes, visiting);
+                visiting.pop();
+                erased
+            }
+            None => t.clone(),
+        },
+        Type::App(ref f, ref ts) => {
+            let f2 = match **f {
+                TypeFunc::Const(c) => TypeFunc::Const(c),
+                TypeFunc::Poly(ref p) => TypeFunc::Poly(erase_poly(p, visiting)),
+            };
+            Type::App(
+                Box::new(f2),
+                ts.iter()
+                    .map(|t2| erase_newtypes_in_type(t2, newtypes, visiting))
+                    .collect(),
+            )
+        }
+        Type::Poly(ref p) => Type::Poly(Box::new(erase_poly(p, visiting))),
+        Type::Var(_) => t.clone(),
+    }
+}
+
+/// Replace all newtypes in `ast` with their underlying types
+///
+/// Newtypes are only distinct from their underlying types during type checking.
+/// After erasure, the backend only has to handle core types
+fn erase_newtypes<'src>(ast: &mut Ast<'src>) {
+    let newtypes = &ast.newtypes;
+    let mut erase = |t: &Type<'src>| erase_newtypes_in_type(t, newtypes, &mut Vec::new());
+    for ext in ast.externs.values_mut() {
+        ext.typ = erase(&ext.typ);
+    }
+    for binding in ast.globals.bindings_mut() {
+        map_binding_types(binding, &mut erase);
+    }
+}
+
+/// Type check `ast` and infer the types of all expressions
+///
+/// Unlike `infer_types`, no monomorphization or newtype erasure is done
+pub fn check_types(ast: &mut Ast, type_var_generator: &mut TypeVarGen) {
     assert_externs_monomorphic(&ast.externs);
     let mut inferrer = Inferrer::new(&mut ast.externs, type_var_generator);
+    inferrer.add_newtypes(&ast.newtypes);
     inferrer.infer_bindings(&mut ast.globals);
 
     // Apply all substitutions recursively to get rid of reduntant, indirect type variables
@@ -871,6 +1113,31 @@ pub fn infer_types(ast: &mut Ast, type_var_generator: &mut TypeVarGen) {
         subst_expr(&mut binding.val, &mut inferrer.type_var_map);
     }
 
+    for &(ref tv, ref pos) in &ast.type_holes {
+        let t = subst(&Type::Var(tv.clone()), &mut inferrer.type_var_map);
+        pos.print_note(format!("Found type hole. Inferred type is `{}`", t))
+    }
+}
+
+/// Write the type signatures of all external declarations and global definitions
+/// of the type checked `ast` to `w`
+pub fn write_inferred_types<W: io::Write>(ast: &Ast, w: &mut W) -> io::Result<()> {
+    for ext in ast.externs.values() {
+        writeln!(w, "(extern {} {})", ext.ident, ext.typ)?;
+    }
+    let mut globals = ast.globals.bindings().collect::<Vec<_>>();
+    globals.sort_by_key(|b| b.ident.s);
+    for b in globals {
+        writeln!(w, "(: {} {})", b.ident, b.typ)?;
+    }
+    Ok(())
+}
+
+pub fn infer_types(ast: &mut Ast, type_var_generator: &mut TypeVarGen) {
+    check_types(ast, type_var_generator);
+
     // Map monomorphic instantiations of variables to monomorphization of definitions
     monomorphize_defs_of_insts(&mut ast.globals);
+
+    erase_newtypes(ast);
 }
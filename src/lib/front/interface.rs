@@ -0,0 +1,171 @@
+//! Interfaces of modules
+//!
+//! An interface describes what a module exports: its data types, newtypes, external
+//! declarations, and the type signatures of its global definitions, but none of the
+//! implementation. Interfaces are written as Kvasir source, conventionally to a `.kvi` file,
+//! so that they are readable both by humans and by the compiler itself. The type signatures of
+//! globals are written as `(: NAME TYPE)` items, which are lowered to `Ast::signatures`.
+//!
+//! TODO: Accept an interface in place of the source when importing a module, linking with a
+//!       precompiled object. Requires separate compilation, see the TODO in `main`.
+
+use super::SrcPos;
+use super::ast::*;
+use super::parse::is_std_alias;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Returns the type `t` in source syntax
+///
+/// Type variables are given source names, and a constrained type variable has its constraints
+/// defined at its first occurrence, e.g. `(-> (: a Num) a)`. `names` maps the ids of the
+/// type variables named so far to their names.
+fn source_type(t: &Type, names: &mut BTreeMap<u64, String>) -> String {
+    match *t {
+        Type::Var(ref tv) => {
+            if let Some(name) = names.get(&tv.id) {
+                return name.clone();
+            }
+            let name = match tv.explicit {
+                Some(s) => s.to_string(),
+                None => (0..)
+                    .map(|i| format!("t{}", i))
+                    .find(|s| !names.values().any(|name| name == s))
+                    .unwrap(),
+            };
+            names.insert(tv.id, name.clone());
+            if tv.constrs.is_empty() {
+                name
+            } else {
+                let constrs = tv.constrs.iter().cloned().collect::<Vec<_>>();
+                format!("(: {} {})", name, constrs.join(" "))
+            }
+        }
+        Type::Const(s, _) => s.to_string(),
+        Type::App(ref con, ref args) => {
+            let args_s = args.iter()
+                .map(|arg| format!(" {}", source_type(arg, names)))
+                .collect::<String>();
+            format!("({}{})", con, args_s)
+        }
+        // Type variables are implicitly quantified in source
+        Type::Poly(ref p) => source_type(&p.body, names),
+    }
+}
+
+fn write_variant<W: Write>(v: &AdtVariant, w: &mut W) -> io::Result<()> {
+    match v.discriminant {
+        Some(d) => write!(w, " (= {} {})", v.name, d),
+        None if v.members.is_empty() => write!(w, " {}", v.name),
+        None => {
+            write!(w, " ({}", v.name)?;
+            for member in &v.members {
+                write!(w, " {}", source_type(member, &mut BTreeMap::new()))?;
+            }
+            write!(w, ")")
+        }
+    }
+}
+
+fn write_data<W: Write>(data: &AdtDef, w: &mut W) -> io::Result<()> {
+    write!(w, "(data {}", data.name)?;
+    match data.repr {
+        Some(AdtRepr::C) => write!(w, " (repr C)")?,
+        Some(AdtRepr::Int(ref t)) => write!(w, " (repr {})", t)?,
+        None => (),
+    }
+    for variant in &data.variants {
+        write_variant(variant, w)?;
+    }
+    writeln!(w, ")")
+}
+
+fn write_newtype<W: Write>(nt: &NewtypeDef, w: &mut W) -> io::Result<()> {
+    let underlying = source_type(&nt.underlying, &mut BTreeMap::new());
+    write!(w, "(newtype {} {}", nt.name, underlying)?;
+    if !nt.derives.is_empty() {
+        write!(w, " (deriving")?;
+        for constr in &nt.derives {
+            write!(w, " {}", constr)?;
+        }
+        write!(w, ")")?;
+    }
+    writeln!(w, ")")
+}
+
+/// Write the interface of the module in the file `module` of the type checked `ast` to `w`
+///
+/// Definitions of imported modules are not included. Docstrings of global definitions are
+/// written as doc comments.
+pub fn write_interface<W: Write>(ast: &Ast, module: &Path, w: &mut W) -> io::Result<()> {
+    let in_module = |pos: &SrcPos| pos.filename() == module;
+    writeln!(w, ";;; Interface of module `{}`", module.display())?;
+    for data in ast.datas.values().filter(|d| in_module(&d.pos)) {
+        write_data(data, w)?;
+    }
+    for nt in ast.newtypes.values().filter(|nt| in_module(&nt.pos)) {
+        write_newtype(nt, w)?;
+    }
+    for ext in ast.externs.values().filter(|ext| in_module(&ext.pos)) {
+        let typ = source_type(&ext.typ, &mut BTreeMap::new());
+        writeln!(w, "(extern {} {})", ext.ident, typ)?;
+    }
+    // The constructors of newtypes are generated from the newtype definitions, and the aliases
+    // of the standard library from the definitions they alias
+    let mut globals = ast.globals
+        .bindings()
+        .filter(|b| in_module(&b.pos) && !ast.newtypes.contains_key(b.ident.s))
+        .filter(|b| !is_std_alias(b.ident.s))
+        .collect::<Vec<_>>();
+    globals.sort_by_key(|b| b.ident.s);
+    for b in globals {
+        if let Some(doc) = ast.docs.get(b.ident.s) {
+            for line in doc.lines() {
+                writeln!(w, ";;? {}", line)?;
+            }
+        }
+        writeln!(w, "(: {} {})", b.ident, source_type(&b.typ, &mut BTreeMap::new()))?;
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use lib::collections::AddMap;
+    use lib::front::TypeVarGen;
+    use lib::front::inference::check_types;
+    use lib::front::parse::lower_src;
+    use std::path::Path;
+    use super::write_interface;
+
+    #[test]
+    fn test_interface_round_trip() {
+        let src = "(data Shape (Circle Float64) (Rect Float64 Float64))\n\
+                   (newtype Meter Float64)\n\
+                   (extern sqrt (-> Float64 Float64))\n\
+                   (define (id x) \"The identity function\" x)\n\
+                   (define (twice f x) (f (f x)))\n\
+                   (define one 1)\n\
+                   (define (scale s) (car (cons s one)))";
+        let sources = AddMap::new();
+        let path = Path::new("test.kvs");
+        let mut tvg = TypeVarGen::new(0);
+        let mut ast = lower_src(src, &sources, &mut tvg).expect("Failed to parse program");
+        check_types(&mut ast, &mut tvg);
+        let mut interface = Vec::new();
+        write_interface(&ast, path, &mut interface).unwrap();
+        let interface = String::from_utf8(interface).unwrap();
+
+        let lowered = lower_src(&interface, &sources, &mut tvg)
+            .unwrap_or_else(|_| panic!("Failed to lower interface\n{}", interface));
+        assert!(lowered.datas.contains_key("Shape"));
+        assert!(lowered.newtypes.contains_key("Meter"));
+        assert!(lowered.externs.contains_key("sqrt"));
+        assert_eq!(
+            lowered.signatures.keys().cloned().collect::<Vec<_>>(),
+            vec!["id", "one", "scale", "twice"]
+        );
+        assert_eq!(lowered.globals.bindings().count(), 1, "Only the newtype constructor");
+    }
+}
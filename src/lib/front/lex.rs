@@ -23,6 +23,7 @@ enum LexErr {
     UntermStr,
     UntermRawStr,
     InvalidRawStrDelim(char),
+    InvalidHexBytes,
     InvalidNum,
     InvalidIdent,
     UndelimItem,
@@ -41,6 +42,10 @@ impl fmt::Display for LexErr {
                  allowed",
                 c
             ),
+            InvalidHexBytes => write!(
+                f,
+                "Invalid byte string literal. Expected an even number of hexadecimal digits"
+            ),
             InvalidNum => write!(f, "Invalid numeric literal"),
             InvalidIdent => write!(f, "Invalid ident"),
             UndelimItem => write!(f, "Undelimited item"),
@@ -70,14 +75,26 @@ enum Token<'src> {
     LParen,
     /// Right parenthesis `)`
     RParen,
+    /// Left brace `{`
+    LBrace,
+    /// Right brace `}`
+    RBrace,
     /// Identifier
     Ident(&'src str),
     /// Numeric literal
     Num(&'src str),
     /// String literal
     Str(Cow<'src, str>),
+    /// Byte string literal, e.g. `#x"DEADBEEF"`, as its hexadecimal digits
+    HexBytes(Cow<'src, str>),
     /// Quote (`'`)
     Quote,
+    /// Quasiquote (`` ` ``)
+    Quasiquote,
+    /// Unquote (`,`)
+    Unquote,
+    /// Unquote-splicing (`,@`)
+    UnquoteSplicing,
 }
 
 /// Tokenize the string literal in `src` at `start`.
@@ -109,6 +126,23 @@ fn tokenize_str_lit<'s>(filename: &'s Path, src: &'s str, start: usize) -> (Toke
     SrcPos::new_pos(filename, src, start).error_exit(UntermStr)
 }
 
+/// Tokenize the hexadecimal byte string literal in `src` at `start`, e.g. `#x"DEADBEEF"`.
+/// Return the hexadecimal digits as a `Token` and the length of the literal in the source.
+fn tokenize_hex_bytes_lit<'s>(
+    filename: &'s Path,
+    src: &'s str,
+    start: usize,
+) -> (Token<'s>, usize) {
+    let (digits, len) = match tokenize_str_lit(filename, src, start + 2) {
+        (Token::Str(digits), len) => (digits, len + 2),
+        _ => unreachable!("ICE: String literal tokenized as other token"),
+    };
+    if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_digit(16)) {
+        SrcPos::new_interval(filename, src, start, start + len).error_exit(InvalidHexBytes)
+    }
+    (Token::HexBytes(digits), len)
+}
+
 /// Tokenize the raw string literal in `src` at `start`.
 /// Return the literal as a `Token` and it's length, including delimiting characters, in the source.
 fn tokenize_raw_str_lit<'s>(filename: &'s Path, src: &'s str, start: usize) -> (Token<'s>, usize) {
@@ -237,9 +271,17 @@ impl<'s> Iterator for Tokens<'s> {
                     continue;
                 }
                 '\'' => (Token::Quote, 1),
+                '`' => (Token::Quasiquote, 1),
+                ',' if self.src[i + 1..].starts_with('@') => (Token::UnquoteSplicing, 2),
+                ',' => (Token::Unquote, 1),
                 '(' | '[' => (Token::LParen, 1),
                 ')' | ']' => (Token::RParen, 1),
+                '{' => (Token::LBrace, 1),
+                '}' => (Token::RBrace, 1),
                 '"' => tokenize_str_lit(self.filename, self.src, i),
+                '#' if self.src[i + 1..].starts_with("x\"") => {
+                    tokenize_hex_bytes_lit(self.filename, self.src, i)
+                }
                 'r' if self.src[i + 1..].starts_with(|c: char| c == '"' || c == '#') => {
                     tokenize_raw_str_lit(self.filename, self.src, i)
                 }
@@ -271,17 +313,38 @@ pub enum CST<'s> {
     Num(&'s str, SrcPos<'s>),
     /// A string literal.
     Str(Cow<'s, str>, SrcPos<'s>),
+    /// A list delimited by braces, `{...}`. Reserved for stuff like record literals.
+    Braces(Vec<CST<'s>>, SrcPos<'s>),
 }
 impl<'s> CST<'s> {
     pub fn pos(&self) -> &SrcPos<'s> {
         match *self {
             CST::SExpr(_, ref p)
+            | CST::Braces(_, ref p)
             | CST::Ident(_, ref p)
             | CST::Num(_, ref p)
             | CST::Str(_, ref p) => p,
         }
     }
 
+    /// Construct the syntax tree of a reader shortcut, e.g. `'x`, from the tokens following
+    ///
+    /// The result is the canonical form, e.g. `(quote x)`
+    fn from_prefixed(form: &'static str, pos: SrcPos<'s>, nexts: &mut Tokens<'s>) -> Self {
+        CST::SExpr(
+            vec![
+                CST::Ident(form, pos.clone()),
+                CST::from_token(
+                    nexts
+                        .next()
+                        .unwrap_or_else(|| pos.error_exit(Unexpected(form))),
+                    nexts,
+                ),
+            ],
+            pos,
+        )
+    }
+
     /// Construct a new syntax tree from a token with a position, and the tokens following
     fn from_token((token, mut pos): (Token<'s>, SrcPos<'s>), nexts: &mut Tokens<'s>) -> Self {
         match token {
@@ -290,25 +353,94 @@ impl<'s> CST<'s> {
                 pos.end = end;
                 CST::SExpr(list, pos)
             }
+            Token::LBrace => {
+                let (list, end) = tokens_to_trees_until(nexts, Some((pos.clone(), &Token::RBrace)));
+                pos.end = end;
+                CST::Braces(list, pos)
+            }
             Token::Ident(ident) => CST::Ident(ident, pos),
             Token::Num(num) => CST::Num(num, pos),
             Token::Str(s) => CST::Str(s, pos),
-            Token::Quote => CST::SExpr(
+            // Desugared to a call of the standard library, as there's no literal of bytes
+            Token::HexBytes(digits) => CST::SExpr(
                 vec![
-                    CST::Ident("quote", pos.clone()),
-                    CST::from_token(
-                        nexts
-                            .next()
-                            .unwrap_or_else(|| pos.error_exit(Unexpected("quote"))),
-                        nexts,
-                    ),
+                    CST::Ident("bytes-from-hex", pos.clone()),
+                    CST::Str(digits, pos.clone()),
                 ],
                 pos,
             ),
+            Token::Quote => CST::from_prefixed("quote", pos, nexts),
+            Token::Quasiquote => CST::from_prefixed("quasiquote", pos, nexts),
+            Token::Unquote => CST::from_prefixed("unquote", pos, nexts),
+            Token::UnquoteSplicing => CST::from_prefixed("unquote-splicing", pos, nexts),
             _ => pos.error_exit(Unexpected("token")),
         }
     }
+
+    /// Returns the tree printed with at most `max_depth` levels of nesting, and at most
+    /// about `max_width` characters, with the parts that don't fit elided as `...`
+    ///
+    /// Used in the edit scripts of `--diff`, see `cst_diff::write_edit_script`, where huge
+    /// inserted or deleted trees would be unreadable if printed in full
+    pub fn elided(&self, max_depth: usize, max_width: usize) -> String {
+        let (mut out, mut width) = (String::new(), max_width);
+        self.write_elided(&mut out, max_depth, &mut width);
+        out
+    }
+
+    /// Append the elided rendering of the tree to `out`, consuming `width`
+    ///
+    /// Returns whether the tree was printed without running out of width
+    fn write_elided(&self, out: &mut String, depth: usize, width: &mut usize) -> bool {
+        let (open, close, items) = match *self {
+            CST::SExpr(ref items, _) => ('(', ')', items),
+            CST::Braces(ref items, _) => ('{', '}', items),
+            _ => {
+                let s = self.to_string();
+                let n = s.chars().count();
+                if n <= *width {
+                    out.push_str(&s);
+                    *width -= n;
+                    return true;
+                } else {
+                    out.push_str("...");
+                    *width = 0;
+                    return false;
+                }
+            }
+        };
+        out.push(open);
+        *width = width.saturating_sub(2);
+        let mut complete = true;
+        if depth == 0 && !items.is_empty() {
+            out.push_str("...");
+        } else {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    if *width == 0 {
+                        out.push_str(" ...");
+                        complete = false;
+                        break;
+                    }
+                    out.push(' ');
+                    *width -= 1;
+                }
+                if !item.write_elided(out, depth - 1, width) {
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        out.push(close);
+        complete
+    }
 }
+
+/// Maximum nesting depth of syntax trees printed in diagnostics
+pub const DIAG_MAX_DEPTH: usize = 4;
+/// Maximum width in characters of syntax trees printed in diagnostics
+pub const DIAG_MAX_WIDTH: usize = 60;
+
 impl<'s> fmt::Display for CST<'s> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -322,6 +454,14 @@ impl<'s> fmt::Display for CST<'s> {
                     .intersperse(" ".into())
                     .collect::<String>()
             ),
+            CST::Braces(ref v, _) => write!(
+                f,
+                "{{{}}}",
+                v.iter()
+                    .map(|e| e.to_string())
+                    .intersperse(" ".into())
+                    .collect::<String>()
+            ),
         }
     }
 }
@@ -353,11 +493,58 @@ fn tokens_to_trees_until<'s>(
 }
 
 /// Lex the source code as a Concrete Syntax Tree
-fn lex_src<'s>(filename: &'s Path, src: &'s str) -> Vec<CST<'s>> {
+pub fn lex_src<'s>(filename: &'s Path, src: &'s str) -> Vec<CST<'s>> {
     tokens_to_trees_until(&mut Tokens::new(filename, src), None).0
 }
 
+/// Returns whether the info string of a fenced code block in Markdown marks Kvasir code
+fn is_kvasir_fence_info(info: &str) -> bool {
+    match info.split_whitespace().next() {
+        Some("kvasir") | Some("kvs") => true,
+        _ => false,
+    }
+}
+
+/// Extract the Kvasir code of a literate Markdown document
+///
+/// Only the contents of fenced code blocks with the info string `kvasir` or `kvs` are kept,
+/// e.g. the contents of a block starting with "```kvasir". All other text is replaced with
+/// whitespace, preserving line breaks, so that positions in the code are unchanged.
+fn literate_code(src: &str) -> String {
+    let mut code = String::with_capacity(src.len());
+    let mut in_code_block = false;
+    let mut in_other_block = false;
+    let blank = |line: &str| line.chars().map(|_| ' ').collect::<String>();
+    for line in src.split_terminator('\n') {
+        let trimmed = line.trim_left();
+        let is_fence = trimmed.starts_with("```");
+        if is_fence && in_code_block {
+            in_code_block = false;
+            code.push_str(&blank(line))
+        } else if is_fence && in_other_block {
+            in_other_block = false;
+            code.push_str(&blank(line))
+        } else if is_fence {
+            if is_kvasir_fence_info(&trimmed[3..]) {
+                in_code_block = true
+            } else {
+                in_other_block = true
+            }
+            code.push_str(&blank(line))
+        } else if in_code_block {
+            code.push_str(line)
+        } else {
+            code.push_str(&blank(line))
+        }
+        code.push('\n')
+    }
+    code
+}
+
 /// Lex the source code of the file `filename`
+///
+/// If `filename` has the extension `md`, it's treated as a literate Markdown document,
+/// and only the code of its Kvasir code blocks is lexed. See `literate_code`
 pub fn lex_file<'s>(
     filename: CanonPathBuf,
     sources: &'s AddMap<CanonPathBuf, String>,
@@ -373,6 +560,9 @@ pub fn lex_file<'s>(
             "Reading contents of `{}` failed",
             filename.path().display()
         ));
+    if filename.path().extension().map_or(false, |ext| ext == "md") {
+        src_code = literate_code(&src_code)
+    }
     let (filename_ref, src_ref) = sources.add(filename, src_code);
     lex_src(filename_ref.path(), src_ref)
 }
@@ -0,0 +1,688 @@
+//! Lints, checks for code that is valid but likely wrong or unidiomatic
+//!
+//! A lint implements `Lint`, and checks the syntax trees and/or the AST of a module,
+//! reporting its findings. Lints are registered with a `Linter`, which runs them and reports
+//! the findings according to the level of each lint. Levels can be set on the command line
+//! with `-A`, `-W`, and `-D`, to allow, warn about, or deny a lint.
+//!
+//! TODO: Lints written in Kvasir, as functions from a quoted syntax tree to a list of
+//!       messages with positions, loaded from the project and run by the interpreter.
+//!       Needs a way to call a function of an interpreted program from the compiler with
+//!       a value built from a `CST`, and limits on the time the interpreter may run.
+
+use super::SrcPos;
+use super::ast::*;
+use super::lex::CST;
+use std::collections::BTreeSet;
+
+/// The level of a lint, which decides how its findings are reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// The findings are ignored
+    Allow,
+    /// The findings are reported as warnings
+    Warn,
+    /// The findings are reported as errors
+    Deny,
+}
+
+/// A finding of a lint, a piece of suspicious code
+pub struct Finding<'src> {
+    pub pos: SrcPos<'src>,
+    pub msg: String,
+}
+
+/// A lint. See the module documentation
+pub trait Lint {
+    /// The name of the lint, e.g. `literal-head`, used to set its level
+    fn name(&self) -> &'static str;
+
+    /// A short description of what the lint checks
+    fn description(&self) -> &'static str;
+
+    /// The level of the lint unless set otherwise
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    /// Check the syntax trees of a module
+    fn check_csts<'src>(&self, _csts: &[CST<'src>], _findings: &mut Vec<Finding<'src>>) {}
+
+    /// Check the AST of a program, before type checking
+    fn check_ast<'src>(&self, _ast: &Ast<'src>, _findings: &mut Vec<Finding<'src>>) {}
+}
+
+/// Lint for s-expressions with a literal as head, e.g. `(1 2 3)`, which were likely meant
+/// to be quoted, or have an operator missing
+struct LiteralHead;
+
+impl LiteralHead {
+    fn check<'src>(&self, cst: &CST<'src>, findings: &mut Vec<Finding<'src>>) {
+        match *cst {
+            // Quoted data is not evaluated, so anything goes
+            CST::SExpr(ref items, _) if items.first().map_or(false, is_quote) => (),
+            CST::SExpr(ref items, _) => {
+                match items.first() {
+                    Some(&CST::Num(_, ref pos)) | Some(&CST::Str(_, ref pos)) => {
+                        findings.push(Finding {
+                            pos: pos.clone(),
+                            msg: "Literal in function position. \
+                                  Did you mean to quote the list, e.g. `'(1 2 3)`?"
+                                .to_string(),
+                        })
+                    }
+                    _ => (),
+                }
+                for item in items {
+                    self.check(item, findings)
+                }
+            }
+            CST::Braces(ref items, _) => for item in items {
+                self.check(item, findings)
+            },
+            _ => (),
+        }
+    }
+}
+
+fn is_quote(cst: &CST) -> bool {
+    match *cst {
+        CST::Ident("quote", _) => true,
+        _ => false,
+    }
+}
+
+impl Lint for LiteralHead {
+    fn name(&self) -> &'static str {
+        "literal-head"
+    }
+
+    fn description(&self) -> &'static str {
+        "s-expressions with a number or string literal in function position"
+    }
+
+    fn check_csts<'src>(&self, csts: &[CST<'src>], findings: &mut Vec<Finding<'src>>) {
+        for cst in csts {
+            self.check(cst, findings)
+        }
+    }
+}
+
+/// Lint for local variables, i.e. `let` bindings and lambda parameters, that shadow
+/// a global definition or external declaration
+struct ShadowedGlobal;
+
+impl ShadowedGlobal {
+    fn check_ident<'src>(
+        &self,
+        globals: &BTreeSet<&str>,
+        id: &Ident<'src>,
+        findings: &mut Vec<Finding<'src>>,
+    ) {
+        if globals.contains(id.s) {
+            findings.push(Finding {
+                pos: id.pos.clone(),
+                msg: format!("Local variable `{}` shadows a global of the same name", id.s),
+            })
+        }
+    }
+
+    fn check_expr<'src>(
+        &self,
+        globals: &BTreeSet<&str>,
+        e: &Expr<'src>,
+        findings: &mut Vec<Finding<'src>>,
+    ) {
+        match *e {
+            Expr::App(ref app) => {
+                self.check_expr(globals, &app.func, findings);
+                self.check_expr(globals, &app.arg, findings);
+            }
+            Expr::If(ref cond) => {
+                self.check_expr(globals, &cond.predicate, findings);
+                self.check_expr(globals, &cond.consequent, findings);
+                self.check_expr(globals, &cond.alternative, findings);
+            }
+            Expr::Lambda(ref lam) => {
+                self.check_ident(globals, &lam.param_ident, findings);
+                self.check_expr(globals, &lam.body, findings);
+            }
+            Expr::Let(ref l) => {
+                for b in l.bindings.bindings() {
+                    self.check_ident(globals, &b.ident, findings);
+                    self.check_expr(globals, &b.val, findings);
+                }
+                self.check_expr(globals, &l.body, findings);
+            }
+            Expr::TypeAscript(ref a) => self.check_expr(globals, &a.expr, findings),
+            Expr::Cons(ref c) => {
+                self.check_expr(globals, &c.car, findings);
+                self.check_expr(globals, &c.cdr, findings);
+            }
+            Expr::Car(ref c) => self.check_expr(globals, &c.expr, findings),
+            Expr::Cdr(ref c) => self.check_expr(globals, &c.expr, findings),
+            Expr::Cast(ref c) => self.check_expr(globals, &c.expr, findings),
+            Expr::Variable(_)
+            | Expr::Nil(_)
+            | Expr::NumLit(_)
+            | Expr::StrLit(_)
+            | Expr::Bool(_)
+            | Expr::SizeOf(_)
+            | Expr::AlignOf(_) => (),
+        }
+    }
+}
+
+impl Lint for ShadowedGlobal {
+    fn name(&self) -> &'static str {
+        "shadowed-global"
+    }
+
+    fn description(&self) -> &'static str {
+        "local variables that shadow a global definition or external declaration"
+    }
+
+    fn check_ast<'src>(&self, ast: &Ast<'src>, findings: &mut Vec<Finding<'src>>) {
+        let globals = ast.globals
+            .bindings()
+            .map(|b| b.ident.s)
+            .chain(ast.externs.keys().cloned())
+            .collect::<BTreeSet<_>>();
+        for b in ast.globals.bindings() {
+            self.check_expr(&globals, &b.val, findings)
+        }
+    }
+}
+
+/// The least literal argument of a call that `DeepRecursion` considers a deep recursion
+const DEEP_RECURSION_THRESHOLD: i64 = 100_000;
+
+/// Returns the function and arguments of the possibly curried application `e`
+fn flatten_app<'e, 'src>(e: &'e Expr<'src>) -> (&'e Expr<'src>, Vec<&'e Expr<'src>>) {
+    let mut args = Vec::new();
+    let mut func = e;
+    while let Expr::App(ref app) = *func {
+        args.push(&app.arg);
+        func = &app.func;
+    }
+    args.reverse();
+    (func, args)
+}
+
+fn is_var(e: &Expr, name: &str) -> bool {
+    match *e {
+        Expr::Variable(ref var) => var.ident.s == name,
+        _ => false,
+    }
+}
+
+/// Returns whether `e`, which is in tail position if `tail`, contains a call of `name`
+/// that is not in tail position
+fn calls_outside_tail(name: &str, e: &Expr, tail: bool) -> bool {
+    match *e {
+        Expr::App(_) => {
+            let (func, args) = flatten_app(e);
+            (is_var(func, name) && !tail) || calls_outside_tail(name, func, false)
+                || args.iter().any(|arg| calls_outside_tail(name, arg, false))
+        }
+        Expr::If(ref cond) => {
+            calls_outside_tail(name, &cond.predicate, false)
+                || calls_outside_tail(name, &cond.consequent, tail)
+                || calls_outside_tail(name, &cond.alternative, tail)
+        }
+        // A call in tail position of a lambda is a tail call of the lambda itself
+        Expr::Lambda(ref lam) => calls_outside_tail(name, &lam.body, true),
+        Expr::Let(ref l) => {
+            l.bindings
+                .bindings()
+                .any(|b| calls_outside_tail(name, &b.val, false))
+                || calls_outside_tail(name, &l.body, tail)
+        }
+        Expr::TypeAscript(ref a) => calls_outside_tail(name, &a.expr, tail),
+        Expr::Cons(ref c) => {
+            calls_outside_tail(name, &c.car, false) || calls_outside_tail(name, &c.cdr, false)
+        }
+        Expr::Car(ref c) => calls_outside_tail(name, &c.expr, false),
+        Expr::Cdr(ref c) => calls_outside_tail(name, &c.expr, false),
+        Expr::Cast(ref c) => calls_outside_tail(name, &c.expr, false),
+        Expr::Variable(_)
+        | Expr::Nil(_)
+        | Expr::NumLit(_)
+        | Expr::StrLit(_)
+        | Expr::Bool(_)
+        | Expr::SizeOf(_)
+        | Expr::AlignOf(_) => false,
+    }
+}
+
+/// Lint for calls with a large literal argument of functions that call themselves outside of
+/// tail position, e.g. `(sum-to 1000000)`, where each level of recursion takes a stack frame
+///
+/// The literal is taken as an estimate of the depth of the recursion, which is a heuristic,
+/// but catches the common case of recursing on a counter.
+struct DeepRecursion;
+
+impl DeepRecursion {
+    fn check_expr<'src>(
+        &self,
+        recursive: &BTreeSet<&str>,
+        e: &Expr<'src>,
+        findings: &mut Vec<Finding<'src>>,
+    ) {
+        match *e {
+            Expr::App(ref app) => {
+                let (func, args) = flatten_app(e);
+                let callee = match *func {
+                    Expr::Variable(ref var) if recursive.contains(var.ident.s) => {
+                        Some(var.ident.s)
+                    }
+                    _ => None,
+                };
+                let deepest = args.iter()
+                    .filter_map(|arg| match **arg {
+                        Expr::NumLit(ref lit) => lit.lit.parse::<i64>().ok(),
+                        _ => None,
+                    })
+                    .max();
+                match (callee, deepest) {
+                    (Some(f), Some(n)) if n >= DEEP_RECURSION_THRESHOLD => {
+                        findings.push(Finding {
+                            pos: app.pos.clone(),
+                            msg: format!(
+                                "`{}` calls itself outside of tail position, so calling it \
+                                 with `{}` may overflow the stack. Consider making it tail \
+                                 recursive, e.g. with an accumulator parameter",
+                                f, n
+                            ),
+                        })
+                    }
+                    _ => (),
+                }
+                self.check_expr(recursive, func, findings);
+                for arg in args {
+                    self.check_expr(recursive, arg, findings)
+                }
+            }
+            Expr::If(ref cond) => {
+                self.check_expr(recursive, &cond.predicate, findings);
+                self.check_expr(recursive, &cond.consequent, findings);
+                self.check_expr(recursive, &cond.alternative, findings);
+            }
+            Expr::Lambda(ref lam) => self.check_expr(recursive, &lam.body, findings),
+            Expr::Let(ref l) => {
+                for b in l.bindings.bindings() {
+                    self.check_expr(recursive, &b.val, findings)
+                }
+                self.check_expr(recursive, &l.body, findings)
+            }
+            Expr::TypeAscript(ref a) => self.check_expr(recursive, &a.expr, findings),
+            Expr::Cons(ref c) => {
+                self.check_expr(recursive, &c.car, findings);
+                self.check_expr(recursive, &c.cdr, findings);
+            }
+            Expr::Car(ref c) => self.check_expr(recursive, &c.expr, findings),
+            Expr::Cdr(ref c) => self.check_expr(recursive, &c.expr, findings),
+            Expr::Cast(ref c) => self.check_expr(recursive, &c.expr, findings),
+            Expr::Variable(_)
+            | Expr::Nil(_)
+            | Expr::NumLit(_)
+            | Expr::StrLit(_)
+            | Expr::Bool(_)
+            | Expr::SizeOf(_)
+            | Expr::AlignOf(_) => (),
+        }
+    }
+}
+
+impl Lint for DeepRecursion {
+    fn name(&self) -> &'static str {
+        "deep-recursion"
+    }
+
+    fn description(&self) -> &'static str {
+        "calls with a large literal argument of functions that are not tail recursive"
+    }
+
+    fn check_ast<'src>(&self, ast: &Ast<'src>, findings: &mut Vec<Finding<'src>>) {
+        let non_tail_recursive = ast.globals
+            .bindings()
+            .filter(|b| calls_outside_tail(b.ident.s, &b.val, true))
+            .map(|b| b.ident.s)
+            .collect::<BTreeSet<_>>();
+        if non_tail_recursive.is_empty() {
+            return;
+        }
+        for b in ast.globals.bindings() {
+            self.check_expr(&non_tail_recursive, &b.val, findings)
+        }
+    }
+}
+
+/// The maximum count of a bounded repetition in a regex, `RE_DUP_MAX` of POSIX
+const REGEX_DUP_MAX: u32 = 0x7fff;
+
+const REGEX_CHAR_CLASSES: &[&str] = &[
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+/// Parse the bracket expression, e.g. `[^a-z]`, of a regex, starting after the `[` at `i`
+///
+/// Returns the index after the closing `]`
+fn parse_regex_bracket(cs: &[char], mut i: usize) -> Result<usize, String> {
+    if cs.get(i) == Some(&'^') {
+        i += 1
+    }
+    // A `]` first in the list is literal
+    if cs.get(i) == Some(&']') {
+        i += 1
+    }
+    loop {
+        let start = match cs.get(i) {
+            None => return Err("Unterminated bracket expression".to_string()),
+            Some(&']') => return Ok(i + 1),
+            Some(&'[') if cs.get(i + 1).map_or(false, |c| ":.=".contains(*c)) => {
+                let delim = cs[i + 1];
+                let len = cs[i + 2..]
+                    .windows(2)
+                    .position(|w| w[0] == delim && w[1] == ']')
+                    .ok_or(format!("Unterminated `[{}` in bracket expression", delim))?;
+                let name = cs[i + 2..i + 2 + len].iter().collect::<String>();
+                if delim == ':' && !REGEX_CHAR_CLASSES.contains(&name.as_str()) {
+                    return Err(format!("Invalid character class `[:{}:]`", name));
+                }
+                i += len + 4;
+                continue;
+            }
+            Some(&c) => c,
+        };
+        match (cs.get(i + 1), cs.get(i + 2)) {
+            (Some(&'-'), Some(&end)) if end != ']' => {
+                if end < start {
+                    return Err(format!("Invalid range `{}-{}`", start, end));
+                }
+                i += 3
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Parse the bound of a repetition, e.g. `{2,5}`, of a regex, starting after the `{` at `i`
+///
+/// Returns the index after the closing `}`
+fn parse_regex_bound(cs: &[char], i: usize) -> Result<usize, String> {
+    let len = cs[i..]
+        .iter()
+        .position(|&c| c == '}')
+        .ok_or("Unterminated `{` of repetition".to_string())?;
+    let content = cs[i..i + len].iter().collect::<String>();
+    let invalid = || format!("Invalid repetition `{{{}}}`", content);
+    let mut bounds = content.splitn(2, ',');
+    let min = bounds.next().unwrap_or("");
+    let max = bounds.next();
+    let parse = |s: &str| -> Result<Option<u32>, String> {
+        if s.is_empty() {
+            Ok(None)
+        } else {
+            match s.parse() {
+                Ok(n) if n <= REGEX_DUP_MAX => Ok(Some(n)),
+                _ => Err(invalid()),
+            }
+        }
+    };
+    match (parse(min)?, max.map_or(Ok(None), |s| parse(s))?) {
+        (None, None) if max.is_none() => Err(invalid()),
+        (Some(n), Some(m)) if n > m => Err(invalid()),
+        _ => Ok(i + len + 1),
+    }
+}
+
+/// Returns the error of the POSIX extended regular expression `pattern`, if it's invalid
+///
+/// Follows the validation of `regcomp`, which literal patterns are compiled with at runtime
+fn regex_error(pattern: &str) -> Option<String> {
+    let cs = pattern.chars().collect::<Vec<_>>();
+    let mut open_groups = 0;
+    // Whether there's something before that a repetition could apply to
+    let mut repeatable = false;
+    let mut i = 0;
+    while i < cs.len() {
+        let c = cs[i];
+        i += 1;
+        let res = match c {
+            '\\' if i == cs.len() => Err("Trailing backslash".to_string()),
+            '\\' => Ok(i + 1),
+            '[' => parse_regex_bracket(&cs, i),
+            '*' | '+' | '?' | '{' if !repeatable => {
+                Err(format!("Nothing to repeat before `{}`", c))
+            }
+            '{' => parse_regex_bound(&cs, i),
+            _ => Ok(i),
+        };
+        i = match res {
+            Ok(next) => next,
+            Err(msg) => return Some(msg),
+        };
+        match c {
+            '(' => open_groups += 1,
+            // An unmatched `)` is an ordinary character
+            ')' if open_groups > 0 => open_groups -= 1,
+            _ => (),
+        }
+        repeatable = match c {
+            '(' | '|' | '^' | '$' => false,
+            _ => true,
+        };
+    }
+    if open_groups > 0 {
+        Some("Unmatched `(`".to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the number of capture groups of the valid POSIX extended regular expression
+/// `pattern`, like `re_nsub` of `regcomp`
+pub fn regex_group_count(pattern: &str) -> usize {
+    let cs = pattern.chars().collect::<Vec<_>>();
+    let mut count = 0;
+    let mut i = 0;
+    while i < cs.len() {
+        i = match cs[i] {
+            '\\' => i + 2,
+            '[' => parse_regex_bracket(&cs, i + 1).unwrap_or(cs.len()),
+            '(' => {
+                count += 1;
+                i + 1
+            }
+            _ => i + 1,
+        }
+    }
+    count
+}
+
+/// Lint for literal patterns given to `regex` of the standard library, that are invalid
+/// and would make the program exit at runtime
+struct InvalidRegex;
+
+impl InvalidRegex {
+    fn check<'src>(&self, cst: &CST<'src>, findings: &mut Vec<Finding<'src>>) {
+        match *cst {
+            CST::SExpr(ref items, _) if items.first().map_or(false, is_quote) => (),
+            CST::SExpr(ref items, _) | CST::Braces(ref items, _) => {
+                match (items.len(), items.first(), items.get(1)) {
+                    (2, Some(&CST::Ident("regex", _)), Some(&CST::Str(ref pattern, ref pos))) => {
+                        if let Some(err) = regex_error(pattern) {
+                            findings.push(Finding {
+                                pos: pos.clone(),
+                                msg: format!("Invalid regex. {}", err),
+                            })
+                        }
+                    }
+                    _ => (),
+                }
+                for item in items {
+                    self.check(item, findings)
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Lint for InvalidRegex {
+    fn name(&self) -> &'static str {
+        "invalid-regex"
+    }
+
+    fn description(&self) -> &'static str {
+        "invalid literal patterns of regular expressions"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Deny
+    }
+
+    fn check_csts<'src>(&self, csts: &[CST<'src>], findings: &mut Vec<Finding<'src>>) {
+        for cst in csts {
+            self.check(cst, findings)
+        }
+    }
+}
+
+/// A set of lints with levels, that can be run over a program
+pub struct Linter {
+    lints: Vec<(Box<Lint>, Level)>,
+}
+
+impl Linter {
+    /// Returns a linter of the builtin lints, at their default levels
+    pub fn new() -> Self {
+        let mut linter = Linter { lints: Vec::new() };
+        linter.register(Box::new(LiteralHead));
+        linter.register(Box::new(ShadowedGlobal));
+        linter.register(Box::new(DeepRecursion));
+        linter.register(Box::new(InvalidRegex));
+        linter
+    }
+
+    /// Add the lint `lint`, at its default level
+    pub fn register(&mut self, lint: Box<Lint>) {
+        let level = lint.default_level();
+        self.lints.push((lint, level))
+    }
+
+    /// Set the level of the lint named `name`
+    ///
+    /// Returns an error if there is no such lint
+    pub fn set_level(&mut self, name: &str, level: Level) -> Result<(), String> {
+        match self.lints.iter_mut().find(|entry| entry.0.name() == name) {
+            Some(entry) => {
+                entry.1 = level;
+                Ok(())
+            }
+            None => Err(format!("Unknown lint `{}`", name)),
+        }
+    }
+
+    /// Returns the names and descriptions of all lints
+    pub fn lints(&self) -> Vec<(&'static str, &'static str)> {
+        self.lints
+            .iter()
+            .map(|&(ref lint, _)| (lint.name(), lint.description()))
+            .collect()
+    }
+
+    /// Run all lints that are not allowed over the syntax trees `csts` of the main module
+    /// and the AST `ast` of the program, and report the findings
+    ///
+    /// Returns whether there were no findings of denied lints
+    pub fn run<'src>(&self, csts: &[CST<'src>], ast: &Ast<'src>) -> bool {
+        let mut ok = true;
+        for &(ref lint, level) in &self.lints {
+            if level == Level::Allow {
+                continue;
+            }
+            let mut findings = Vec::new();
+            lint.check_csts(csts, &mut findings);
+            lint.check_ast(ast, &mut findings);
+            for finding in findings {
+                let msg = format!("{} [{}]", finding.msg, lint.name());
+                if level == Level::Deny {
+                    finding.pos.print_error(msg);
+                    ok = false
+                } else {
+                    finding.pos.print_warn(msg)
+                }
+            }
+        }
+        ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{regex_error, regex_group_count};
+
+    /// Patterns, and whether they are valid
+    const REGEXES: &[(&str, bool)] = &[
+        ("abc", true),
+        ("a|b", true),
+        ("(ab)*c", true),
+        ("[a-z]+", true),
+        ("[]a]", true),
+        ("[^]a]", true),
+        ("[[:digit:]]+", true),
+        ("a{2}", true),
+        ("a{2,}", true),
+        ("a{2,5}", true),
+        ("\\.", true),
+        ("^a$", true),
+        ("(ab", false),
+        ("[a-", false),
+        ("[z-a]", false),
+        ("[[:foo:]]", false),
+        ("a{3,2}", false),
+        ("a{2", false),
+        ("*a", false),
+        ("\\", false),
+    ];
+
+    #[test]
+    fn test_regex_error() {
+        for &(pattern, valid) in REGEXES {
+            assert_eq!(regex_error(pattern).is_none(), valid, "pattern `{}`", pattern);
+        }
+    }
+
+    /// The lint must agree with `regcomp`, which the patterns are compiled with at runtime
+    #[cfg(unix)]
+    #[test]
+    fn test_regex_error_agrees_with_regcomp() {
+        use libc;
+        use std::ffi::CString;
+        use std::mem;
+
+        for &(pattern, _) in REGEXES {
+            let c_pattern = CString::new(pattern).unwrap();
+            let compiles = unsafe {
+                let mut re: libc::regex_t = mem::zeroed();
+                let err = libc::regcomp(&mut re, c_pattern.as_ptr(), libc::REG_EXTENDED);
+                if err == 0 {
+                    libc::regfree(&mut re);
+                }
+                err == 0
+            };
+            assert_eq!(regex_error(pattern).is_none(), compiles, "pattern `{}`", pattern);
+        }
+    }
+
+    #[test]
+    fn test_regex_group_count() {
+        assert_eq!(regex_group_count("abc"), 0);
+        assert_eq!(regex_group_count("(a)(b(c))"), 3);
+        assert_eq!(regex_group_count("[(]\\(a"), 0);
+        assert_eq!(regex_group_count("[]()](x)"), 1);
+    }
+}
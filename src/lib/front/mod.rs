@@ -7,6 +7,7 @@ use std::cmp::min;
 use std::fmt::{self, Debug, Display};
 use std::iter::{once, repeat};
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
 use std::path::Path;
 use std::collections::BTreeSet;
 use std::io::{self, Write};
@@ -20,6 +21,15 @@ pub mod parse;
 pub mod inference;
 pub mod monomorphization;
 pub mod substitution;
+pub mod symbols;
+pub mod callgraph;
+pub mod cst_diff;
+pub mod grammar;
+pub mod interface;
+pub mod lint;
+pub mod query;
+pub mod reduce;
+pub mod termination;
 
 /// A generator of unique type variables
 pub struct TypeVarGen(u64);
@@ -45,20 +55,63 @@ impl TypeVarGen {
     }
 }
 
+/// The default maximum number of errors to print
+pub const DEFAULT_ERROR_LIMIT: usize = 100;
+
+/// The number of errors encountered so far, whether printed or not
+static ERROR_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+/// The maximum number of errors to print, or zero for the default
+static ERROR_LIMIT: AtomicUsize = ATOMIC_USIZE_INIT;
+/// Whether the last error was not printed, in which case notes and help that
+/// accompany it are not printed either
+static SUPPRESSING: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Set the maximum number of errors to print. Further errors are only counted
+pub fn set_error_limit(limit: usize) {
+    ERROR_LIMIT.store(limit, Ordering::SeqCst)
+}
+
+fn error_limit() -> usize {
+    match ERROR_LIMIT.load(Ordering::SeqCst) {
+        0 => DEFAULT_ERROR_LIMIT,
+        limit => limit,
+    }
+}
+
+/// Count an error, and return whether it should be printed
+fn count_error() -> bool {
+    let n = ERROR_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+    let print = n <= error_limit();
+    SUPPRESSING.store(!print, Ordering::SeqCst);
+    print
+}
+
 /// Exit compilation
+///
+/// Prints the number of errors, including any that were not printed because of the limit
 pub fn exit() -> ! {
-    println!("\nError occured during compilation. Exiting");
+    let count = ERROR_COUNT.load(Ordering::SeqCst);
+    let limit = error_limit();
+    if count > limit {
+        println!("\n... and {} more errors", count - limit);
+    }
+    match count {
+        0 | 1 => println!("\nError occured during compilation. Exiting"),
+        _ => println!("\n{} errors occured during compilation. Exiting", count),
+    }
     process::exit(0)
 }
 
 /// Print an error and exit the compilation
 pub fn error_exit<E: Display>(msg: E) -> ! {
-    let mut t = term::stdout().expect("Could not acquire access to stdout");
+    if count_error() {
+        let mut t = term::stdout().expect("Could not acquire access to stdout");
 
-    t.fg(color::BRIGHT_RED).ok();
-    print!("Error: ");
-    t.reset().ok();
-    println!("{}", msg);
+        t.fg(color::BRIGHT_RED).ok();
+        print!("Error: ");
+        t.reset().ok();
+        println!("{}", msg);
+    }
 
     exit()
 }
@@ -102,6 +155,11 @@ impl<'src> SrcPos<'src> {
         }
     }
 
+    /// Returns the path of the source file
+    pub fn filename(&self) -> &'src Path {
+        self.filename
+    }
+
     fn to(&self, other: &Self) -> Self {
         assert_eq!(
             self.filename, other.filename,
@@ -136,7 +194,7 @@ impl<'src> SrcPos<'src> {
     }
 
     /// Note: for compatibility with tooling, columns are 1-indexed on print
-    fn line_len_row_col(&self) -> (&'src str, usize, usize, usize) {
+    pub fn line_len_row_col(&self) -> (&'src str, usize, usize, usize) {
         let mut line_start = 0;
 
         for (row, line) in self.src.lines().enumerate().map(|(n, line)| (n + 1, line)) {
@@ -224,8 +282,11 @@ impl<'src> SrcPos<'src> {
         self.write_message(w, msg, "Error", color::BRIGHT_RED);
     }
 
+    /// Prints an error to stdout, unless the limit of printed errors has been reached
     pub fn print_error<E: Display>(&self, msg: E) {
-        self.write_error(&mut io::stdout(), msg)
+        if count_error() {
+            self.write_error(&mut io::stdout(), msg)
+        }
     }
 
     /// Like `SrcPos::error`, but exits after message has been printed
@@ -249,7 +310,9 @@ impl<'src> SrcPos<'src> {
     }
 
     pub fn print_note<S: Display>(&self, msg: S) {
-        self.write_note(&mut io::stdout(), msg);
+        if !SUPPRESSING.load(Ordering::SeqCst) {
+            self.write_note(&mut io::stdout(), msg);
+        }
     }
 
     /// Like `SrcPos::error`, but text is cyan and kind is "Help"
@@ -258,7 +321,9 @@ impl<'src> SrcPos<'src> {
     }
 
     pub fn print_help<S: Display>(&self, msg: S) {
-        self.write_help(&mut io::stdout(), msg);
+        if !SUPPRESSING.load(Ordering::SeqCst) {
+            self.write_help(&mut io::stdout(), msg);
+        }
     }
 }
 impl<'src> Debug for SrcPos<'src> {
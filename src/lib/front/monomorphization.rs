@@ -112,7 +112,11 @@ fn monomorphize_defs_of_insts_in_expr<'src>(
         Expr::Cast(ref mut c) => {
             monomorphize_defs_of_insts_in_expr(&mut c.expr, env);
         }
-        Expr::Nil(_) | Expr::StrLit(_) | Expr::Bool(_) => (),
+        Expr::Nil(_)
+        | Expr::StrLit(_)
+        | Expr::Bool(_)
+        | Expr::SizeOf(_)
+        | Expr::AlignOf(_) => (),
     }
 }
 
@@ -1,3 +1,8 @@
+//! Lowering of Concrete Syntax Trees to an Abstract Syntax Tree
+//!
+//! Checks the shapes of all forms, e.g. the arity of `if` and the patterns of definitions,
+//! and desugars derived forms, like `cond` and `fn*`, to the core forms of the AST.
+
 use self::PErr::*;
 use super::*;
 use super::ast::*;
@@ -6,11 +11,13 @@ use super::dependency_graph::*;
 use lib::CanonPathBuf;
 use lib::collections::AddMap;
 use lib::front::lex::lex_file;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::once;
+use std::path::{Path, PathBuf};
 
 /// Constructors for common parse errors to prevent repetition and spelling mistakes
 #[derive(PartialEq, Eq)]
-enum PErr<'s> {
+pub enum PErr<'s> {
     /// Mismatch in the amount of parameters given. Some amount was expected, another was given
     ArityMis(SrcPos<'s>, usize, usize),
     /// Mismatch in the amount of parameters given. At least some amount was expected, another was given
@@ -19,6 +26,14 @@ enum PErr<'s> {
     Expected(SrcPos<'s>, &'static str),
     /// Duplicate definition of external variable
     ExtDuplDef(SrcPos<'s>, &'s str),
+    /// Duplicate type signature of global variable
+    SigDuplDef(SrcPos<'s>, &'s str),
+    /// A special form that translates to definitions of the standard library was used
+    /// without importing it
+    RequiresStd(SrcPos<'s>, &'static str),
+    /// Mismatch between the number of `{}` placeholders in the message of a `panic` and the
+    /// number of arguments. (placeholders, arguments)
+    FormatArgsMis(SrcPos<'s>, usize, usize),
     /// Undefined constraint
     UndefConstr(SrcPos<'s>, &'s str),
     /// Invalid constraint
@@ -37,6 +52,20 @@ enum PErr<'s> {
     InvalidAdtConstrIdent(SrcPos<'s>, &'s str),
     /// Invalid algebraic data type variant
     InvalidAdtVariant(SrcPos<'s>),
+    /// Invalid newtype identifier
+    InvalidNewtypeIdent(SrcPos<'s>, &'s str),
+    /// Invalid representation attribute of algebraic data type
+    InvalidAdtRepr(SrcPos<'s>),
+    /// Explicit discriminant given for a variant with members
+    DiscriminantWithMembers(SrcPos<'s>, &'s str),
+    /// Discriminant does not fit in the representation type of the algebraic data type
+    DiscriminantOutOfRange(SrcPos<'s>, i64, Type<'s>),
+    /// Two variants of an algebraic data type have the same discriminant
+    DiscriminantDupl {
+        pos: SrcPos<'s>,
+        discriminant: i64,
+        prev_pos: SrcPos<'s>,
+    },
     /// Duplicate constraints definition for type variable
     TVarDuplDef {
         pos: SrcPos<'s>,
@@ -51,6 +80,22 @@ enum PErr<'s> {
     },
     /// Undefined type constructor
     UndefTypeCon(SrcPos<'s>, &'s str),
+    /// Data type or newtype that contains itself without indirection, and would be of
+    /// infinite size
+    InfiniteSizeData {
+        pos: SrcPos<'s>,
+        name: &'s str,
+        /// The chain of types through which the type contains itself
+        path: Vec<&'s str>,
+    },
+    /// The patterns of the last clause of a `fn*` may fail to match
+    NonExhaustive(SrcPos<'s>),
+    /// Too many parameters of a `fn*`. At most some amount is supported
+    TooManyParams(SrcPos<'s>, usize),
+    /// Braces are reserved for future use
+    ReservedBraces(SrcPos<'s>),
+    /// Operator of an `infix` form without a declared fixity
+    NoFixity(SrcPos<'s>, &'s str),
     /// Duplicate definition of a nnnnnvariable
     VarDuplDef {
         pos: SrcPos<'s>,
@@ -60,7 +105,7 @@ enum PErr<'s> {
 }
 
 impl<'s> PErr<'s> {
-    fn write<W: Write>(&self, w: &mut W) {
+    pub fn write<W: Write>(&self, w: &mut W) {
         match *self {
             ArityMis(ref pos, expected, found) => pos.write_error(
                 w,
@@ -74,6 +119,22 @@ impl<'s> PErr<'s> {
                 w,
                 format!("Duplicate declaration of external variable `{}`", e),
             ),
+            SigDuplDef(ref pos, s) => pos.write_error(
+                w,
+                format!("Duplicate type signature of variable `{}`", s),
+            ),
+            RequiresStd(ref pos, form) => pos.write_error(
+                w,
+                format!("`{}` requires the standard library. Import it with `(import std)`", form),
+            ),
+            FormatArgsMis(ref pos, placeholders, args) => pos.write_error(
+                w,
+                format!(
+                    "Format arguments mismatch. The message has {} `{{}}` placeholders, \
+                     but {} arguments are given",
+                    placeholders, args
+                ),
+            ),
             UndefConstr(ref pos, s) => pos.write_error(w, format!("Undefined constraint {}", s)),
             InvalidConstr(ref pos) => pos.write_error(w, "Invalid constraint"),
             InvalidTVar(ref pos) => pos.write_error(
@@ -82,7 +143,15 @@ impl<'s> PErr<'s> {
             ),
             InvalidType(ref pos) => pos.write_error(w, "Invalid type"),
             InvalidPatt(ref pos) => pos.write_error(w, "Invalid pattern"),
-            InvalidTopLevelItem(ref pos) => pos.write_error(w, "Invalid top level item"),
+            InvalidTopLevelItem(ref pos) => {
+                pos.write_error(w, "Invalid top level item");
+                pos.write_help(
+                    w,
+                    "Only definitions and declarations may appear at the top level.\n\
+                     To evaluate an expression when the program runs, \
+                     put it in the definition of `main`, e.g. `(define main <expr>)`",
+                )
+            }
             InvalidAdtIdent(ref pos, name) => {
                 pos.write_error(w, format!("Invalid Algebraic Data Type name `{}`", name))
             }
@@ -94,6 +163,35 @@ impl<'s> PErr<'s> {
                 ),
             ),
             InvalidAdtVariant(ref pos) => pos.write_error(w, "Invalid Algebraic Data Type variant"),
+            InvalidNewtypeIdent(ref pos, name) => {
+                pos.write_error(w, format!("Invalid newtype name `{}`", name))
+            }
+            InvalidAdtRepr(ref pos) => pos.write_error(
+                w,
+                "Invalid representation. Expected `C` or an integer type, e.g. `UInt8`",
+            ),
+            DiscriminantWithMembers(ref pos, name) => pos.write_error(
+                w,
+                format!(
+                    "Variant `{}` has members, and can not have an explicit discriminant",
+                    name
+                ),
+            ),
+            DiscriminantOutOfRange(ref pos, d, ref t) => pos.write_error(
+                w,
+                format!("Discriminant `{}` does not fit in representation type `{}`", d, t),
+            ),
+            DiscriminantDupl {
+                ref pos,
+                discriminant,
+                ref prev_pos,
+            } => {
+                pos.write_error(
+                    w,
+                    format!("Discriminant `{}` is used by multiple variants", discriminant),
+                );
+                prev_pos.write_note(w, "The discriminant was first used here:")
+            }
             TVarDuplDef {
                 ref pos,
                 name,
@@ -131,6 +229,49 @@ impl<'s> PErr<'s> {
             UndefTypeCon(ref pos, c) => {
                 pos.write_error(w, format!("Undefined type constructor `{}`", c))
             }
+            InfiniteSizeData {
+                ref pos,
+                name,
+                ref path,
+            } => {
+                pos.write_error(
+                    w,
+                    format!(
+                        "Recursive type `{}` has infinite size. It contains itself \
+                         through `{} -> {}`",
+                        name,
+                        path.join(" -> "),
+                        name
+                    ),
+                );
+                pos.write_note(
+                    w,
+                    format!(
+                        "Insert indirection, e.g. a `Ptr`, in this definition to make `{}` \
+                         representable",
+                        name
+                    ),
+                )
+            }
+            NonExhaustive(ref pos) => pos.write_error(
+                w,
+                "Non-exhaustive patterns. The patterns of the last clause must match any value",
+            ),
+            TooManyParams(ref pos, max) => pos.write_error(
+                w,
+                format!("Too many parameters. At most {} are supported", max),
+            ),
+            ReservedBraces(ref pos) => pos.write_error(
+                w,
+                "Braces, `{...}`, are reserved for future use, like record literals",
+            ),
+            NoFixity(ref pos, op) => pos.write_error(
+                w,
+                format!(
+                    "Operator `{}` has no declared fixity. Declare it with `infixl` or `infixr`",
+                    op
+                ),
+            ),
             VarDuplDef {
                 ref pos,
                 name,
@@ -146,13 +287,45 @@ impl<'s> PErr<'s> {
         }
     }
 
-    fn print(&self) {
-        self.write(&mut io::stdout())
+    /// Prints the error to stdout, unless the limit of printed errors has been reached
+    pub fn print(&self) {
+        if count_error() {
+            self.write(&mut io::stdout())
+        }
     }
 }
 
 type PRes<'s, T> = Result<T, PErr<'s>>;
 
+/// The result of lowering several items, with the errors of all that failed
+type PResAll<'s, T> = Result<T, Vec<PErr<'s>>>;
+
+/// Returns `x` if no errors were found, and otherwise the errors `errs`
+fn all_ok<'s, T>(x: T, errs: Vec<PErr<'s>>) -> PResAll<'s, T> {
+    if errs.is_empty() {
+        Ok(x)
+    } else {
+        Err(errs)
+    }
+}
+
+/// Returns the first of the errors `errs` of lowering several items
+fn first_err<'s>(errs: Vec<PErr<'s>>) -> PErr<'s> {
+    errs.into_iter().next().expect("ICE: failure without errors")
+}
+
+/// Names of the parameters of a `fn*`, which can not occur in source code
+const FN_STAR_PARAMS: [&str; 8] = [
+    "fn* arg 0",
+    "fn* arg 1",
+    "fn* arg 2",
+    "fn* arg 3",
+    "fn* arg 4",
+    "fn* arg 5",
+    "fn* arg 6",
+    "fn* arg 7",
+];
+
 /// A binding pattern
 ///
 /// Patterns are used in variable bindings as a sort of syntax sugar
@@ -173,6 +346,13 @@ fn n<'s, 'c>(n_: usize, cs: &'c [CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, &'c [CS
     }
 }
 
+/// Returns the source code of `cst`
+fn source_text<'s>(cst: &CST<'s>) -> &'s str {
+    let pos = cst.pos();
+    let src = pos.src;
+    &src[pos.start..pos.end.unwrap_or(pos.start)]
+}
+
 fn one<'s, 'c>(cs: &'c [CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, &'c CST<'s>> {
     n(1, cs, pos).map(|cs| &cs[0])
 }
@@ -227,6 +407,14 @@ fn split_last<'s, 'c>(
     cs.split_last().ok_or(ArityMisTooFew(pos.clone(), 0))
 }
 
+/// Returns whether the head of the list `cs` is the identifier `s`
+fn head_is(cs: &[CST], s: &str) -> bool {
+    match cs.first() {
+        Some(&CST::Ident(h, _)) => h == s,
+        _ => false,
+    }
+}
+
 fn first<'s, 'c>(cs: &'c [CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, &'c CST<'s>> {
     split_first(cs, pos).map(|(f, _)| f)
 }
@@ -243,11 +431,237 @@ fn constant<'s, T: Eq>(x: T, y: T, err: PErr<'s>) -> PRes<'s, ()> {
     }
 }
 
+/// Token trees of the top level items of a program, separated by kind of item
+#[derive(Default)]
+struct TopLevelCsts<'s> {
+    externs: Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
+    signatures: Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
+    /// Global definitions, and whether they are of the typed kind `define:`
+    globals: Vec<(bool, Vec<CST<'s>>, SrcPos<'s>)>,
+    datas: Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
+    newtypes: Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
+    /// Docstrings of global definitions
+    docs: BTreeMap<&'s str, String>,
+    /// Global definitions marked as total
+    totals: BTreeSet<&'s str>,
+    /// Features required by the modules, and where they were first required
+    features: BTreeMap<&'s str, SrcPos<'s>>,
+    /// Import dependencies between the visited modules
+    module_deps: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+/// Add the token trees `csts` of a global definition to `top`, taking its docstring if any
+fn push_global<'s>(
+    top: &mut TopLevelCsts<'s>,
+    is_typed: bool,
+    csts: &[CST<'s>],
+    pos: &SrcPos<'s>,
+) -> PRes<'s, ()> {
+    let mut def_csts = csts.to_vec();
+    if let Some((name, doc)) = take_docstring(&mut def_csts, is_typed)? {
+        top.docs.insert(name, doc);
+    }
+    top.globals.push((is_typed, def_csts, pos.clone()));
+    Ok(())
+}
+
+/// Remove the docstring from the token trees of a global definition, if there is one
+///
+/// A docstring is a string literal immediately after the pattern of a definition,
+/// e.g. `(define (inc x) "Increment x by one" (+ x 1))`.
+/// Returns the name of the defined variable and the docstring
+fn take_docstring<'s>(
+    csts: &mut Vec<CST<'s>>,
+    is_typed: bool,
+) -> PRes<'s, Option<(&'s str, String)>> {
+    let n_without_doc = if is_typed { 3 } else { 2 };
+    if csts.len() != n_without_doc + 1 {
+        return Ok(None);
+    }
+    let doc = match csts[1] {
+        CST::Str(ref doc, _) => doc.to_string(),
+        _ => return Ok(None),
+    };
+    let name = match csts[0] {
+        CST::SExpr(ref patt, ref pos) => ident_s(first(patt, pos)?)?,
+        ref c => ident_s(c)?,
+    };
+    csts.remove(1);
+    Ok(Some((name, doc)))
+}
+
+/// Returns the name of the variable defined by the token trees `csts` of a global definition
+fn global_name<'s>(csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, &'s str> {
+    match *first(csts, pos)? {
+        CST::SExpr(ref patt, ref pos) => ident_s(first(patt, pos)?),
+        ref c => ident_s(c),
+    }
+}
+
+/// Returns the token trees of a typed global definition of the constructor of
+/// the newtype defined by `csts`
+///
+/// E.g. for `(newtype Meters Float64)`, the constructor is
+/// `(define: (Meters x) (-> Float64 Meters) (cast x Meters))`
+fn newtype_constructor_csts<'s>(csts: &[CST<'s>], pos: &SrcPos<'s>) -> Vec<CST<'s>> {
+    let (name, underlying) = (&csts[0], &csts[1]);
+    let id = |s: &'s str| CST::Ident(s, pos.clone());
+    vec![
+        CST::SExpr(vec![name.clone(), id("x")], pos.clone()),
+        CST::SExpr(vec![id("->"), underlying.clone(), name.clone()], pos.clone()),
+        CST::SExpr(vec![id("cast"), id("x"), name.clone()], pos.clone()),
+    ]
+}
+
+/// Add the names of the data types in `datas` and newtypes in `newtypes` that `t` contains
+/// by value to `contained`
+///
+/// Pointers and functions are indirections, so data types behind them are not contained by value
+fn data_types_by_value<'s>(
+    t: &Type<'s>,
+    datas: &BTreeMap<&'s str, AdtDef<'s>>,
+    newtypes: &BTreeMap<&'s str, NewtypeDef<'s>>,
+    contained: &mut Vec<&'s str>,
+) {
+    match *t {
+        Type::Const(s, _) if datas.contains_key(s) || newtypes.contains_key(s) => {
+            contained.push(s)
+        }
+        Type::App(ref f, ref ts) => match **f {
+            TypeFunc::Const("Ptr") | TypeFunc::Const("->") => (),
+            _ => for t2 in ts {
+                data_types_by_value(t2, datas, newtypes, contained)
+            },
+        },
+        _ => (),
+    }
+}
+
+/// Returns the types contained by value in the data type or newtype `name`, each paired with
+/// the position of the variant or newtype definition that contains it
+fn types_contained_by<'s>(
+    name: &'s str,
+    datas: &BTreeMap<&'s str, AdtDef<'s>>,
+    newtypes: &BTreeMap<&'s str, NewtypeDef<'s>>,
+) -> Vec<(&'s str, SrcPos<'s>)> {
+    let mut contained = Vec::new();
+    if let Some(def) = datas.get(name) {
+        for variant in &def.variants {
+            let mut cs = Vec::new();
+            for member in &variant.members {
+                data_types_by_value(member, datas, newtypes, &mut cs);
+            }
+            contained.extend(cs.into_iter().map(|c| (c, variant.pos.clone())));
+        }
+    } else if let Some(def) = newtypes.get(name) {
+        let mut cs = Vec::new();
+        data_types_by_value(&def.underlying, datas, newtypes, &mut cs);
+        contained.extend(cs.into_iter().map(|c| (c, def.pos.clone())));
+    }
+    contained
+}
+
+/// Search for a chain of by-value containment from the last type in `path` back to the first
+///
+/// On success, returns the position of the variant or newtype that closes the cycle, and `path`
+/// is the chain of types
+fn find_data_type_cycle<'s>(
+    datas: &BTreeMap<&'s str, AdtDef<'s>>,
+    newtypes: &BTreeMap<&'s str, NewtypeDef<'s>>,
+    path: &mut Vec<&'s str>,
+) -> Option<SrcPos<'s>> {
+    let name = *path.last().expect("ICE: empty path in find_data_type_cycle");
+    for (c, pos) in types_contained_by(name, datas, newtypes) {
+        if c == path[0] {
+            return Some(pos);
+        } else if !path.contains(&c) {
+            path.push(c);
+            if let Some(pos) = find_data_type_cycle(datas, newtypes, path) {
+                return Some(pos);
+            }
+            path.pop();
+        }
+    }
+    None
+}
+
+/// Check that no data type in `datas` or newtype in `newtypes` contains itself by value,
+/// directly or through other types
+///
+/// Such a type would be of infinite size. A recursive data type, like a list or a tree,
+/// must refer to itself through an indirection, like a pointer.
+fn check_data_type_cycles<'s>(
+    datas: &BTreeMap<&'s str, AdtDef<'s>>,
+    newtypes: &BTreeMap<&'s str, NewtypeDef<'s>>,
+) -> PRes<'s, ()> {
+    for &name in datas.keys().chain(newtypes.keys()) {
+        let mut path = vec![name];
+        if let Some(pos) = find_data_type_cycle(datas, newtypes, &mut path) {
+            return Err(InfiniteSizeData { pos, name, path });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the token tree of the thread-first or thread-last form `csts`
+///
+/// E.g. for the thread-first `(-> x (f a) g)`, the result is `(g (f x a))`,
+/// while for the thread-last `(->> x (f a) g)`, the result is `(g (f a x))`
+fn threaded_cst<'s>(csts: &[CST<'s>], pos: &SrcPos<'s>, last: bool) -> PRes<'s, CST<'s>> {
+    let (init, forms) = split_first(csts, pos)?;
+    Ok(forms.iter().fold(init.clone(), |acc, form| match *form {
+        CST::SExpr(ref xs, ref form_pos) if !xs.is_empty() => {
+            let mut app = xs.clone();
+            if last {
+                app.push(acc)
+            } else {
+                app.insert(1, acc)
+            }
+            CST::SExpr(app, form_pos.clone())
+        }
+        _ => CST::SExpr(vec![form.clone(), acc], form.pos().clone()),
+    }))
+}
+
+/// The definitions of the standard library that special forms translate to, with their
+/// numbers of parameters, and the hidden names they are referred to by
+///
+/// A hidden name can't occur in source, so unlike the original name, it can't be shadowed by
+/// a local variable at the site of the special form. See `Parser::alias_std_defs`
+const STD_ALIASES: &'static [(&'static str, usize, &'static str)] = &[
+    ("=", 2, "std ="),
+    ("show-num", 1, "std show-num"),
+    ("string-append", 2, "std string-append"),
+    ("panic-at", 2, "std panic-at"),
+    ("assert-eq-failed-at", 4, "std assert-eq-failed-at"),
+];
+
+/// Hidden names of the parameters of the definitions of `STD_ALIASES`
+const STD_ALIAS_PARAMS: &'static [&'static str] = &["std a", "std b", "std c", "std d"];
+
+/// Returns whether `name` is a hidden name of a definition of the standard library
+pub fn is_std_alias(name: &str) -> bool {
+    STD_ALIASES.iter().any(|&(_, _, alias)| alias == name)
+}
+
+/// Associativity of an infix operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Assoc {
+    Left,
+    Right,
+}
+
 struct Parser<'tvg, 's> {
     /// An additive-only map of module file paths to source code strings
     sources: &'s AddMap<CanonPathBuf, String>,
     /// Counter for generation of unique type variable ids
     type_var_gen: &'tvg mut TypeVarGen,
+    /// Type holes, `?`, encountered so far
+    type_holes: Vec<(TVar<'s>, SrcPos<'s>)>,
+    /// Precedences and associativities of operators in `infix` forms
+    fixities: BTreeMap<&'s str, (u32, Assoc)>,
+    /// The definitions of `STD_ALIASES` that are defined, and so can be referred to
+    std_defs: BTreeSet<&'static str>,
 }
 
 impl<'tvg, 's> Parser<'tvg, 's> {
@@ -255,7 +669,98 @@ impl<'tvg, 's> Parser<'tvg, 's> {
         Parser {
             sources,
             type_var_gen,
+            type_holes: Vec::new(),
+            fixities: btreemap! {
+                "*" => (7, Assoc::Left),
+                "/" => (7, Assoc::Left),
+                "+" => (6, Assoc::Left),
+                "-" => (6, Assoc::Left),
+                "=" => (4, Assoc::Left),
+                "/=" => (4, Assoc::Left),
+                "<" => (4, Assoc::Left),
+                "<=" => (4, Assoc::Left),
+                ">" => (4, Assoc::Left),
+                ">=" => (4, Assoc::Left),
+                ">>" => (1, Assoc::Left),
+                ">>=" => (1, Assoc::Left),
+            },
+            std_defs: BTreeSet::new(),
+        }
+    }
+
+    /// Define the hidden names of `STD_ALIASES` for those of the definitions that are found
+    /// in `top`, e.g. `(define ("std =" "std a" "std b") (= "std a" "std b"))`
+    fn alias_std_defs(&mut self, top: &mut TopLevelCsts<'s>) {
+        let defined = top.globals
+            .iter()
+            .filter_map(|&(_, ref csts, ref pos)| {
+                global_name(csts, pos).ok().map(|name| (name, pos.clone()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for &(name, n_params, alias) in STD_ALIASES {
+            if let Some(pos) = defined.get(name) {
+                let id = |s: &'s str| CST::Ident(s, pos.clone());
+                let params = STD_ALIAS_PARAMS[..n_params]
+                    .iter()
+                    .map(|&param| id(param))
+                    .collect::<Vec<_>>();
+                let pattern = once(id(alias)).chain(params.iter().cloned()).collect();
+                let body = once(id(name)).chain(params).collect();
+                top.globals.push((
+                    false,
+                    vec![
+                        CST::SExpr(pattern, pos.clone()),
+                        CST::SExpr(body, pos.clone()),
+                    ],
+                    pos.clone(),
+                ));
+                self.std_defs.insert(name);
+            }
+        }
+    }
+
+    /// Returns a variable of the definition `name` of the standard library, by its hidden
+    /// name, for the translation of the special form `form` at `pos`
+    fn new_std_variable(
+        &mut self,
+        name: &'static str,
+        form: &'static str,
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Expr<'s>> {
+        let &(_, _, alias) = STD_ALIASES
+            .iter()
+            .find(|&&(def, _, _)| def == name)
+            .expect("ICE: definition missing in STD_ALIASES");
+        if self.std_defs.contains(name) {
+            Ok(self.new_variable(Ident::new(alias, pos.clone())))
+        } else {
+            Err(RequiresStd(pos.clone(), form))
+        }
+    }
+
+    /// Declare the fixity of operators
+    ///
+    /// `(infixl PRECEDENCE OPERATORS...)` or `(infixr PRECEDENCE OPERATORS...)`,
+    /// e.g. `(infixl 6 <+> <->)`. Operators of higher precedence bind tighter
+    fn declare_fixities(
+        &mut self,
+        csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+        assoc: Assoc,
+    ) -> PRes<'s, ()> {
+        let (prec_c, ops_c) = split_first(csts, pos)?;
+        let prec = match *prec_c {
+            CST::Num(n, ref num_pos) => n.parse::<u32>()
+                .map_err(|_| Expected(num_pos.clone(), "precedence level"))?,
+            _ => return Err(Expected(prec_c.pos().clone(), "precedence level")),
+        };
+        if ops_c.is_empty() {
+            return Err(ArityMisTooFew(pos.clone(), 2));
         }
+        for op_c in ops_c {
+            self.fixities.insert(ident_s(op_c)?, (prec, assoc));
+        }
+        Ok(())
     }
 
     fn gen_tvar(&mut self) -> TVar<'s> {
@@ -312,15 +817,45 @@ impl<'tvg, 's> Parser<'tvg, 's> {
     fn parse_externs(
         &mut self,
         decls_csts: &[(Vec<CST<'s>>, SrcPos<'s>)],
-    ) -> PRes<'s, BTreeMap<&'s str, ExternDecl<'s>>> {
+    ) -> PResAll<'s, BTreeMap<&'s str, ExternDecl<'s>>> {
         let mut externs = BTreeMap::new();
+        let mut errs = Vec::new();
         for &(ref decl_csts, ref pos) in decls_csts {
-            let ext = self.parse_extern(decl_csts, pos)?;
-            if let Some(ext) = externs.insert(ext.ident.s, ext) {
-                return Err(ExtDuplDef(ext.pos.clone(), ext.ident.s));
+            match self.parse_extern(decl_csts, pos) {
+                Ok(ext) => if let Some(ext) = externs.insert(ext.ident.s, ext) {
+                    errs.push(ExtDuplDef(ext.pos.clone(), ext.ident.s))
+                },
+                Err(e) => errs.push(e),
+            }
+        }
+        all_ok(externs, errs)
+    }
+
+    /// Parse a list of `CST`s as a type signature of a global variable
+    fn parse_signature(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Signature<'s>> {
+        let (a, b) = two(csts, pos)?;
+        Ok(Signature {
+            ident: ident(a)?,
+            typ: self.parse_type(b)?,
+            pos: pos.clone(),
+        })
+    }
+
+    fn parse_signatures(
+        &mut self,
+        sigs_csts: &[(Vec<CST<'s>>, SrcPos<'s>)],
+    ) -> PResAll<'s, BTreeMap<&'s str, Signature<'s>>> {
+        let mut signatures = BTreeMap::new();
+        let mut errs = Vec::new();
+        for &(ref sig_csts, ref pos) in sigs_csts {
+            match self.parse_signature(sig_csts, pos) {
+                Ok(sig) => if let Some(sig) = signatures.insert(sig.ident.s, sig) {
+                    errs.push(SigDuplDef(sig.pos.clone(), sig.ident.s))
+                },
+                Err(e) => errs.push(e),
             }
         }
-        Ok(externs)
+        all_ok(signatures, errs)
     }
 
     fn parse_constraint(&mut self, cst: &CST<'s>) -> PRes<'s, &'s str> {
@@ -405,6 +940,17 @@ impl<'tvg, 's> Parser<'tvg, 's> {
             .map(Type::new_ptr)
     }
 
+    /// Parse the type of a thunk, `(Lazy T)`, as the function type `(-> Nil T)`
+    fn parse_lazy_type(
+        &mut self,
+        tvars: &mut BTreeMap<&'s str, (TVar<'s>, SrcPos<'s>)>,
+        csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Type<'s>> {
+        self.parse_type_with_tvars(tvars, one(csts, pos)?)
+            .map(|t| Type::new_func(TYPE_NIL.clone(), t))
+    }
+
     fn parse_type_sexpr(
         &mut self,
         tvars: &mut BTreeMap<&'s str, (TVar<'s>, SrcPos<'s>)>,
@@ -419,6 +965,7 @@ impl<'tvg, 's> Parser<'tvg, 's> {
             "->" => self.parse_func_type(tvars, rest, pos),
             "Cons" => self.parse_cons_type(tvars, rest, pos),
             "Ptr" => self.parse_ptr_type(tvars, rest, pos),
+            "Lazy" => self.parse_lazy_type(tvars, rest, pos),
             _ => Err(UndefTypeCon(p.clone(), s)),
         }
     }
@@ -431,6 +978,11 @@ impl<'tvg, 's> Parser<'tvg, 's> {
     ) -> PRes<'s, Type<'s>> {
         match id {
             "_" => Ok(self.gen_type_var()),
+            "?" => {
+                let tv = self.gen_tvar();
+                self.type_holes.push((tv.clone(), pos.clone()));
+                Ok(Type::Var(tv))
+            }
             "Nil" => Ok(TYPE_NIL.clone()),
             // The type identifier starts with a lowercase letter => Is a type variable
             s if s.starts_with(char::is_lowercase) => {
@@ -570,6 +1122,610 @@ impl<'tvg, 's> Parser<'tvg, 's> {
         })
     }
 
+    /// Parse a `delay` special form as a thunk of type `(Lazy T)`
+    ///
+    /// `(delay EXPR)` is translated to `(lambda (_) EXPR)`, where the parameter is of type `Nil`.
+    /// The thunk is not memoized, so `EXPR` is evaluated each time the thunk is forced
+    fn parse_delay(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let body = self.parse_expr(one(csts, pos)?)?;
+        Ok(Expr::Lambda(Box::new(Lambda {
+            param_ident: Ident::new("_", pos.clone()),
+            param_type: TYPE_NIL.clone(),
+            body,
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })))
+    }
+
+    /// Parse a `force` special form, evaluating a thunk
+    ///
+    /// `(force THUNK)` is translated to `(THUNK nil)`
+    fn parse_force(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let thunk = self.parse_expr(one(csts, pos)?)?;
+        Ok(Expr::App(Box::new(App {
+            func: thunk,
+            arg: Expr::Nil(Nil { pos: pos.clone() }),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })))
+    }
+
+    /// Returns a reference to the variable `ident`, of yet unknown type
+    fn new_variable(&mut self, ident: Ident<'s>) -> Expr<'s> {
+        Expr::Variable(Variable {
+            ident,
+            typ: self.gen_type_var(),
+        })
+    }
+
+    /// Parse a `catch` special form, handling the error case of a result
+    ///
+    /// A result is an Either-like pair of a `Bool` tag and a value, as constructed by
+    /// `right` and `left` in the standard library. `(catch BODY (E HANDLER))` evaluates to
+    /// the value of `BODY` if it is `right`, and to `HANDLER` with `E` bound to the value
+    /// of `BODY` otherwise. Translated to
+    /// `((lambda (r) (if (car r) (cdr r) ((lambda (E) HANDLER) (cdr r)))) BODY)`,
+    /// where `r` is an identifier that can not occur in source code.
+    fn parse_catch(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (body_cst, handler_cst) = two(csts, pos)?;
+        let (e_cst, handler_body_cst) = pair(handler_cst)?;
+        let body = self.parse_expr(body_cst)?;
+        let e_ident = ident(e_cst)?;
+        let handler_body = self.parse_expr(handler_body_cst)?;
+        let handler_pos = handler_cst.pos();
+        let r_ident = Ident::new("catch result", pos.clone());
+        let handler = Expr::Lambda(Box::new(Lambda {
+            param_ident: e_ident,
+            param_type: self.gen_type_var(),
+            body: handler_body,
+            typ: self.gen_type_var(),
+            pos: handler_pos.clone(),
+        }));
+        let is_ok = Expr::Car(Box::new(Car {
+            expr: self.new_variable(r_ident.clone()),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        }));
+        let ok_val = Expr::Cdr(Box::new(Cdr {
+            expr: self.new_variable(r_ident.clone()),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        }));
+        let err_val = Expr::Cdr(Box::new(Cdr {
+            expr: self.new_variable(r_ident.clone()),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        }));
+        let handled = Expr::App(Box::new(App {
+            func: handler,
+            arg: err_val,
+            typ: self.gen_type_var(),
+            pos: handler_pos.clone(),
+        }));
+        let dispatch = Expr::Lambda(Box::new(Lambda {
+            param_ident: r_ident.clone(),
+            param_type: self.gen_type_var(),
+            body: Expr::If(Box::new(If {
+                predicate: is_ok,
+                consequent: ok_val,
+                alternative: handled,
+                typ: self.gen_type_var(),
+                pos: pos.clone(),
+            })),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        }));
+        Ok(Expr::App(Box::new(App {
+            func: dispatch,
+            arg: body,
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })))
+    }
+
+    /// Returns a string literal of the source location of `pos`, e.g. `"main.kvs:3:5"`
+    fn new_location_lit(&mut self, pos: &SrcPos<'s>) -> Expr<'s> {
+        let (_, _, row, col) = pos.line_len_row_col();
+        Expr::StrLit(StrLit {
+            lit: format!("{}:{}:{}", pos.filename().display(), row, col).into(),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })
+    }
+
+    /// Returns a string literal of `lit`
+    fn new_str_lit(&mut self, lit: String, pos: &SrcPos<'s>) -> Expr<'s> {
+        Expr::StrLit(StrLit {
+            lit: lit.into(),
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })
+    }
+
+    /// Returns `expr` ascribed a numeric type, as it's an operand of a special form that must
+    /// be a number. `reason` explains why, if the operand is not a number
+    fn new_num_operand(&mut self, expr: Expr<'s>, reason: &'static str) -> Expr<'s> {
+        let mut tv = self.gen_tvar();
+        tv.constrs.insert("Num");
+        let pos = expr.pos().clone();
+        Expr::TypeAscript(Box::new(TypeAscript {
+            typ: Type::Var(tv),
+            expr,
+            pos,
+            reason: Some(reason),
+        }))
+    }
+
+    /// Returns the application of `show-num` of the standard library to `num`, for the
+    /// translation of the special form `form` at `pos`
+    fn new_show_num(
+        &mut self,
+        num: Expr<'s>,
+        form: &'static str,
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Expr<'s>> {
+        Ok(Expr::App(Box::new(App {
+            func: self.new_std_variable("show-num", form, pos)?,
+            arg: num,
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })))
+    }
+
+    /// Returns the application of `panic-at` of the standard library to the source location
+    /// of `pos`, e.g. `"main.kvs:3:5"`, and `msg`, for the translation of the special form
+    /// `form`
+    fn new_panic(
+        &mut self,
+        msg: Expr<'s>,
+        form: &'static str,
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Expr<'s>> {
+        let location = self.new_location_lit(pos);
+        let panic_at = self.new_std_variable("panic-at", form, pos)?;
+        Ok(Expr::App(Box::new(
+            self.new_multary_app(panic_at, &[location, msg], pos)?,
+        )))
+    }
+
+    /// Parse the message and arguments of a `panic` as a single string expression
+    ///
+    /// Without arguments, the message is any string expression. With arguments, it must be a
+    /// string literal with a `{}` placeholder for each argument, e.g. `"{} is not {}"` with
+    /// the arguments `A` and `B`, which is translated to
+    /// `(string-append (show-num A) (string-append " is not " (show-num B)))`.
+    fn parse_panic_msg(
+        &mut self,
+        msg_cst: &CST<'s>,
+        args_csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Expr<'s>> {
+        if args_csts.is_empty() {
+            return self.parse_expr(msg_cst);
+        }
+        let (fmt, fmt_pos) = match *msg_cst {
+            CST::Str(ref fmt, ref fmt_pos) => (fmt, fmt_pos),
+            _ => {
+                return Err(Expected(
+                    msg_cst.pos().clone(),
+                    "string literal message with a `{}` placeholder for each argument",
+                ))
+            }
+        };
+        let pieces = fmt.split("{}").collect::<Vec<_>>();
+        if pieces.len() != args_csts.len() + 1 {
+            return Err(FormatArgsMis(
+                fmt_pos.clone(),
+                pieces.len() - 1,
+                args_csts.len(),
+            ));
+        }
+        // The pieces of the message and the shown arguments, in order, without empty pieces
+        let mut parts = Vec::new();
+        for (piece, arg_cst) in pieces.iter().zip(args_csts) {
+            if !piece.is_empty() {
+                parts.push(self.new_str_lit(piece.to_string(), fmt_pos));
+            }
+            let arg = self.parse_expr(arg_cst)?;
+            let num = self.new_num_operand(arg, "The arguments of `panic` must be numbers");
+            parts.push(self.new_show_num(num, "panic", pos)?);
+        }
+        let last_piece = pieces[args_csts.len()];
+        if !last_piece.is_empty() {
+            parts.push(self.new_str_lit(last_piece.to_string(), fmt_pos));
+        }
+        // Append the parts from the right
+        let mut msg = parts.pop().expect("ICE: no parts of panic message");
+        while let Some(part) = parts.pop() {
+            msg = self.new_string_append(part, msg, pos)?;
+        }
+        Ok(msg)
+    }
+
+    /// Returns the application of `string-append` of the standard library to `a` and `b`, for
+    /// the translation of a `panic`
+    fn new_string_append(
+        &mut self,
+        a: Expr<'s>,
+        b: Expr<'s>,
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, Expr<'s>> {
+        let append = self.new_std_variable("string-append", "panic", pos)?;
+        Ok(Expr::App(Box::new(self.new_multary_app(append, &[a, b], pos)?)))
+    }
+
+    /// Parse a `panic` special form
+    ///
+    /// `(panic MSG ARGS...)` exits the program with the error `MSG`, reported together with
+    /// the source location of the `panic`. The arguments, which must be numbers, are shown
+    /// in place of the `{}` placeholders of the message, see `parse_panic_msg`. Translated to
+    /// `(panic-at LOCATION MSG)`, which never returns, and so is of any type.
+    fn parse_panic(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (msg_cst, args_csts) = split_first(csts, pos)?;
+        let msg = self.parse_panic_msg(msg_cst, args_csts, pos)?;
+        self.new_panic(msg, "panic", pos)
+    }
+
+    /// Parse an `unreachable` special form
+    ///
+    /// `(unreachable)` is translated to `(panic "Entered unreachable code")`
+    fn parse_unreachable(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        n(0, csts, pos)?;
+        let msg = self.new_str_lit("Entered unreachable code".to_string(), pos);
+        self.new_panic(msg, "unreachable", pos)
+    }
+
+    /// Parse an `assert` special form
+    ///
+    /// `(assert EXPR)` panics, with the source code of `EXPR` in the message, if `EXPR` is
+    /// false. Translated to `(if EXPR nil (panic "Assertion failed: EXPR"))`.
+    fn parse_assert(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let test_cst = one(csts, pos)?;
+        let test = self.parse_expr(test_cst)?;
+        let msg = format!("Assertion failed: {}", source_text(test_cst));
+        let msg = self.new_str_lit(msg, pos);
+        Ok(Expr::If(Box::new(If {
+            predicate: test,
+            consequent: Expr::Nil(Nil { pos: pos.clone() }),
+            alternative: self.new_panic(msg, "assert", pos)?,
+            typ: self.gen_type_var(),
+            pos: pos.clone(),
+        })))
+    }
+
+    /// Parse an `assert-eq` special form
+    ///
+    /// `(assert-eq A B)` panics, with the source code and the values of `A` and `B` in the
+    /// message, if `(= A B)` is false. As `=`, it's only defined for numbers. Translated to
+    /// `((lambda (l r) (if (= l r) nil (assert-eq-failed-at LOCATION "A = B" (show-num l)
+    /// (show-num r)))) A B)`, where `l` and `r` are identifiers that can not occur in
+    /// source code.
+    fn parse_assert_eq(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (a_cst, b_cst) = two(csts, pos)?;
+        let mut args = Vec::new();
+        for operand_cst in &[a_cst, b_cst] {
+            let operand = self.parse_expr(operand_cst)?;
+            args.push(self.new_num_operand(
+                operand,
+                "The operands of `assert-eq` must be numbers, as for `=`",
+            ));
+        }
+        let l_ident = Ident::new("assert-eq left", pos.clone());
+        let r_ident = Ident::new("assert-eq right", pos.clone());
+        let operands = [
+            self.new_variable(l_ident.clone()),
+            self.new_variable(r_ident.clone()),
+        ];
+        let eq = self.new_std_variable("=", "assert-eq", pos)?;
+        let test = Expr::App(Box::new(self.new_multary_app(eq, &operands, pos)?));
+        let mut shown = Vec::new();
+        for operand_ident in &[l_ident.clone(), r_ident.clone()] {
+            let operand = self.new_variable(operand_ident.clone());
+            shown.push(self.new_show_num(operand, "assert-eq", pos)?);
+        }
+        let source = format!("{} = {}", source_text(a_cst), source_text(b_cst));
+        let source = self.new_str_lit(source, pos);
+        let location = self.new_location_lit(pos);
+        let failed_at = self.new_std_variable("assert-eq-failed-at", "assert-eq", pos)?;
+        let mut failed_args = vec![location, source];
+        failed_args.extend(shown);
+        let failed = Expr::App(Box::new(
+            self.new_multary_app(failed_at, &failed_args, pos)?,
+        ));
+        let params = [
+            (l_ident, self.gen_type_var()),
+            (r_ident, self.gen_type_var()),
+        ];
+        let check = self.new_multary_lambda(
+            &params,
+            pos,
+            Expr::If(Box::new(If {
+                predicate: test,
+                consequent: Expr::Nil(Nil { pos: pos.clone() }),
+                alternative: failed,
+                typ: self.gen_type_var(),
+                pos: pos.clone(),
+            })),
+            pos,
+        )?;
+        Ok(Expr::App(Box::new(self.new_multary_app(
+            Expr::Lambda(Box::new(check)),
+            &args,
+            pos,
+        )?)))
+    }
+
+    /// Returns a predicate for whether the argument `arg` matches the pattern `patt`,
+    /// or `None` if the pattern is irrefutable
+    fn parse_clause_pattern_test(
+        &mut self,
+        patt: &CST<'s>,
+        arg: &Ident<'s>,
+    ) -> PRes<'s, Option<Expr<'s>>> {
+        let arg_var = self.new_variable(arg.clone());
+        match *patt {
+            CST::Ident("true", _) => Ok(Some(arg_var)),
+            CST::Ident("false", ref pos) => Ok(Some(Expr::If(Box::new(If {
+                predicate: arg_var,
+                consequent: Expr::Bool(Bool {
+                    val: false,
+                    pos: pos.clone(),
+                }),
+                alternative: Expr::Bool(Bool {
+                    val: true,
+                    pos: pos.clone(),
+                }),
+                typ: self.gen_type_var(),
+                pos: pos.clone(),
+            })))),
+            CST::Ident(_, _) => Ok(None),
+            CST::Num(_, ref pos) => {
+                let eq = self.new_variable(Ident::new("=", pos.clone()));
+                let lit = self.parse_expr(patt)?;
+                self.new_multary_app(eq, &[arg_var, lit], pos)
+                    .map(|app| Some(Expr::App(Box::new(app))))
+            }
+            _ => Err(InvalidPatt(patt.pos().clone())),
+        }
+    }
+
+    /// Parse a clause of a `fn*` as a predicate for whether the clause matches the
+    /// arguments `args`, and the body of the clause with the variables of the patterns bound
+    ///
+    /// The predicate is `None` if all patterns of the clause are irrefutable
+    fn parse_fn_star_clause(
+        &mut self,
+        cst: &CST<'s>,
+        args: &[Ident<'s>],
+    ) -> PRes<'s, (Option<Expr<'s>>, Expr<'s>)> {
+        let (patts_cst, body_cst) = pair(cst)?;
+        let patts = sexpr(patts_cst)?;
+        if patts.len() != args.len() {
+            return Err(ArityMis(patts_cst.pos().clone(), args.len(), patts.len()));
+        }
+        let mut tests = Vec::new();
+        for (patt, arg) in patts.iter().zip(args) {
+            if let Some(test) = self.parse_clause_pattern_test(patt, arg)? {
+                tests.push(test)
+            }
+        }
+        let predicate = match tests.pop() {
+            Some(last_test) => Some(tests.into_iter().rev().fold(last_test, |rest, test| {
+                let pos = test.pos().clone();
+                Expr::If(Box::new(If {
+                    predicate: test,
+                    consequent: rest,
+                    alternative: Expr::Bool(Bool {
+                        val: false,
+                        pos: pos.clone(),
+                    }),
+                    typ: self.gen_type_var(),
+                    pos,
+                }))
+            })),
+            None => None,
+        };
+        let mut body = self.parse_expr(body_cst)?;
+        for (patt, arg) in patts.iter().zip(args).rev() {
+            match *patt {
+                CST::Ident("true", _) | CST::Ident("false", _) => (),
+                CST::Ident(s, ref pos) => {
+                    let pos = pos.clone();
+                    body = Expr::App(Box::new(App {
+                        func: Expr::Lambda(Box::new(Lambda {
+                            param_ident: Ident::new(s, pos.clone()),
+                            param_type: self.gen_type_var(),
+                            body,
+                            typ: self.gen_type_var(),
+                            pos: pos.clone(),
+                        })),
+                        arg: self.new_variable(arg.clone()),
+                        typ: self.gen_type_var(),
+                        pos,
+                    }))
+                }
+                _ => (),
+            }
+        }
+        Ok((predicate, body))
+    }
+
+    /// Parse a `fn*` special form, a lambda of multiple clauses matching on the arguments
+    ///
+    /// `(fn* (PATTERNS BODY) ...)`, e.g. `(fn* ((0) 1) ((n) (* n 2)))`. A pattern is either
+    /// a variable to bind, a number literal, or `true` or `false`. Number patterns are compared
+    /// to the argument with `=`. The clauses are tried in order, and the last
+    /// clause must be irrefutable. As the lambda is anonymous, recursion is done by binding
+    /// it to a name with `define` or `let`.
+    ///
+    /// Translated to a lambda of fresh parameters, with the clauses as nested `If`s
+    fn parse_fn_star(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (last, init) = split_last(csts, pos)?;
+        let n_params = sexpr(first(sexpr(last)?, last.pos())?)?.len();
+        if n_params > FN_STAR_PARAMS.len() {
+            return Err(TooManyParams(pos.clone(), FN_STAR_PARAMS.len()));
+        }
+        let args = FN_STAR_PARAMS[..n_params]
+            .iter()
+            .map(|&s| Ident::new(s, pos.clone()))
+            .collect::<Vec<_>>();
+        let otherwise = match self.parse_fn_star_clause(last, &args)? {
+            (None, body) => body,
+            (Some(_), _) => return Err(NonExhaustive(last.pos().clone())),
+        };
+        let mut body = otherwise;
+        for clause in init.iter().rev() {
+            let (predicate, consequent) = self.parse_fn_star_clause(clause, &args)?;
+            body = match predicate {
+                Some(predicate) => Expr::If(Box::new(If {
+                    predicate,
+                    consequent,
+                    alternative: body,
+                    typ: self.gen_type_var(),
+                    pos: clause.pos().clone(),
+                })),
+                None => consequent,
+            };
+        }
+        let params = args.into_iter()
+            .map(|id| (id, self.gen_type_var()))
+            .collect::<Vec<_>>();
+        self.new_multary_lambda(&params, pos, body, pos)
+            .map(|lam| Expr::Lambda(Box::new(lam)))
+    }
+
+    /// Parse a `partial` special form, a partial application of a function
+    ///
+    /// `(partial F A B)` evaluates to a function of the remaining parameters of `F`,
+    /// with the first two bound to `A` and `B`. As all functions are curried, e.g. a
+    /// function defined with `(define (f a b c) ...)` is a function of one parameter that
+    /// returns a function of two, this is translated to the application `(F A B)`.
+    fn parse_partial(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (func, args) = split_first(csts, pos)?;
+        if args.is_empty() {
+            return Err(ArityMisTooFew(pos.clone(), 1));
+        }
+        self.parse_app(func, args, pos)
+            .map(|app| Expr::App(Box::new(app)))
+    }
+
+    /// Returns the token tree of the part of the infix expression `csts` starting at `*i`,
+    /// where only operators of at least precedence `min_prec` are included
+    ///
+    /// Advances `*i` past the included part
+    fn infix_cst(&self, csts: &[CST<'s>], i: &mut usize, min_prec: u32) -> PRes<'s, CST<'s>> {
+        let mut lhs = csts[*i].clone();
+        *i += 1;
+        while *i < csts.len() {
+            let op_c = &csts[*i];
+            let op = ident_s(op_c)?;
+            let (prec, assoc) = *self.fixities
+                .get(op)
+                .ok_or(NoFixity(op_c.pos().clone(), op))?;
+            if prec < min_prec {
+                break;
+            }
+            *i += 1;
+            if *i == csts.len() {
+                return Err(Expected(op_c.pos().clone(), "operand after operator"));
+            }
+            let next_min_prec = if assoc == Assoc::Left { prec + 1 } else { prec };
+            let rhs = self.infix_cst(csts, i, next_min_prec)?;
+            let pos = lhs.pos().to(rhs.pos());
+            lhs = CST::SExpr(vec![op_c.clone(), lhs, rhs], pos);
+        }
+        Ok(lhs)
+    }
+
+    /// Parse an infix expression
+    ///
+    /// `(infix a + b * c)` is equivalent to `(+ a (* b c))`. Operands may be arbitrary
+    /// expressions, while operators must have a declared fixity
+    fn parse_infix(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        if csts.is_empty() {
+            return Err(ArityMisTooFew(pos.clone(), 1));
+        }
+        let cst = self.infix_cst(csts, &mut 0, 0)?;
+        self.parse_expr(&cst)
+    }
+
+    /// Parse a thread-first `->` or thread-last `->>` special form
+    ///
+    /// The first expression is inserted as the first or last argument of the second form,
+    /// which in turn is inserted in the third, and so on. See `threaded_cst`
+    fn parse_threading(
+        &mut self,
+        csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+        last: bool,
+    ) -> PRes<'s, Expr<'s>> {
+        self.parse_expr(&threaded_cst(csts, pos, last)?)
+    }
+
+    /// Parse an `as->` special form, threading a value through forms as a named variable
+    ///
+    /// `(as-> EXPR NAME FORM ...)`, e.g. `(as-> 2 x (+ x 1) (f 0 x 3))`. Translated to nested
+    /// applications of lambdas, where each lambda binds `NAME` to the value of the previous form
+    fn parse_as_threading(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        let (init, rest) = split_first(csts, pos)?;
+        let (name, forms) = split_first(rest, pos)?;
+        let name = ident(name)?;
+        let mut acc = self.parse_expr(init)?;
+        for form in forms {
+            let form_pos = form.pos().clone();
+            let body = self.parse_expr(form)?;
+            acc = Expr::App(Box::new(App {
+                func: Expr::Lambda(Box::new(Lambda {
+                    param_ident: name.clone(),
+                    param_type: self.gen_type_var(),
+                    body,
+                    typ: self.gen_type_var(),
+                    pos: form_pos.clone(),
+                })),
+                arg: acc,
+                typ: self.gen_type_var(),
+                pos: form_pos,
+            }))
+        }
+        Ok(acc)
+    }
+
+    /// Returns the value of a quoted syntax tree
+    ///
+    /// Lists are represented as chains of `Cons` pairs ending in `nil`, numbers as number
+    /// literals, and identifiers and strings as string literals. E.g. `'(foo 1 ("bar"))` is
+    /// equivalent to `(cons "foo" (cons 1 (cons (cons "bar" nil) nil)))`
+    fn quoted_expr(&mut self, cst: &CST<'s>) -> PRes<'s, Expr<'s>> {
+        match *cst {
+            CST::SExpr(ref xs, ref pos) => {
+                let mut list = Expr::Nil(Nil { pos: pos.clone() });
+                for x in xs.iter().rev() {
+                    list = Expr::Cons(Box::new(Cons {
+                        typ: self.gen_type_var(),
+                        car: self.quoted_expr(x)?,
+                        cdr: list,
+                        pos: x.pos().clone(),
+                    }))
+                }
+                Ok(list)
+            }
+            CST::Ident(s, ref pos) => Ok(Expr::StrLit(StrLit {
+                lit: s.into(),
+                typ: self.gen_type_var(),
+                pos: pos.clone(),
+            })),
+            CST::Num(..) | CST::Str(..) => self.parse_expr(cst),
+            CST::Braces(_, ref pos) => Err(ReservedBraces(pos.clone())),
+        }
+    }
+
+    /// Parse a `quote` special form, the syntax tree given as data
+    ///
+    /// `(quote DATA)` or the shorthand `'DATA`. See `quoted_expr` for the representation
+    fn parse_quote(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, Expr<'s>> {
+        self.quoted_expr(one(csts, pos)?)
+    }
+
     fn new_multary_lambda(
         &mut self,
         params: &[(Ident<'s>, Type<'s>)],
@@ -686,30 +1842,39 @@ impl<'tvg, 's> Parser<'tvg, 's> {
     fn parse_bindings_to_flat_map(
         &mut self,
         defs: &[(bool, &[CST<'s>], SrcPos<'s>)],
-    ) -> PRes<'s, BTreeMap<&'s str, Binding<'s>>> {
+    ) -> PResAll<'s, BTreeMap<&'s str, Binding<'s>>> {
         let mut bindings = BTreeMap::new();
+        let mut errs = Vec::new();
         for &(is_typed, ref def_csts, ref pos) in defs {
             let binding = if is_typed {
-                self.parse_typed_binding(def_csts, pos)?
+                self.parse_typed_binding(def_csts, pos)
             } else {
-                self.parse_untyped_binding(def_csts, pos)?
+                self.parse_untyped_binding(def_csts, pos)
+            };
+            let binding = match binding {
+                Ok(binding) => binding,
+                Err(e) => {
+                    errs.push(e);
+                    continue;
+                }
             };
             let (name, pos) = (binding.ident.s, binding.pos.clone());
             if let Some(prev_binding) = bindings.insert(name, binding) {
-                return Err(VarDuplDef {
+                errs.push(VarDuplDef {
                     name,
                     pos,
                     prev_pos: prev_binding.pos,
-                });
+                })
             }
         }
-        Ok(bindings)
+        all_ok(bindings, errs)
     }
 
+    /// Parse the definitions `defs`, returning the errors of all that fail
     fn parse_bindings(
         &mut self,
         defs: &[(bool, &[CST<'s>], SrcPos<'s>)],
-    ) -> PRes<'s, TopologicallyOrderedDependencyGroups<'s>> {
+    ) -> PResAll<'s, TopologicallyOrderedDependencyGroups<'s>> {
         self.parse_bindings_to_flat_map(defs)
             .map(flat_bindings_to_topologically_ordered)
     }
@@ -723,7 +1888,7 @@ impl<'tvg, 's> Parser<'tvg, 's> {
             let binding_pair = sexpr(cst)?;
             bindings_csts.push((false, binding_pair.clone(), cst.pos().clone()))
         }
-        self.parse_bindings(&bindings_csts)
+        self.parse_bindings(&bindings_csts).map_err(first_err)
     }
 
     /// Parse a `let` special form and return as an invocation of a lambda
@@ -749,6 +1914,7 @@ impl<'tvg, 's> Parser<'tvg, 's> {
             typ: self.parse_type(b)?,
             expr: self.parse_expr(a)?,
             pos: pos.clone(),
+            reason: None,
         })
     }
 
@@ -793,6 +1959,26 @@ impl<'tvg, 's> Parser<'tvg, 's> {
         })
     }
 
+    /// Parse a size query
+    ///
+    /// `(size-of TYPE)`, e.g. `(size-of (Cons Int8 Int64))`
+    fn parse_size_of(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, SizeOf<'s>> {
+        Ok(SizeOf {
+            queried: self.parse_type(one(csts, pos)?)?,
+            pos: pos.clone(),
+        })
+    }
+
+    /// Parse an alignment query
+    ///
+    /// `(align-of TYPE)`, e.g. `(align-of Float64)`
+    fn parse_align_of(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, AlignOf<'s>> {
+        Ok(AlignOf {
+            queried: self.parse_type(one(csts, pos)?)?,
+            pos: pos.clone(),
+        })
+    }
+
     /// Parse a `CST` as an `Expr`
     fn parse_expr(&mut self, cst: &CST<'s>) -> PRes<'s, Expr<'s>> {
         match *cst {
@@ -815,9 +2001,29 @@ impl<'tvg, 's> Parser<'tvg, 's> {
                         CST::Ident("cast", _) => {
                             Ok(Expr::Cast(Box::new(self.parse_cast(tail, pos)?)))
                         }
+                        CST::Ident("size-of", _) => {
+                            Ok(Expr::SizeOf(Box::new(self.parse_size_of(tail, pos)?)))
+                        }
+                        CST::Ident("align-of", _) => {
+                            Ok(Expr::AlignOf(Box::new(self.parse_align_of(tail, pos)?)))
+                        }
+                        CST::Ident("quote", _) => self.parse_quote(tail, pos),
 
                         // "Macros"
                         CST::Ident("cond", _) => self.parse_cond(tail, pos),
+                        CST::Ident("delay", _) => self.parse_delay(tail, pos),
+                        CST::Ident("force", _) => self.parse_force(tail, pos),
+                        CST::Ident("catch", _) => self.parse_catch(tail, pos),
+                        CST::Ident("panic", _) => self.parse_panic(tail, pos),
+                        CST::Ident("unreachable", _) => self.parse_unreachable(tail, pos),
+                        CST::Ident("assert", _) => self.parse_assert(tail, pos),
+                        CST::Ident("assert-eq", _) => self.parse_assert_eq(tail, pos),
+                        CST::Ident("fn*", _) => self.parse_fn_star(tail, pos),
+                        CST::Ident("partial", _) => self.parse_partial(tail, pos),
+                        CST::Ident("infix", _) => self.parse_infix(tail, pos),
+                        CST::Ident("->", _) => self.parse_threading(tail, pos, false),
+                        CST::Ident("->>", _) => self.parse_threading(tail, pos, true),
+                        CST::Ident("as->", _) => self.parse_as_threading(tail, pos),
                         _ => Ok(Expr::App(Box::new(self.parse_app(&sexpr[0], tail, pos)?))),
                     }
                 } else {
@@ -847,6 +2053,7 @@ impl<'tvg, 's> Parser<'tvg, 's> {
                 typ: self.gen_type_var(),
                 pos: pos.clone(),
             })),
+            CST::Braces(_, ref pos) => Err(ReservedBraces(pos.clone())),
         }
     }
 
@@ -861,6 +2068,8 @@ impl<'tvg, 's> Parser<'tvg, 's> {
     }
 
     /// Parse a variant of a data type definition
+    ///
+    /// A variant without members may be given an explicit discriminant, e.g. `(= Red 1)`
     fn parse_data_type_variant(&mut self, c: &CST<'s>) -> PRes<'s, AdtVariant<'s>> {
         match *c {
             CST::Ident(s, ref p) => Ok(AdtVariant {
@@ -869,8 +2078,27 @@ impl<'tvg, 's> Parser<'tvg, 's> {
                     pos: p.clone(),
                 },
                 members: vec![],
+                discriminant: None,
                 pos: p.clone(),
             }),
+            CST::SExpr(ref cs, ref p) if head_is(cs, "=") => {
+                let (_, name_c, discr_c) = three(cs, p)?;
+                let name = ident(name_c)?;
+                if !name.s.starts_with(char::is_uppercase) {
+                    return Err(InvalidAdtConstrIdent(name.pos, name.s));
+                }
+                let discriminant = match *discr_c {
+                    CST::Num(n, ref num_pos) => n.parse::<i64>()
+                        .map_err(|_| Expected(num_pos.clone(), "integer discriminant"))?,
+                    _ => return Err(Expected(discr_c.pos().clone(), "integer discriminant")),
+                };
+                Ok(AdtVariant {
+                    name: name,
+                    members: vec![],
+                    discriminant: Some(discriminant),
+                    pos: p.clone(),
+                })
+            }
             CST::SExpr(ref cs, ref p) => {
                 let (name_c, members_cs) = split_first(cs, p)?;
                 let name = ident(name_c)?;
@@ -881,6 +2109,7 @@ impl<'tvg, 's> Parser<'tvg, 's> {
                 Ok(AdtVariant {
                     name: name,
                     members: self.parse_data_type_variant_members(members_cs, &p.after(&name_pos))?,
+                    discriminant: None,
                     pos: p.clone(),
                 })
             }
@@ -893,110 +2122,337 @@ impl<'tvg, 's> Parser<'tvg, 's> {
         cs.iter().map(|c| self.parse_data_type_variant(c)).collect()
     }
 
+    /// Parse a `repr` attribute of a data type definition, e.g. `(repr C)` or `(repr UInt8)`
+    fn parse_data_type_repr(
+        &mut self,
+        csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, AdtRepr<'s>> {
+        match *one(csts, pos)? {
+            CST::Ident("C", _) => Ok(AdtRepr::C),
+            ref c => {
+                let t = self.parse_type(c)?;
+                if t.is_int() || t.is_uint() {
+                    Ok(AdtRepr::Int(t))
+                } else {
+                    Err(InvalidAdtRepr(c.pos().clone()))
+                }
+            }
+        }
+    }
+
+    /// Check that the discriminants of `def` are distinct, and fit in the representation type
+    fn check_data_type_discriminants(&self, def: &AdtDef<'s>) -> PRes<'s, ()> {
+        let mut seen: BTreeMap<i64, &SrcPos<'s>> = BTreeMap::new();
+        for (variant, d) in def.variants.iter().zip(def.discriminants()) {
+            if variant.discriminant.is_some() && !variant.members.is_empty() {
+                return Err(DiscriminantWithMembers(variant.pos.clone(), variant.name.s));
+            }
+            if let Some(AdtRepr::Int(ref t)) = def.repr {
+                // Be conservative with pointer sized integers, as the target is not yet known
+                let (min, max) = match (t.int_size(32), t.uint_size(32)) {
+                    (Some(64), _) => (i64::min_value(), i64::max_value()),
+                    (Some(n), _) => (-(1 << (n - 1)), (1 << (n - 1)) - 1),
+                    (_, Some(64)) => (0, i64::max_value()),
+                    (_, Some(n)) => (0, (1 << n) - 1),
+                    _ => unreachable!("ICE: repr type not integer in check_data_type_discriminants"),
+                };
+                if d < min || d > max {
+                    return Err(DiscriminantOutOfRange(variant.pos.clone(), d, t.clone()));
+                }
+            }
+            if let Some(prev_pos) = seen.insert(d, &variant.pos) {
+                return Err(DiscriminantDupl {
+                    pos: variant.pos.clone(),
+                    discriminant: d,
+                    prev_pos: prev_pos.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Parse a data type definition
+    ///
+    /// `(data NAME [(repr REPR)] VARIANTS...)`, e.g. `(data Color (repr UInt8) Red (= Green 4))`
     fn parse_data_type_def(&mut self, csts: &[CST<'s>], pos: &SrcPos<'s>) -> PRes<'s, AdtDef<'s>> {
-        let (name_c, variants_c) = split_first(csts, pos)?;
+        let (name_c, rest_c) = split_first(csts, pos)?;
         let name = ident(name_c)?;
         if !name.s.starts_with(char::is_uppercase) {
             return Err(InvalidAdtIdent(name.pos.clone(), name.s));
         }
-        Ok(AdtDef {
+        let (repr, variants_c) = match rest_c.split_first() {
+            Some((&CST::SExpr(ref attr, ref attr_pos), variants_c)) if head_is(attr, "repr") => {
+                let repr = self.parse_data_type_repr(&attr[1..], attr_pos)?;
+                (Some(repr), variants_c)
+            }
+            _ => (None, rest_c),
+        };
+        let def = AdtDef {
             name,
+            repr,
             variants: self.parse_data_type_variants(variants_c)?,
             pos: pos.clone(),
-        })
+        };
+        self.check_data_type_discriminants(&def)?;
+        Ok(def)
     }
 
     fn parse_data_type_defs(
         &mut self,
         defs_csts: &[(Vec<CST<'s>>, SrcPos<'s>)],
-    ) -> PRes<'s, BTreeMap<&'s str, AdtDef<'s>>> {
+    ) -> PResAll<'s, BTreeMap<&'s str, AdtDef<'s>>> {
         let mut datas = BTreeMap::new();
+        let mut errs = Vec::new();
         for &(ref def_csts, ref pos) in defs_csts {
-            let def = self.parse_data_type_def(def_csts, pos)?;
+            let def = match self.parse_data_type_def(def_csts, pos) {
+                Ok(def) => def,
+                Err(e) => {
+                    errs.push(e);
+                    continue;
+                }
+            };
             let def_pos = def.pos.clone();
             if let Some(prev_def) = datas.insert(def.name.s, def) {
-                return Err(DataTypeDuplDef {
+                errs.push(DataTypeDuplDef {
                     pos: def_pos,
                     name: prev_def.name.s,
                     prev_pos: prev_def.pos.clone(),
-                });
+                })
             }
         }
-        Ok(datas)
+        all_ok(datas, errs)
+    }
+
+    /// Parse a newtype definition
+    ///
+    /// `(newtype NAME TYPE [(deriving CONSTRAINTS...)])`, e.g.
+    /// `(newtype Meters Float64 (deriving Num))`
+    fn parse_newtype_def(
+        &mut self,
+        csts: &[CST<'s>],
+        pos: &SrcPos<'s>,
+    ) -> PRes<'s, NewtypeDef<'s>> {
+        let (name_c, rest) = split_first(csts, pos)?;
+        let name = ident(name_c)?;
+        if !name.s.starts_with(char::is_uppercase) {
+            return Err(InvalidNewtypeIdent(name.pos.clone(), name.s));
+        }
+        let (underlying_c, deriving_cs) = split_first(rest, &pos.after(&name.pos))?;
+        let underlying = self.parse_type(underlying_c)?;
+        let derives = match deriving_cs.first() {
+            None => BTreeSet::new(),
+            Some(&CST::SExpr(ref cs, _))
+                if deriving_cs.len() == 1 && head_is(cs, "deriving") =>
+            {
+                cs[1..]
+                    .iter()
+                    .map(|c| self.parse_constraint(c))
+                    .collect::<PRes<_>>()?
+            }
+            Some(c) => return Err(Expected(c.pos().clone(), "deriving clause")),
+        };
+        Ok(NewtypeDef {
+            name,
+            underlying,
+            derives,
+            pos: pos.clone(),
+        })
+    }
+
+    fn parse_newtype_defs(
+        &mut self,
+        defs_csts: &[(Vec<CST<'s>>, SrcPos<'s>)],
+    ) -> PResAll<'s, BTreeMap<&'s str, NewtypeDef<'s>>> {
+        let mut newtypes = BTreeMap::new();
+        let mut errs = Vec::new();
+        for &(ref def_csts, ref pos) in defs_csts {
+            let def = match self.parse_newtype_def(def_csts, pos) {
+                Ok(def) => def,
+                Err(e) => {
+                    errs.push(e);
+                    continue;
+                }
+            };
+            let def_pos = def.pos.clone();
+            if let Some(prev_def) = newtypes.insert(def.name.s, def) {
+                errs.push(DataTypeDuplDef {
+                    pos: def_pos,
+                    name: prev_def.name.s,
+                    prev_pos: prev_def.pos.clone(),
+                })
+            }
+        }
+        all_ok(newtypes, errs)
     }
 
     fn _get_top_level_csts<'c>(
         &mut self,
+        filename: &Path,
         csts: &'c [CST<'s>],
-        externs: &mut Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
-        globals: &mut Vec<(bool, Vec<CST<'s>>, SrcPos<'s>)>,
-        datas: &mut Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
+        top: &mut TopLevelCsts<'s>,
     ) -> PRes<'s, ()> {
         let mut imports_csts = Vec::new();
         for cst in csts {
             let pos = cst.pos();
-            let (first, rest) = split_first(sexpr(cst)?, cst.pos())?;
-            let first_s = ident_s(first)?;
+            // Bare expressions at the top level are never evaluated, so reject any item
+            // that isn't an s-expression headed by a known keyword
+            let (first_s, rest) = match *cst {
+                CST::SExpr(ref cs, _) => match cs.split_first() {
+                    Some((&CST::Ident(first_s, _), rest)) => (first_s, rest),
+                    _ => return Err(InvalidTopLevelItem(pos.clone())),
+                },
+                _ => return Err(InvalidTopLevelItem(pos.clone())),
+            };
             match first_s {
                 "import" => imports_csts.push((rest.to_vec(), pos)),
-                "extern" => externs.push((rest.to_vec(), pos.clone())),
-                "define" => globals.push((false, rest.to_vec(), pos.clone())),
-                "define:" => globals.push((true, rest.to_vec(), pos.clone())),
-                "data" => datas.push((rest.to_vec(), pos.clone())),
+                "extern" => top.externs.push((rest.to_vec(), pos.clone())),
+                ":" => top.signatures.push((rest.to_vec(), pos.clone())),
+                "define" | "define:" => push_global(top, first_s == "define:", rest, pos)?,
+                // A definition marked total, e.g. `(total define (f x) ...)`,
+                // has its termination checked
+                "total" => match rest.split_first() {
+                    Some((&CST::Ident(def_s, _), def_rest))
+                        if def_s == "define" || def_s == "define:" =>
+                    {
+                        top.totals.insert(global_name(def_rest, pos)?);
+                        push_global(top, def_s == "define:", def_rest, pos)?
+                    }
+                    _ => return Err(Expected(pos.clone(), "definition after `total`")),
+                },
+                "feature" => match (rest.len(), rest.first()) {
+                    (1, Some(&CST::Ident(name, _))) => {
+                        top.features.entry(name).or_insert(pos.clone());
+                    }
+                    _ => return Err(Expected(pos.clone(), "feature name")),
+                },
+                "data" => top.datas.push((rest.to_vec(), pos.clone())),
+                "newtype" => top.newtypes.push((rest.to_vec(), pos.clone())),
+                "infixl" => self.declare_fixities(rest, pos, Assoc::Left)?,
+                "infixr" => self.declare_fixities(rest, pos, Assoc::Right)?,
                 _ => return Err(InvalidTopLevelItem(pos.clone())),
             }
         }
         let imports = self.parse_imports(&imports_csts)?;
+        top.module_deps
+            .entry(filename.to_path_buf())
+            .or_insert(BTreeSet::new());
         // Recursively get top level csts of imported modules as well
         for (module_name, (_, _)) in imports {
             let module_path = CanonPathBuf::new(&format!("{}.kvs", module_name))
                 .expect("ICE: Failed to canonicalize module path");
+            top.module_deps
+                .get_mut(filename)
+                .expect("ICE: module missing in module_deps")
+                .insert(module_path.path().to_path_buf());
             if !self.sources.contains_key(&module_path) {
+                let import_path = module_path.path().to_path_buf();
                 let import_csts = lex_file(module_path, &self.sources);
-                self._get_top_level_csts(&import_csts, externs, globals, datas)?
+                self._get_top_level_csts(&import_path, &import_csts, top)?
             }
         }
         Ok(())
     }
 
-    /// Separate `csts` into token trees for externs, and globals
+    /// Separate `csts` into token trees for the different kinds of top level items
     ///
-    /// Recursively follow imports and get top level csts from there as well
+    /// Recursively follow imports and get top level csts from there as well.
+    /// Also records the import dependencies between the visited modules
     fn get_top_level_csts<'c>(
         &mut self,
+        filename: &Path,
         csts: &'c [CST<'s>],
-    ) -> PRes<
-        's,
-        (
-            Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
-            Vec<(bool, Vec<CST<'s>>, SrcPos<'s>)>,
-            Vec<(Vec<CST<'s>>, SrcPos<'s>)>,
-        ),
-    > {
-        let (mut externs, mut globals, mut datas) = (Vec::new(), Vec::new(), Vec::new());
-        self._get_top_level_csts(csts, &mut externs, &mut globals, &mut datas)?;
-        Ok((externs, globals, datas))
-    }
-
-    fn parse_ast(&mut self, csts: &[CST<'s>]) -> PRes<'s, Ast<'s>> {
-        let (externs_csts, globals_csts, datas_csts) = self.get_top_level_csts(csts)?;
-        let globals_csts_slc = globals_csts
+    ) -> PRes<'s, TopLevelCsts<'s>> {
+        let mut top = TopLevelCsts::default();
+        self._get_top_level_csts(filename, csts, &mut top)?;
+        Ok(top)
+    }
+
+    /// Lower the syntax trees `csts` of the file `filename`, and of its imports, to an AST
+    ///
+    /// The top level items are lowered separately, so that if there are errors in several of
+    /// them, all are reported
+    fn lower(&mut self, filename: &Path, csts: &[CST<'s>]) -> Result<Ast<'s>, Vec<PErr<'s>>> {
+        let mut top = self.get_top_level_csts(filename, csts)
+            .map_err(|e| vec![e])?;
+        self.alias_std_defs(&mut top);
+        let newtypes = self.parse_newtype_defs(&top.newtypes);
+        if newtypes.is_ok() {
+            for &(ref newtype_csts, ref pos) in &top.newtypes {
+                top.globals.push((
+                    true,
+                    newtype_constructor_csts(newtype_csts, pos),
+                    pos.clone(),
+                ));
+            }
+        }
+        let globals_csts_slc = top.globals
             .iter()
             .map(|&(is_typed, ref v, ref p)| (is_typed, v.as_slice(), p.clone()))
             .collect::<Vec<_>>();
-        Ok(Ast {
-            externs: self.parse_externs(&externs_csts)?,
-            globals: self.parse_bindings(&globals_csts_slc)?,
-            datas: self.parse_data_type_defs(&datas_csts)?,
-        })
+        let externs = self.parse_externs(&top.externs);
+        let signatures = self.parse_signatures(&top.signatures);
+        let globals = self.parse_bindings(&globals_csts_slc);
+        let datas = match (self.parse_data_type_defs(&top.datas), &newtypes) {
+            (Ok(datas), &Ok(ref newtypes)) => {
+                check_data_type_cycles(&datas, newtypes)
+                    .map(|_| datas)
+                    .map_err(|e| vec![e])
+            }
+            (datas, _) => datas,
+        };
+        match (externs, signatures, globals, datas, newtypes) {
+            (Ok(externs), Ok(signatures), Ok(globals), Ok(datas), Ok(newtypes)) => Ok(Ast {
+                externs,
+                signatures,
+                globals,
+                datas,
+                newtypes,
+                docs: top.docs,
+                totals: top.totals,
+                features: top.features,
+                type_holes: self.type_holes.drain(..).collect(),
+                module_deps: top.module_deps,
+            }),
+            (externs, signatures, globals, datas, newtypes) => Err(newtypes
+                .err()
+                .into_iter()
+                .chain(externs.err())
+                .chain(signatures.err())
+                .chain(globals.err())
+                .chain(datas.err())
+                .flat_map(|errs| errs)
+                .collect()),
+        }
     }
+}
 
-    /// Parse the file `filename`, and recursively parse imports as well
-    fn parse_file(&mut self, filename: CanonPathBuf) -> PRes<'s, Ast<'s>> {
-        let csts = lex_file(filename, &self.sources);
-        self.parse_ast(&csts)
-    }
+/// Lower the syntax trees `csts` of the source file `filename` to an Abstract Syntax Tree
+///
+/// Imported modules are read, lexed, and lowered as well. On failure, returns the errors
+/// that were found. Each top level definition is lowered up to its first error, so at most one
+/// error is reported per definition. An error in the top level structure itself, e.g. of an
+/// import, is reported alone.
+pub fn lower<'s>(
+    filename: &Path,
+    csts: &[CST<'s>],
+    sources: &'s AddMap<CanonPathBuf, String>,
+    type_var_gen: &mut TypeVarGen,
+) -> Result<Ast<'s>, Vec<PErr<'s>>> {
+    Parser::new(sources, type_var_gen).lower(filename, csts)
+}
+
+/// Lex and lower the program `src` of the source file `test.kvs`
+///
+/// The fixture of tests of the passes that take an Abstract Syntax Tree
+#[cfg(test)]
+pub fn lower_src<'s>(
+    src: &'s str,
+    sources: &'s AddMap<CanonPathBuf, String>,
+    type_var_gen: &mut TypeVarGen,
+) -> Result<Ast<'s>, Vec<PErr<'s>>> {
+    let filename = Path::new("test.kvs");
+    lower(filename, &lib::front::lex::lex_src(filename, src), sources, type_var_gen)
 }
 
 /// Returns the Abstract Syntax Tree of the program with entry point in `filename`
@@ -1009,9 +2465,12 @@ pub fn parse_program<'s>(
     sources: &'s AddMap<CanonPathBuf, String>,
     type_var_gen: &mut TypeVarGen,
 ) -> Ast<'s> {
-    let mut parser = Parser::new(sources, type_var_gen);
-    parser.parse_file(filename).unwrap_or_else(|e| {
-        e.print();
+    let path = filename.path().to_path_buf();
+    let csts = lex_file(filename, sources);
+    lower(&path, &csts, sources, type_var_gen).unwrap_or_else(|errs| {
+        for e in errs {
+            e.print();
+        }
         exit()
     })
 }
@@ -1025,7 +2484,9 @@ mod test {
     use lib::front::lex::CST;
     use lib::front::*;
     use lib::front::ast::*;
-    use super::Parser;
+    use lib::front::callgraph::global_refs;
+    use std::collections::BTreeSet;
+    use super::{lower_src, threaded_cst, PErr, Parser};
 
     fn dummy_cident(s: &str) -> CST {
         CST::Ident(s, SrcPos::new_dummy())
@@ -1053,10 +2514,12 @@ mod test {
                     s: "Foo",
                     pos: SrcPos::new_dummy(),
                 },
+                repr: None,
                 variants: vec![
                     AdtVariant {
                         name: dummy_ident("Foo"),
                         members: vec![],
+                        discriminant: None,
                         pos: SrcPos::new_dummy(),
                     },
                 ],
@@ -1064,4 +2527,161 @@ mod test {
             })
         )
     }
+
+    #[test]
+    fn test_parse_data_type_def_repr_discriminants() {
+        let sources = AddMap::new();
+        let mut tvg = TypeVarGen::new(0);
+        let mut parser = Parser::new(&sources, &mut tvg);
+        let def = parser
+            .parse_data_type_def(
+                &[
+                    dummy_cident("Color"),
+                    CST::SExpr(
+                        vec![dummy_cident("repr"), dummy_cident("UInt8")],
+                        SrcPos::new_dummy(),
+                    ),
+                    dummy_cident("Red"),
+                    CST::SExpr(
+                        vec![
+                            dummy_cident("="),
+                            dummy_cident("Green"),
+                            CST::Num("4", SrcPos::new_dummy()),
+                        ],
+                        SrcPos::new_dummy(),
+                    ),
+                    dummy_cident("Blue"),
+                ],
+                &SrcPos::new_dummy(),
+            )
+            .ok()
+            .expect("failed to parse data type definition");
+        assert_eq!(def.repr, Some(AdtRepr::Int(Type::Const("UInt8", None))));
+        assert_eq!(def.discriminants(), vec![0, 4, 5]);
+    }
+
+    /// Returns the names of the types of infinite size that `lower` reports for `src`
+    fn infinite_size_types(src: &str) -> Vec<String> {
+        let sources = AddMap::new();
+        let mut tvg = TypeVarGen::new(0);
+        match lower_src(src, &sources, &mut tvg) {
+            Ok(_) => vec![],
+            Err(es) => es.into_iter()
+                .filter_map(|e| match e {
+                    PErr::InfiniteSizeData { name, .. } => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_lower_reports_error_of_each_definition() {
+        let sources = AddMap::new();
+        let src = "(define 1 2) (define g (if)) (define h 2) (define h 3) (extern e)";
+        let mut tvg = TypeVarGen::new(0);
+        let errs = lower_src(src, &sources, &mut tvg)
+            .err()
+            .expect("lowered invalid program");
+        assert_eq!(errs.len(), 4);
+    }
+
+    #[test]
+    fn test_infinite_size_types() {
+        assert_eq!(infinite_size_types("(data L Nil (Node UInt8 L))"), vec!["L"]);
+        assert_eq!(infinite_size_types("(newtype N (Cons UInt8 N))"), vec!["N"]);
+        assert_eq!(infinite_size_types("(data D (D N)) (newtype N D)"), vec!["D"]);
+        assert!(infinite_size_types("(data L Nil (Node UInt8 (Ptr L)))").is_empty());
+        assert!(infinite_size_types("(newtype N (Ptr (Cons UInt8 N)))").is_empty());
+    }
+
+    #[test]
+    fn test_threaded_cst() {
+        let sexpr = |xs| CST::SExpr(xs, SrcPos::new_dummy());
+        let forms = vec![
+            dummy_cident("x"),
+            sexpr(vec![dummy_cident("f"), dummy_cident("a")]),
+            dummy_cident("g"),
+        ];
+        assert_eq!(
+            threaded_cst(&forms, &SrcPos::new_dummy(), false).ok(),
+            Some(sexpr(vec![
+                dummy_cident("g"),
+                sexpr(vec![dummy_cident("f"), dummy_cident("x"), dummy_cident("a")]),
+            ]))
+        );
+        assert_eq!(
+            threaded_cst(&forms, &SrcPos::new_dummy(), true).ok(),
+            Some(sexpr(vec![
+                dummy_cident("g"),
+                sexpr(vec![dummy_cident("f"), dummy_cident("a"), dummy_cident("x")]),
+            ]))
+        );
+    }
+
+    /// Definitions in place of those of the standard library that special forms translate to
+    const STD_DEFS: &'static str = "(define (show-num x) \"\") (define (string-append a b) a) \
+                                    (define (panic-at location msg) msg) (define (= a b) true) \
+                                    (define (assert-eq-failed-at location src a b) nil)\n";
+
+    /// Returns the messages of the errors of lowering `src`
+    fn lowering_errors(src: &str) -> Vec<String> {
+        let sources = AddMap::new();
+        let mut tvg = TypeVarGen::new(0);
+        match lower_src(src, &sources, &mut tvg) {
+            Ok(_) => vec![],
+            Err(es) => es.iter()
+                .map(|e| {
+                    let mut msg = Vec::new();
+                    e.write(&mut msg);
+                    String::from_utf8_lossy(&msg).into_owned()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_panic_refers_to_std_by_hidden_names() {
+        let sources = AddMap::new();
+        let src = format!(
+            "{}(define (f panic-at) (panic \"{{}} of {{}}\" panic-at 2))",
+            STD_DEFS
+        );
+        let mut tvg = TypeVarGen::new(0);
+        let ast = lower_src(&src, &sources, &mut tvg)
+            .unwrap_or_else(|_| panic!("Failed to parse `{}`", src));
+        let expected = ["std panic-at", "std show-num", "std string-append"];
+        assert_eq!(
+            global_refs(&ast)["f"],
+            expected.iter().cloned().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_panic_errors() {
+        let errs = lowering_errors("(define (f x) (panic \"{} of {}\" x 2))");
+        assert!(errs[0].contains("`panic` requires the standard library"));
+        let src = format!("{}(define (f x) (panic \"{{}} of {{}}\" x))", STD_DEFS);
+        let errs = lowering_errors(&src);
+        assert!(errs[0].contains("has 2 `{}` placeholders, but 1 arguments"));
+        let errs = lowering_errors(&format!("{}(define (f x) (panic x 2))", STD_DEFS));
+        assert!(errs[0].contains("Expected string literal message"));
+        assert!(lowering_errors(&format!("{}(define (f x) (panic x))", STD_DEFS)).is_empty());
+    }
+
+    #[test]
+    fn test_assert_eq_refers_to_std_by_hidden_names() {
+        let sources = AddMap::new();
+        let src = format!("{}(define (f = show-num) (assert-eq = show-num))", STD_DEFS);
+        let mut tvg = TypeVarGen::new(0);
+        let ast = lower_src(&src, &sources, &mut tvg)
+            .unwrap_or_else(|_| panic!("Failed to parse `{}`", src));
+        let expected = ["std =", "std show-num", "std assert-eq-failed-at"];
+        assert_eq!(
+            global_refs(&ast)["f"],
+            expected.iter().cloned().collect::<BTreeSet<_>>()
+        );
+        let errs = lowering_errors("(define (f x) (assert-eq x 1))");
+        assert!(errs[0].contains("`assert-eq` requires the standard library"));
+    }
 }
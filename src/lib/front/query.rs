@@ -0,0 +1,204 @@
+//! Queries over Concrete Syntax Trees
+//!
+//! A query is a pattern written in the same syntax as the trees it matches, e.g.
+//! `(define (_ ...) _)` to find all function definitions. In a pattern, `_` matches any
+//! single tree, and `...` in a list matches any number of elements, including none. All other
+//! atoms only match equal atoms, and lists match lists whose elements match pairwise.
+//!
+//! Saves lints and external tools from writing the traversal of the trees themselves.
+
+use super::error_exit;
+use super::cst_diff::cst_eq;
+use super::lex::{lex_src, CST};
+use std::path::Path;
+
+/// A match of a query
+#[derive(Debug)]
+pub struct QueryMatch<'c, 's: 'c> {
+    /// The matching tree
+    pub cst: &'c CST<'s>,
+    /// The nesting depth of the tree, where the trees queried are at depth 0
+    pub depth: usize,
+}
+
+/// A query over syntax trees
+pub struct Query<'q> {
+    pattern: CST<'q>,
+}
+
+impl<'q> Query<'q> {
+    /// Returns the query of the pattern in `src`
+    ///
+    /// Exits with an error if `src` is not exactly one tree
+    pub fn new(src: &'q str) -> Self {
+        let mut trees = lex_src(Path::new("<query>"), src);
+        if trees.len() != 1 {
+            error_exit(format!(
+                "Query `{}` must be exactly one syntax tree, found {}",
+                src,
+                trees.len()
+            ))
+        }
+        Query {
+            pattern: trees.remove(0),
+        }
+    }
+
+    /// Returns whether the tree `cst` itself matches the query
+    pub fn matches(&self, cst: &CST) -> bool {
+        matches(&self.pattern, cst)
+    }
+
+    /// Returns all trees in `csts` and their descendants that match the query, in pre-order
+    pub fn find_all<'c, 's>(&self, csts: &'c [CST<'s>]) -> Vec<QueryMatch<'c, 's>> {
+        let mut found = Vec::new();
+        for cst in csts {
+            self.collect_matches(cst, 0, &mut found)
+        }
+        found
+    }
+
+    /// Add the tree `cst` at `depth` and its descendants that match the query to `found`
+    fn collect_matches<'c, 's>(
+        &self,
+        cst: &'c CST<'s>,
+        depth: usize,
+        found: &mut Vec<QueryMatch<'c, 's>>,
+    ) {
+        if self.matches(cst) {
+            found.push(QueryMatch { cst, depth })
+        }
+        match *cst {
+            CST::SExpr(ref items, _) | CST::Braces(ref items, _) => for item in items {
+                self.collect_matches(item, depth + 1, found)
+            },
+            _ => (),
+        }
+    }
+}
+
+impl<'s> CST<'s> {
+    /// Returns all subtrees of this tree, including itself, that match the query `query`
+    ///
+    /// See `Query`
+    pub fn query<'c>(&'c self, query: &str) -> Vec<QueryMatch<'c, 's>> {
+        let mut found = Vec::new();
+        Query::new(query).collect_matches(self, 0, &mut found);
+        found
+    }
+}
+
+fn matches(pattern: &CST, cst: &CST) -> bool {
+    match (pattern, cst) {
+        (&CST::Ident("_", _), _) => true,
+        (&CST::SExpr(ref patterns, _), &CST::SExpr(ref items, _))
+        | (&CST::Braces(ref patterns, _), &CST::Braces(ref items, _)) => {
+            matches_seq(patterns, items)
+        }
+        _ => cst_eq(pattern, cst),
+    }
+}
+
+fn matches_seq(patterns: &[CST], items: &[CST]) -> bool {
+    match patterns.split_first() {
+        None => items.is_empty(),
+        Some((&CST::Ident("...", _), rest)) => {
+            (0..items.len() + 1).any(|i| matches_seq(rest, &items[i..]))
+        }
+        Some((pattern, rest)) => match items.split_first() {
+            Some((item, items_rest)) => matches(pattern, item) && matches_seq(rest, items_rest),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lib::front::lex::{lex_src, CST};
+    use std::path::Path;
+    use super::{matches_seq, Query};
+
+    fn trees(src: &'static str) -> Vec<CST<'static>> {
+        lex_src(Path::new("test.kvs"), src)
+    }
+
+    fn query_matches(query: &str, src: &'static str) -> bool {
+        Query::new(query).matches(&trees(src)[0])
+    }
+
+    #[test]
+    fn test_matches_seq() {
+        let items = trees("a b c\n");
+        let seq_matches = |patterns| matches_seq(&trees(patterns), &items);
+        assert!(seq_matches("a b c\n"));
+        assert!(!seq_matches("a b\n"));
+        assert!(!seq_matches("a b c d\n"));
+        assert!(!seq_matches("a c b\n"));
+        assert!(matches_seq(&[], &[]));
+        assert!(!matches_seq(&[], &items));
+    }
+
+    #[test]
+    fn test_ellipsis_matches_any_number_of_elements() {
+        let items = trees("a b c\n");
+        let seq_matches = |patterns| matches_seq(&trees(patterns), &items);
+        assert!(seq_matches("...\n"));
+        assert!(matches_seq(&trees("...\n"), &[]));
+        assert!(seq_matches("a ...\n"));
+        assert!(seq_matches("... c\n"));
+        assert!(seq_matches("a ... c\n"));
+        assert!(seq_matches("a b c ...\n"));
+        assert!(seq_matches("a ... b c\n"));
+        assert!(seq_matches("... b ...\n"));
+        assert!(!seq_matches("... a ... a\n"));
+        assert!(!seq_matches("... d\n"));
+        assert!(!seq_matches("a b c d ...\n"));
+    }
+
+    #[test]
+    fn test_underscore_matches_any_single_tree() {
+        assert!(query_matches("(f _)", "(f x)"));
+        assert!(query_matches("(f _)", "(f (g x))"));
+        assert!(query_matches("(f _)", "(f \"s\")"));
+        assert!(!query_matches("(f _)", "(f)"));
+        assert!(!query_matches("(f _)", "(f x y)"));
+        assert!(query_matches("(_ _ ...)", "(g 1 2)"));
+        assert!(query_matches("{_ ...}", "{a b}"));
+        assert!(!query_matches("{_ ...}", "(a b)"));
+    }
+
+    #[test]
+    fn test_atoms_match_equal_atoms() {
+        assert!(query_matches("(1 \"s\" x)", "(1 \"s\" x)"));
+        assert!(!query_matches("(1)", "(2)"));
+        assert!(!query_matches("(1)", "(x)"));
+        assert!(!query_matches("(\"x\")", "(x)"));
+    }
+
+    #[test]
+    fn test_nested_patterns() {
+        let query = "(define (_ ...) _)";
+        assert!(query_matches(query, "(define (f x y) (+ x y))"));
+        assert!(query_matches(query, "(define (main) 0)"));
+        assert!(!query_matches(query, "(define x 1)"));
+        assert!(!query_matches(query, "(define (f x) 1 2)"));
+    }
+
+    #[test]
+    fn test_find_all() {
+        let csts = trees("(define (f x) (g (f x)))\n(f y)\n");
+        let found = Query::new("(f _)")
+            .find_all(&csts)
+            .iter()
+            .map(|m| (m.cst.to_string(), m.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                ("(f x)".to_string(), 1),
+                ("(f x)".to_string(), 2),
+                ("(f y)".to_string(), 0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Reduction of programs to minimal reproductions of failures
+//!
+//! Given a program that makes some command fail, e.g. a crash of the compiler itself,
+//! repeatedly removes and simplifies parts of its syntax trees, keeping every change after
+//! which the command still fails in the same way. The search is a variant of delta debugging:
+//! elements of lists, starting with the top level items, are removed in chunks of decreasing
+//! size, and s-expressions are replaced by their own elements, until no change is kept.
+
+use super::error_exit;
+use super::lex::CST;
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A test of whether a candidate program still exhibits the failure being reduced
+pub struct Predicate {
+    /// The program and arguments of the command to run. The path of the candidate program
+    /// is appended as the last argument
+    pub command: Vec<String>,
+    /// The exit code of a failure. If `None`, any unsuccessful exit is a failure
+    pub exit_code: Option<i32>,
+    /// A message that must occur in the output of the command, on stdout or stderr
+    pub message: Option<String>,
+    /// The file that candidate programs are written to
+    pub candidate_path: PathBuf,
+}
+
+/// Write the tree `cst` as source code to `w`
+///
+/// String literals are written as they appear in the source, as the unescaped contents
+/// can't always be escaped again
+fn write_cst<W: Write>(cst: &CST, w: &mut W) -> io::Result<()> {
+    let (open, close, items) = match *cst {
+        CST::SExpr(ref items, _) => ("(", ")", items),
+        CST::Braces(ref items, _) => ("{", "}", items),
+        CST::Ident(s, _) | CST::Num(s, _) => return write!(w, "{}", s),
+        CST::Str(_, ref pos) => {
+            return write!(w, "{}", &pos.src[pos.start..pos.end.unwrap_or(pos.start)])
+        }
+    };
+    write!(w, "{}", open)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(w, " ")?;
+        }
+        write_cst(item, w)?;
+    }
+    write!(w, "{}", close)
+}
+
+/// Write the trees `csts` as the source code of a program to `w`, one top level item per line
+pub fn write_program<W: Write>(csts: &[CST], w: &mut W) -> io::Result<()> {
+    for cst in csts {
+        write_cst(cst, w)?;
+        writeln!(w, "")?;
+    }
+    w.flush()
+}
+
+/// Returns the list of trees at `path` in `csts`
+///
+/// The empty path is the top level, and each index of the path selects an element of the
+/// current list, which must in turn be a list
+fn list_at_mut<'a, 's>(csts: &'a mut Vec<CST<'s>>, path: &[usize]) -> &'a mut Vec<CST<'s>> {
+    match path.split_first() {
+        None => csts,
+        Some((&i, rest)) => match csts[i] {
+            CST::SExpr(ref mut items, _) | CST::Braces(ref mut items, _) => {
+                list_at_mut(items, rest)
+            }
+            _ => panic!("ICE: Path in `list_at_mut` does not lead to a list"),
+        },
+    }
+}
+
+/// Collect the paths of all lists in `csts`, including the top level, in pre-order
+fn collect_list_paths(csts: &[CST], path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+    paths.push(path.clone());
+    for (i, cst) in csts.iter().enumerate() {
+        match *cst {
+            CST::SExpr(ref items, _) | CST::Braces(ref items, _) => {
+                path.push(i);
+                collect_list_paths(items, path, paths);
+                path.pop();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Returns the path of the `n`th list in `csts`, in pre-order
+///
+/// Changes to a list or its descendants don't affect the pre-order index of the list itself,
+/// so the lists can be visited by index while the program is being reduced
+fn nth_list_path(csts: &[CST], n: usize) -> Option<Vec<usize>> {
+    let mut paths = Vec::new();
+    collect_list_paths(csts, &mut Vec::new(), &mut paths);
+    paths.into_iter().nth(n)
+}
+
+struct Reducer<'p> {
+    predicate: &'p Predicate,
+    /// The number of candidate programs tested
+    n_tests: usize,
+}
+
+impl<'p> Reducer<'p> {
+    /// Returns whether the program `csts` still fails according to the predicate
+    fn fails(&mut self, csts: &[CST]) -> bool {
+        let pred = self.predicate;
+        self.n_tests += 1;
+        File::create(&pred.candidate_path)
+            .and_then(|mut f| write_program(csts, &mut f))
+            .unwrap_or_else(|e| {
+                error_exit(format!(
+                    "Failed to write candidate program to `{}`, {}",
+                    pred.candidate_path.display(),
+                    e
+                ))
+            });
+        let output = Command::new(&pred.command[0])
+            .args(&pred.command[1..])
+            .arg(&pred.candidate_path)
+            .output()
+            .unwrap_or_else(|e| {
+                error_exit(format!("Failed to run command `{}`, {}", pred.command[0], e))
+            });
+        let failed = match pred.exit_code {
+            Some(code) => output.status.code() == Some(code),
+            None => !output.status.success(),
+        };
+        failed && pred.message.as_ref().map_or(true, |msg| {
+            String::from_utf8_lossy(&output.stdout).contains(msg.as_str())
+                || String::from_utf8_lossy(&output.stderr).contains(msg.as_str())
+        })
+    }
+
+    /// Remove chunks of the elements of the list at `path`, halving the chunk size each round
+    ///
+    /// Returns whether anything was removed
+    fn remove_chunks(&mut self, csts: &mut Vec<CST>, path: &[usize]) -> bool {
+        let mut changed = false;
+        let mut chunk = max(1, list_at_mut(csts, path).len() / 2);
+        loop {
+            let mut start = 0;
+            while start < list_at_mut(csts, path).len() {
+                let mut candidate = csts.clone();
+                {
+                    let list = list_at_mut(&mut candidate, path);
+                    let end = min(start + chunk, list.len());
+                    list.drain(start..end);
+                }
+                if self.fails(&candidate) {
+                    *csts = candidate;
+                    changed = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            if chunk == 1 {
+                return changed;
+            }
+            chunk /= 2;
+        }
+    }
+
+    /// Replace the list at `path` with one of its elements, if any element still fails
+    ///
+    /// Returns whether the list was replaced
+    fn hoist(&mut self, csts: &mut Vec<CST>, path: &[usize]) -> bool {
+        let (&last, init) = path.split_last().expect("ICE: Hoisting the top level");
+        let n_items = list_at_mut(csts, path).len();
+        for i in 0..n_items {
+            let mut candidate = csts.clone();
+            let item = list_at_mut(&mut candidate, path)[i].clone();
+            list_at_mut(&mut candidate, init)[last] = item;
+            if self.fails(&candidate) {
+                *csts = candidate;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Reduce the program `csts` until no more changes are kept
+    fn reduce(&mut self, csts: &mut Vec<CST>) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut n = 0;
+            while let Some(path) = nth_list_path(csts, n) {
+                changed |= self.remove_chunks(csts, &path);
+                n += 1;
+            }
+            // Skip the top level, which can't be hoisted
+            let mut n = 1;
+            while let Some(path) = nth_list_path(csts, n) {
+                if self.hoist(csts, &path) {
+                    // The list was replaced by one of its elements, which is visited next
+                    changed = true;
+                } else {
+                    n += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Returns a minimal version of the program `csts` that still fails according to `predicate`,
+/// and the number of tests it took to find
+///
+/// The returned program is also left in the candidate file of the predicate.
+/// Exits with an error if `csts` doesn't fail to begin with.
+pub fn reduce<'s>(csts: Vec<CST<'s>>, predicate: &Predicate) -> (Vec<CST<'s>>, usize) {
+    if predicate.command.is_empty() {
+        error_exit("Empty reduction command")
+    }
+    let mut reducer = Reducer {
+        predicate,
+        n_tests: 0,
+    };
+    let mut csts = csts;
+    if !reducer.fails(&csts) {
+        error_exit("The original program does not fail, so there is nothing to reduce")
+    }
+    reducer.reduce(&mut csts);
+    // Leave the result in the candidate file, rather than the last rejected candidate
+    reducer.fails(&csts);
+    (csts, reducer.n_tests)
+}
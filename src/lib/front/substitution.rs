@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
 use lib::front::ast::*;
 
 fn subst_poly<'src>(p: &Poly<'src>, s: &mut HashMap<u64, Type<'src>>) -> Poly<'src> {
@@ -90,6 +91,83 @@ pub fn subst_expr<'src>(e: &mut Expr<'src>, s: &mut HashMap<u64, Type<'src>>) {
             c.typ = subst(&c.typ, s);
             subst_expr(&mut c.expr, s);
         }
+        Expr::SizeOf(ref mut so) => so.queried = subst(&so.queried, s),
+        Expr::AlignOf(ref mut a) => a.queried = subst(&a.queried, s),
         Expr::Nil(_) | Expr::StrLit(_) | Expr::Bool(_) => (),
     }
 }
+
+/// Apply `f` to every type in `b`, including the instantiation types and
+/// definitions of its monomorphizations
+pub fn map_binding_types<'src, F>(b: &mut Binding<'src>, f: &mut F)
+where
+    F: FnMut(&Type<'src>) -> Type<'src>,
+{
+    b.typ = f(&b.typ);
+    map_expr_types(&mut b.val, f);
+    let mono_insts = mem::replace(&mut b.mono_insts, BTreeMap::new());
+    for (inst_ts, mut mono) in mono_insts {
+        map_expr_types(&mut mono, f);
+        b.mono_insts
+            .insert(inst_ts.iter().map(|t| f(t)).collect(), mono);
+    }
+}
+
+/// Apply `f` to every type in `e`
+pub fn map_expr_types<'src, F>(e: &mut Expr<'src>, f: &mut F)
+where
+    F: FnMut(&Type<'src>) -> Type<'src>,
+{
+    match *e {
+        Expr::NumLit(ref mut n) => n.typ = f(&n.typ),
+        Expr::StrLit(ref mut l) => l.typ = f(&l.typ),
+        Expr::Variable(ref mut bnd) => bnd.typ = f(&bnd.typ),
+        Expr::App(ref mut app) => {
+            map_expr_types(&mut app.func, f);
+            map_expr_types(&mut app.arg, f);
+            app.typ = f(&app.typ);
+        }
+        Expr::If(ref mut cond) => {
+            map_expr_types(&mut cond.predicate, f);
+            map_expr_types(&mut cond.consequent, f);
+            map_expr_types(&mut cond.alternative, f);
+            cond.typ = f(&cond.typ);
+        }
+        Expr::Lambda(ref mut l) => {
+            l.param_type = f(&l.param_type);
+            map_expr_types(&mut l.body, f);
+            l.typ = f(&l.typ);
+        }
+        Expr::Let(ref mut l) => {
+            for binding in l.bindings.bindings_mut() {
+                map_binding_types(binding, f);
+            }
+            map_expr_types(&mut l.body, f);
+            l.typ = f(&l.typ);
+        }
+        Expr::TypeAscript(ref mut a) => {
+            a.typ = f(&a.typ);
+            map_expr_types(&mut a.expr, f);
+        }
+        Expr::Cons(ref mut c) => {
+            c.typ = f(&c.typ);
+            map_expr_types(&mut c.car, f);
+            map_expr_types(&mut c.cdr, f);
+        }
+        Expr::Car(ref mut c) => {
+            c.typ = f(&c.typ);
+            map_expr_types(&mut c.expr, f);
+        }
+        Expr::Cdr(ref mut c) => {
+            c.typ = f(&c.typ);
+            map_expr_types(&mut c.expr, f);
+        }
+        Expr::Cast(ref mut c) => {
+            c.typ = f(&c.typ);
+            map_expr_types(&mut c.expr, f);
+        }
+        Expr::SizeOf(ref mut so) => so.queried = f(&so.queried),
+        Expr::AlignOf(ref mut a) => a.queried = f(&a.queried),
+        Expr::Nil(_) | Expr::Bool(_) => (),
+    }
+}
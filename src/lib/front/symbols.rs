@@ -0,0 +1,481 @@
+//! Cross-referenced index of the symbols of a program
+//!
+//! Collects every definition and every variable reference, together with the definition each
+//! reference resolves to. The index can be serialized as JSON, to be used by external tools
+//! for stuff like ctags generation or code search.
+//!
+//! The index also backs refactorings, like renaming a symbol, which are returned as text edits
+//! for an editor or other tool to apply.
+
+use super::SrcPos;
+use super::ast::*;
+use super::lex::CST;
+use super::parse::is_std_alias;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::ptr;
+
+/// The kind of a symbol definition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// An external variable declaration
+    Extern,
+    /// A global variable or function definition
+    Global,
+    /// A binding of a `let` form
+    Local,
+    /// A lambda parameter
+    Param,
+    /// An algebraic data type
+    Data,
+    /// A variant of an algebraic data type
+    Variant,
+    /// A newtype
+    Newtype,
+}
+
+impl SymbolKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            SymbolKind::Extern => "extern",
+            SymbolKind::Global => "global",
+            SymbolKind::Local => "local",
+            SymbolKind::Param => "param",
+            SymbolKind::Data => "data",
+            SymbolKind::Variant => "variant",
+            SymbolKind::Newtype => "newtype",
+        }
+    }
+}
+
+/// The definition of a symbol
+#[derive(Clone, Debug)]
+pub struct Definition<'src> {
+    pub name: &'src str,
+    pub kind: SymbolKind,
+    pub pos: SrcPos<'src>,
+    /// The docstring of the definition, if any
+    pub doc: Option<String>,
+}
+
+/// A reference to a symbol
+#[derive(Clone, Debug)]
+pub struct Reference<'src> {
+    pub name: &'src str,
+    pub pos: SrcPos<'src>,
+    /// The index of the definition that this reference resolves to, if any
+    pub target: Option<usize>,
+}
+
+/// An index of all definitions and references of a program
+#[derive(Clone, Debug)]
+pub struct SymbolIndex<'src> {
+    pub defs: Vec<Definition<'src>>,
+    pub refs: Vec<Reference<'src>>,
+}
+
+/// Escape `s` as a JSON string literal
+fn json_str(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            _ if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_span(pos: &SrcPos) -> String {
+    let (_, _, row, col) = pos.line_len_row_col();
+    format!(
+        "{{\"file\": {}, \"line\": {}, \"column\": {}, \"start\": {}, \"end\": {}}}",
+        json_str(&pos.filename.to_string_lossy()),
+        row,
+        col,
+        pos.start,
+        pos.end.unwrap_or(pos.start)
+    )
+}
+
+impl<'src> SymbolIndex<'src> {
+    /// Write the index as a JSON object of definitions and references to `w`
+    ///
+    /// The `target` of a reference is the `id` of the definition it resolves to,
+    /// or `null` if unresolved.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"definitions\": [")?;
+        for (i, def) in self.defs.iter().enumerate() {
+            writeln!(
+                w,
+                "    {{\"id\": {}, \"name\": {}, \"kind\": \"{}\", \"span\": {}, \"doc\": {}}}{}",
+                i,
+                json_str(def.name),
+                def.kind.as_str(),
+                json_span(&def.pos),
+                def.doc
+                    .as_ref()
+                    .map(|d| json_str(d))
+                    .unwrap_or("null".to_string()),
+                if i + 1 < self.defs.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(w, "  ],")?;
+        writeln!(w, "  \"references\": [")?;
+        for (i, r) in self.refs.iter().enumerate() {
+            writeln!(
+                w,
+                "    {{\"name\": {}, \"span\": {}, \"target\": {}}}{}",
+                json_str(r.name),
+                json_span(&r.pos),
+                r.target
+                    .map(|t| t.to_string())
+                    .unwrap_or("null".to_string()),
+                if i + 1 < self.refs.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(w, "  ]")?;
+        writeln!(w, "}}")
+    }
+}
+
+/// A replacement of the source text at `pos` with `text`
+#[derive(Clone, Debug)]
+pub struct TextEdit<'src> {
+    pub pos: SrcPos<'src>,
+    pub text: String,
+}
+
+/// Returns whether `pos` covers the 1-indexed `line` and `column` of the source file `file`
+fn pos_covers(pos: &SrcPos, file: &Path, line: usize, column: usize) -> bool {
+    if pos.filename() != file {
+        return false;
+    }
+    let (_, _, row, col) = pos.line_len_row_col();
+    let len = pos.end.unwrap_or(pos.start + 1) - pos.start;
+    row == line && col <= column && column < col + len
+}
+
+fn is_global(kind: SymbolKind) -> bool {
+    match kind {
+        SymbolKind::Local | SymbolKind::Param => false,
+        _ => true,
+    }
+}
+
+/// Returns whether `name` could be written as an identifier in the source
+fn is_valid_ident(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(|c: char| c.is_digit(10))
+        && !name.chars()
+            .any(|c| c.is_whitespace() || "()[]{};\"'`,".contains(c))
+}
+
+impl<'src> SymbolIndex<'src> {
+    /// Returns the id of the definition of the symbol at `line` and `column` of `file`,
+    /// where the symbol is either the definition itself or a reference to it
+    ///
+    /// Symbols introduced by desugaring, which can't be written in source, are skipped
+    pub fn definition_at(&self, file: &Path, line: usize, column: usize) -> Option<usize> {
+        let at = |name: &str, pos: &SrcPos| {
+            is_valid_ident(name) && pos_covers(pos, file, line, column)
+        };
+        self.defs
+            .iter()
+            .position(|def| at(def.name, &def.pos))
+            .or_else(|| {
+                self.refs
+                    .iter()
+                    .find(|r| at(r.name, &r.pos))
+                    .and_then(|r| r.target)
+            })
+    }
+
+    /// Returns the edits that rename the definition `def`, and all references that resolve
+    /// to it, to `new_name`
+    ///
+    /// Returns an error if `new_name` is not a valid identifier, or if the definition is
+    /// global and there already is a global of the same name. Capture of the new name by
+    /// local definitions is not checked.
+    pub fn rename(&self, def: usize, new_name: &str) -> Result<Vec<TextEdit<'src>>, String> {
+        if !is_valid_ident(new_name) {
+            return Err(format!("Invalid identifier `{}`", new_name));
+        }
+        let target = &self.defs[def];
+        if is_global(target.kind)
+            && self.defs
+                .iter()
+                .any(|d| d.name == new_name && is_global(d.kind))
+        {
+            return Err(format!("`{}` is already defined", new_name));
+        }
+        let mut edits = vec![
+            TextEdit {
+                pos: target.pos.clone(),
+                text: new_name.to_string(),
+            },
+        ];
+        for r in self.refs.iter().filter(|r| r.target == Some(def)) {
+            edits.push(TextEdit {
+                pos: r.pos.clone(),
+                text: new_name.to_string(),
+            })
+        }
+        Ok(edits)
+    }
+}
+
+/// Returns the outermost tree of `cst` and its descendants that starts at `line` and `column`
+fn subtree_at<'c, 's>(cst: &'c CST<'s>, line: usize, column: usize) -> Option<&'c CST<'s>> {
+    let (_, _, row, col) = cst.pos().line_len_row_col();
+    if row == line && col == column {
+        return Some(cst);
+    }
+    match *cst {
+        CST::SExpr(ref items, _) | CST::Braces(ref items, _) => items
+            .iter()
+            .filter_map(|item| subtree_at(item, line, column))
+            .next(),
+        _ => None,
+    }
+}
+
+impl<'src> SymbolIndex<'src> {
+    /// Returns the edits that extract the expression starting at `line` and `column` of the
+    /// syntax trees `csts` of a module into a new global definition `name`
+    ///
+    /// The variables of the expression that are bound by local definitions outside of it
+    /// become the parameters of the new definition, which is inserted before the top level
+    /// item of the expression. The expression itself is replaced by an application of the new
+    /// definition to those variables, or just by its name if there are none.
+    pub fn extract_definition<'s>(
+        &self,
+        csts: &[CST<'s>],
+        line: usize,
+        column: usize,
+        name: &str,
+    ) -> Result<Vec<TextEdit<'s>>, String> {
+        if !is_valid_ident(name) {
+            return Err(format!("Invalid identifier `{}`", name));
+        }
+        if self.defs
+            .iter()
+            .any(|d| d.name == name && is_global(d.kind))
+        {
+            return Err(format!("`{}` is already defined", name));
+        }
+        let (expr, top) = csts.iter()
+            .filter_map(|top| subtree_at(top, line, column).map(|expr| (expr, top)))
+            .next()
+            .ok_or_else(|| format!("No expression at {}:{}", line, column))?;
+        if ptr::eq(expr, top) {
+            return Err("Can't extract a top level item".to_string());
+        }
+        let pos = expr.pos();
+        let (start, end) = (pos.start, pos.end.unwrap_or(pos.start + 1));
+        let inside = |p: &SrcPos| p.filename == pos.filename && start <= p.start && p.start < end;
+        let mut params = Vec::new();
+        for target in self.refs
+            .iter()
+            .filter(|r| inside(&r.pos))
+            .filter_map(|r| r.target)
+        {
+            let def = &self.defs[target];
+            if is_global(def.kind) || inside(&def.pos) || params.contains(&def.name) {
+                continue;
+            } else if !is_valid_ident(def.name) {
+                return Err(format!(
+                    "The expression uses the variable `{}`, introduced by desugaring, \
+                     which can't be passed as a parameter",
+                    def.name
+                ));
+            }
+            params.push(def.name)
+        }
+        let body = &pos.src[start..end];
+        let (definition, call) = if params.is_empty() {
+            (format!("(define {}\n  {})\n\n", name, body), name.to_string())
+        } else {
+            let params = params.join(" ");
+            (
+                format!("(define ({} {})\n  {})\n\n", name, params, body),
+                format!("({} {})", name, params),
+            )
+        };
+        let top_pos = top.pos();
+        Ok(vec![
+            TextEdit {
+                pos: SrcPos::new_interval(
+                    top_pos.filename,
+                    top_pos.src,
+                    top_pos.start,
+                    top_pos.start,
+                ),
+                text: definition,
+            },
+            TextEdit {
+                pos: pos.clone(),
+                text: call,
+            },
+        ])
+    }
+}
+
+/// Write the text edits `edits` as a JSON array of spans and replacement texts to `w`
+pub fn write_edits_json<W: Write>(edits: &[TextEdit], w: &mut W) -> io::Result<()> {
+    writeln!(w, "[")?;
+    for (i, edit) in edits.iter().enumerate() {
+        writeln!(
+            w,
+            "  {{\"span\": {}, \"text\": {}}}{}",
+            json_span(&edit.pos),
+            json_str(&edit.text),
+            if i + 1 < edits.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(w, "]")
+}
+
+struct Indexer<'src> {
+    index: SymbolIndex<'src>,
+    /// For each name, a stack of the definitions currently in scope
+    scopes: HashMap<&'src str, Vec<usize>>,
+}
+
+impl<'src> Indexer<'src> {
+    fn new() -> Self {
+        Indexer {
+            index: SymbolIndex {
+                defs: Vec::new(),
+                refs: Vec::new(),
+            },
+            scopes: HashMap::new(),
+        }
+    }
+
+    /// Add a definition to the index and bring it into scope
+    fn define(&mut self, name: &'src str, kind: SymbolKind, pos: &SrcPos<'src>) {
+        let n = self.index.defs.len();
+        self.index.defs.push(Definition {
+            name,
+            kind,
+            pos: pos.clone(),
+            doc: None,
+        });
+        self.scopes.entry(name).or_insert(Vec::new()).push(n)
+    }
+
+    fn undefine(&mut self, name: &str) {
+        self.scopes
+            .get_mut(name)
+            .and_then(|v| v.pop())
+            .expect("ICE: definition gone from scope in undefine");
+    }
+
+    fn index_variable(&mut self, var: &Variable<'src>) {
+        // References from translated special forms to the standard library are not in source
+        if is_std_alias(var.ident.s) {
+            return;
+        }
+        let target = self.scopes
+            .get(var.ident.s)
+            .and_then(|v| v.last())
+            .cloned();
+        self.index.refs.push(Reference {
+            name: var.ident.s,
+            pos: var.ident.pos.clone(),
+            target,
+        })
+    }
+
+    fn index_let(&mut self, l: &Let<'src>) {
+        for b in l.bindings.bindings() {
+            self.define(b.ident.s, SymbolKind::Local, &b.ident.pos);
+        }
+        for b in l.bindings.bindings() {
+            self.index_expr(&b.val);
+        }
+        self.index_expr(&l.body);
+        for id in l.bindings.ids() {
+            self.undefine(id);
+        }
+    }
+
+    fn index_expr(&mut self, e: &Expr<'src>) {
+        match *e {
+            Expr::Variable(ref var) => self.index_variable(var),
+            Expr::App(ref app) => {
+                self.index_expr(&app.func);
+                self.index_expr(&app.arg);
+            }
+            Expr::If(ref cond) => {
+                self.index_expr(&cond.predicate);
+                self.index_expr(&cond.consequent);
+                self.index_expr(&cond.alternative);
+            }
+            Expr::Lambda(ref lam) => {
+                self.define(lam.param_ident.s, SymbolKind::Param, &lam.param_ident.pos);
+                self.index_expr(&lam.body);
+                self.undefine(lam.param_ident.s);
+            }
+            Expr::Let(ref l) => self.index_let(l),
+            Expr::TypeAscript(ref a) => self.index_expr(&a.expr),
+            Expr::Cons(ref c) => {
+                self.index_expr(&c.car);
+                self.index_expr(&c.cdr);
+            }
+            Expr::Car(ref c) => self.index_expr(&c.expr),
+            Expr::Cdr(ref c) => self.index_expr(&c.expr),
+            Expr::Cast(ref c) => self.index_expr(&c.expr),
+            Expr::Nil(_)
+            | Expr::NumLit(_)
+            | Expr::StrLit(_)
+            | Expr::Bool(_)
+            | Expr::SizeOf(_)
+            | Expr::AlignOf(_) => (),
+        }
+    }
+}
+
+/// Returns an index of all definitions and references in `ast`
+///
+/// Global definitions, externs, data types, and newtypes are in scope in the whole program,
+/// while `let` bindings and lambda parameters are only in scope in their bodies.
+pub fn index_symbols<'src>(ast: &Ast<'src>) -> SymbolIndex<'src> {
+    let mut indexer = Indexer::new();
+    for data in ast.datas.values() {
+        indexer.define(data.name.s, SymbolKind::Data, &data.name.pos);
+        for variant in &data.variants {
+            indexer.define(variant.name.s, SymbolKind::Variant, &variant.name.pos);
+        }
+    }
+    for nt in ast.newtypes.values() {
+        indexer.define(nt.name.s, SymbolKind::Newtype, &nt.name.pos);
+    }
+    for ext in ast.externs.values() {
+        indexer.define(ext.ident.s, SymbolKind::Extern, &ext.ident.pos);
+    }
+    let globals = ast.globals
+        .bindings()
+        .filter(|b| !is_std_alias(b.ident.s))
+        .collect::<Vec<_>>();
+    for b in &globals {
+        indexer.define(b.ident.s, SymbolKind::Global, &b.ident.pos);
+        if let Some(doc) = ast.docs.get(b.ident.s) {
+            if let Some(def) = indexer.index.defs.last_mut() {
+                def.doc = Some(doc.clone())
+            }
+        }
+    }
+    for b in &globals {
+        indexer.index_expr(&b.val);
+    }
+    indexer.index
+}
@@ -0,0 +1,285 @@
+//! Termination checking of global definitions marked as total
+//!
+//! A definition marked total, e.g. `(total define (length xs) ...)`, must be structurally
+//! recursive: there must be one parameter for which every recursive call passes an argument
+//! that is structurally smaller than the value of the parameter, i.e. a `car` or `cdr` of it,
+//! possibly through several layers and `let` bindings. As lists are finite, such recursion
+//! terminates. It's not enough that each call shrinks some parameter, as calls that shrink
+//! different parameters may grow the others back.
+//! Useful for code that is to be evaluated at compile time.
+//!
+//! Calls of other definitions are assumed to terminate. Mutual recursion is not supported,
+//! and neither is using the definition as a value, e.g. passing it to `map`, as the calls
+//! made through the value can't be followed.
+
+use super::ast::*;
+
+/// What is known of the size of a local variable, relative to the parameters of the
+/// definition being checked
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Size {
+    /// The variable is the parameter of the given index
+    Param(usize),
+    /// The variable is structurally smaller than the parameter of the given index
+    Smaller(usize),
+}
+
+fn smaller(size: Size) -> Size {
+    match size {
+        Size::Param(i) | Size::Smaller(i) => Size::Smaller(i),
+    }
+}
+
+/// A recursive call of the definition being checked
+struct RecursiveCall<'src> {
+    pos: SrcPos<'src>,
+    /// The indices of the parameters that the call passes structurally smaller arguments for
+    decreasing: Vec<usize>,
+}
+
+struct Checker<'src> {
+    /// The name of the definition being checked
+    name: &'src str,
+    /// The number of parameters of the definition
+    n_params: usize,
+    /// The local variables in scope, innermost last, and what is known of their sizes
+    scope: Vec<(&'src str, Option<Size>)>,
+    /// The recursive calls found so far
+    calls: Vec<RecursiveCall<'src>>,
+    ok: bool,
+}
+
+impl<'src> Checker<'src> {
+    /// Returns the size of the local variable `name`, or `None` if it's not a local
+    fn lookup(&self, name: &str) -> Option<Option<Size>> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|&&(s, _)| s == name)
+            .map(|&(_, size)| size)
+    }
+
+    /// Returns what is known of the size of `e`
+    fn size_of(&self, e: &Expr) -> Option<Size> {
+        match *e {
+            Expr::Variable(ref var) => self.lookup(var.ident.s).and_then(|size| size),
+            Expr::Car(ref c) => self.size_of(&c.expr).map(smaller),
+            Expr::Cdr(ref c) => self.size_of(&c.expr).map(smaller),
+            Expr::TypeAscript(ref a) => self.size_of(&a.expr),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `e` refers to the definition being checked
+    fn is_recursive_ref(&self, e: &Expr) -> bool {
+        match *e {
+            Expr::Variable(ref var) => {
+                var.ident.s == self.name && self.lookup(self.name).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    fn check_app(&mut self, e: &Expr<'src>) {
+        let mut args = Vec::new();
+        let mut func = e;
+        while let Expr::App(ref app) = *func {
+            args.push(&app.arg);
+            func = &app.func;
+        }
+        args.reverse();
+        if self.is_recursive_ref(func) {
+            let decreasing = args.iter()
+                .take(self.n_params)
+                .enumerate()
+                .filter(|&(i, arg)| self.size_of(arg) == Some(Size::Smaller(i)))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if decreasing.is_empty() {
+                e.pos().print_error(format!(
+                    "Recursive call of total definition `{}` may not terminate",
+                    self.name
+                ));
+                e.pos().print_help(
+                    "No argument is structurally smaller than the corresponding parameter, \
+                     i.e. a `car` or `cdr` of it",
+                );
+                self.ok = false
+            }
+            self.calls.push(RecursiveCall {
+                pos: e.pos().clone(),
+                decreasing,
+            })
+        } else {
+            self.check(func)
+        }
+        for arg in args {
+            self.check(arg)
+        }
+    }
+
+    fn check(&mut self, e: &Expr<'src>) {
+        match *e {
+            Expr::Variable(_) if self.is_recursive_ref(e) => {
+                e.pos().print_error(format!(
+                    "Total definition `{}` is used as a value, \
+                     so the termination of calls through it can't be checked",
+                    self.name
+                ));
+                self.ok = false
+            }
+            Expr::App(_) => self.check_app(e),
+            Expr::If(ref cond) => {
+                self.check(&cond.predicate);
+                self.check(&cond.consequent);
+                self.check(&cond.alternative);
+            }
+            Expr::Lambda(ref lam) => {
+                self.scope.push((lam.param_ident.s, None));
+                self.check(&lam.body);
+                self.scope.pop();
+            }
+            Expr::Let(ref l) => {
+                let n_scope = self.scope.len();
+                // Visit dependencies before dependents, so that their sizes are known
+                for b in l.bindings.bindings().rev() {
+                    self.check(&b.val);
+                    let size = self.size_of(&b.val);
+                    self.scope.push((b.ident.s, size));
+                }
+                self.check(&l.body);
+                self.scope.truncate(n_scope);
+            }
+            Expr::TypeAscript(ref a) => self.check(&a.expr),
+            Expr::Cons(ref c) => {
+                self.check(&c.car);
+                self.check(&c.cdr);
+            }
+            Expr::Car(ref c) => self.check(&c.expr),
+            Expr::Cdr(ref c) => self.check(&c.expr),
+            Expr::Cast(ref c) => self.check(&c.expr),
+            Expr::Variable(_)
+            | Expr::Nil(_)
+            | Expr::NumLit(_)
+            | Expr::StrLit(_)
+            | Expr::Bool(_)
+            | Expr::SizeOf(_)
+            | Expr::AlignOf(_) => (),
+        }
+    }
+}
+
+/// Check that the global definition `b` is structurally recursive
+fn check_binding(b: &Binding) -> bool {
+    let mut params = Vec::new();
+    let mut body = &b.val;
+    loop {
+        match *body {
+            Expr::Lambda(ref lam) => {
+                params.push(lam.param_ident.s);
+                body = &lam.body
+            }
+            Expr::TypeAscript(ref a) => body = &a.expr,
+            _ => break,
+        }
+    }
+    let mut checker = Checker {
+        name: b.ident.s,
+        n_params: params.len(),
+        scope: params
+            .into_iter()
+            .enumerate()
+            .map(|(i, param)| (param, Some(Size::Param(i))))
+            .collect(),
+        calls: Vec::new(),
+        ok: true,
+    };
+    checker.check(body);
+    if !checker.ok {
+        return false;
+    }
+    // Every call shrinks some parameter, but one parameter must shrink in all of them
+    let n_params = checker.n_params;
+    let calls = &checker.calls;
+    let decreasing_in_all = (0..n_params).any(|i| calls.iter().all(|c| c.decreasing.contains(&i)));
+    if !calls.is_empty() && !decreasing_in_all {
+        b.pos.print_error(format!(
+            "Recursive calls of total definition `{}` may not terminate",
+            b.ident.s
+        ));
+        b.pos.print_help(
+            "No single parameter is passed a structurally smaller argument in every \
+             recursive call",
+        );
+        for call in calls {
+            call.pos.print_note("A recursive call is here");
+        }
+        return false;
+    }
+    true
+}
+
+/// Check the termination of all global definitions of `ast` that are marked as total,
+/// reporting the definitions that may not terminate
+///
+/// Returns whether all of them were found to terminate
+pub fn check_termination(ast: &Ast) -> bool {
+    let mut ok = true;
+    for group in ast.globals.groups() {
+        let bindings = group.bindings().collect::<Vec<_>>();
+        for b in bindings.iter().filter(|b| ast.totals.contains(b.ident.s)) {
+            if bindings.len() > 1 {
+                b.pos.print_error(format!(
+                    "Termination of total definition `{}` can't be checked, \
+                     as it's mutually recursive with other definitions",
+                    b.ident.s
+                ));
+                ok = false
+            } else {
+                ok &= check_binding(b)
+            }
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod test {
+    use lib::collections::AddMap;
+    use lib::front::TypeVarGen;
+    use lib::front::parse::lower_src;
+    use super::check_termination;
+
+    /// Returns whether all total definitions of the program `src` are found to terminate
+    fn terminates(src: &str) -> bool {
+        let sources = AddMap::new();
+        let mut tvg = TypeVarGen::new(0);
+        let ast = lower_src(src, &sources, &mut tvg)
+            .unwrap_or_else(|_| panic!("Failed to parse `{}`", src));
+        check_termination(&ast)
+    }
+
+    #[test]
+    fn test_structural_recursion_terminates() {
+        assert!(terminates("(total define (f xs) (if true xs (f (cdr xs))))"));
+        assert!(terminates(
+            "(total define (f xs) (if true xs (let ((r (cdr xs)) (rr (cdr r))) (f rr))))"
+        ));
+        assert!(terminates("(total define (f n xs) (if true xs (f (cons n n) (car xs))))"));
+        assert!(terminates(
+            "(total define (f xs ys) (if true xs (if true (f (cdr xs) ys) (f (cdr xs) (cdr ys)))))"
+        ));
+    }
+
+    #[test]
+    fn test_non_structural_recursion_may_not_terminate() {
+        assert!(!terminates("(total define (f xs) (f xs))"));
+        assert!(!terminates("(total define (f xs) (f (cons 1 xs)))"));
+        assert!(!terminates("(total define (f xs) (let ((r xs)) (f r)))"));
+        assert!(!terminates("(total define (f xs) (let ((g f)) (g (cdr xs))))"));
+        assert!(!terminates(
+            "(total define (f xs ys) \
+               (if (nil? xs) (f (cons 1 xs) (cdr ys)) (f (cdr xs) (cons 1 ys))))"
+        ));
+    }
+}
@@ -0,0 +1,1517 @@
+//! A tree-walking interpreter
+//!
+//! Directly evaluates the expressions of a type checked AST, without any backend.
+//! The arithmetic and relational primitives, e.g. `add` and `eq`, are evaluated as builtins,
+//! in the same way as they are special cased in codegen. Of the external functions, only
+//! the functions of the core library are supported.
+//!
+//! All integers are represented as `Int64`, and all floats as `Float64`, so arithmetic on the
+//! other numeric types only wraps or rounds when the result is cast, and unsigned values of
+//! 2^63 and above compare and divide as negative numbers.
+//!
+//! Strings and byte strings share a representation in compiled code, and so a string is also
+//! the value of bytes that are valid UTF-8. Only other bytes are of the separate bytes value.
+//!
+//! The values of global constants are evaluated at compile time by the same interpreter,
+//! and handed to the backends in a `ConstEnv`. Only constants computed entirely in the types
+//! that the interpreter represents exactly are evaluated, so that compile time and run time
+//! semantics are identical.
+//!
+//! With coverage enabled, the interpreter counts the evaluations of each expression, which can
+//! then be written as a report in the lcov format, e.g. to be turned into HTML by `genhtml`.
+
+#[cfg(unix)]
+use libc;
+use lib::front::{error_exit, SrcPos};
+use lib::front::ast::*;
+#[cfg(unix)]
+use lib::front::lint::regex_group_count;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Write};
+#[cfg(unix)]
+use std::mem;
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::u16;
+
+/// A builtin function
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    /// A primitive binary operation on a pair, e.g. `add`
+    Binop(&'static str),
+    /// A supported external function of the core library, e.g. `print_int64`
+    Extern(&'static str),
+}
+
+const BINOPS: &[&str] = &[
+    "add", "sub", "mul", "div", "eq", "neq", "gt", "gteq", "lt", "lteq", "and", "or", "xor",
+];
+
+const EXTERNS: &[&str] = &[
+    "c_display",
+    "print_int64",
+    "print_uint64",
+    "print_float64",
+    "read_int64",
+    "read_uint64",
+    "show_int64",
+    "show_uint64",
+    "show_float64",
+    "can_parse_int64",
+    "parse_int64",
+    "can_parse_float64",
+    "parse_float64",
+    "read_line",
+    "read_file",
+    "write_file",
+    "io_failed",
+    "log_message",
+    "run_command",
+    "tcp_connect",
+    "tcp_read",
+    "tcp_write",
+    "tcp_close",
+    "http_get",
+    "http_status",
+    "program_arg_count",
+    "program_arg",
+    "has_env",
+    "get_env",
+    "now_millis",
+    "monotonic_nanos",
+    "days_from_civil",
+    "format_timestamp",
+    "splitmix64",
+    "panic_at",
+    "assert_eq_failed_at",
+    "string_length",
+    "hex_to_bytes",
+    "byte_at",
+    "is_utf8",
+    "is_valid_regex",
+    "compile_regex",
+    "regex_is_match",
+    "regex_group_count",
+    "regex_has_group",
+    "regex_group",
+    "can_slice_string",
+    "slice_string",
+    "split_line",
+    "concat_strings",
+    "join_path",
+    "path_exists",
+    "is_dir",
+    "list_dir",
+    "create_dir",
+    "canonicalize_path",
+];
+
+thread_local! {
+    /// Whether the last line or file operation failed, as queried by `io_failed`
+    static IO_FAILED: Cell<bool> = Cell::new(false);
+    /// The point in time that `monotonic_nanos` counts from
+    static MONOTONIC_START: Instant = Instant::now();
+    /// The open connections, where a connection of the program is an index
+    static CONNECTIONS: RefCell<Vec<Option<TcpStream>>> = RefCell::new(Vec::new());
+    /// The status code of the response of the last successful `http_get`
+    static HTTP_STATUS: Cell<i64> = Cell::new(-1);
+}
+
+/// A scope of variables, with a reference to the enclosing scope
+struct Frame<'ast, 'src: 'ast> {
+    vars: RefCell<HashMap<&'src str, Value<'ast, 'src>>>,
+    parent: Option<Env<'ast, 'src>>,
+}
+
+type Env<'ast, 'src> = Rc<Frame<'ast, 'src>>;
+
+fn new_frame<'ast, 'src: 'ast>(parent: Option<Env<'ast, 'src>>) -> Env<'ast, 'src> {
+    Rc::new(Frame {
+        vars: RefCell::new(HashMap::new()),
+        parent,
+    })
+}
+
+fn lookup<'ast, 'src: 'ast>(env: &Env<'ast, 'src>, name: &str) -> Option<Value<'ast, 'src>> {
+    match env.vars.borrow().get(name) {
+        Some(v) => Some(v.clone()),
+        None => env.parent.as_ref().and_then(|p| lookup(p, name)),
+    }
+}
+
+/// A closure of a lambda and the environment it was created in
+#[derive(Clone)]
+pub struct Closure<'ast, 'src: 'ast> {
+    lam: &'ast Lambda<'src>,
+    env: Env<'ast, 'src>,
+}
+
+/// A runtime value of the interpreter
+#[derive(Clone)]
+pub enum Value<'ast, 'src: 'ast> {
+    Nil,
+    Bool(bool),
+    /// A value of any of the integer types
+    Int(i64),
+    /// A value of any of the floating point types
+    Float(f64),
+    /// A string, or a byte string that is valid UTF-8
+    Str(Rc<String>),
+    /// A byte string that is not valid UTF-8
+    Bytes(Rc<Vec<u8>>),
+    /// A compiled regex
+    Regex(Rc<Regex>),
+    Cons(Rc<(Value<'ast, 'src>, Value<'ast, 'src>)>),
+    Closure(Closure<'ast, 'src>),
+    Builtin(Builtin),
+    /// The token of the outside world, threaded through IO actions
+    RealWorld,
+}
+
+impl<'ast, 'src> fmt::Display for Value<'ast, 'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Str(ref s) => write!(f, "{:?}", s),
+            Value::Bytes(ref bs) => {
+                write!(f, "#x\"")?;
+                for b in bs.iter() {
+                    write!(f, "{:02X}", b)?;
+                }
+                write!(f, "\"")
+            }
+            Value::Regex(_) => write!(f, "<regex>"),
+            Value::Cons(ref c) => write!(f, "(cons {} {})", c.0, c.1),
+            Value::Closure(_) => write!(f, "<lambda>"),
+            Value::Builtin(Builtin::Binop(s)) | Value::Builtin(Builtin::Extern(s)) => {
+                write!(f, "<builtin {}>", s)
+            }
+            Value::RealWorld => write!(f, "<real world>"),
+        }
+    }
+}
+
+fn cons<'ast, 'src: 'ast>(car: Value<'ast, 'src>, cdr: Value<'ast, 'src>) -> Value<'ast, 'src> {
+    Value::Cons(Rc::new((car, cdr)))
+}
+
+/// Apply the primitive binary operation `op` to the operands `a` and `b`
+fn apply_binop<'ast, 'src: 'ast>(
+    op: &str,
+    a: &Value<'ast, 'src>,
+    b: &Value<'ast, 'src>,
+    pos: &SrcPos,
+) -> Value<'ast, 'src> {
+    match (op, a, b) {
+        ("div", &Value::Int(_), &Value::Int(0)) => pos.error_exit("Division by zero"),
+        ("add", &Value::Int(x), &Value::Int(y)) => Value::Int(x.wrapping_add(y)),
+        ("sub", &Value::Int(x), &Value::Int(y)) => Value::Int(x.wrapping_sub(y)),
+        ("mul", &Value::Int(x), &Value::Int(y)) => Value::Int(x.wrapping_mul(y)),
+        ("div", &Value::Int(x), &Value::Int(y)) => Value::Int(x.wrapping_div(y)),
+        ("add", &Value::Float(x), &Value::Float(y)) => Value::Float(x + y),
+        ("sub", &Value::Float(x), &Value::Float(y)) => Value::Float(x - y),
+        ("mul", &Value::Float(x), &Value::Float(y)) => Value::Float(x * y),
+        ("div", &Value::Float(x), &Value::Float(y)) => Value::Float(x / y),
+        ("eq", &Value::Int(x), &Value::Int(y)) => Value::Bool(x == y),
+        ("neq", &Value::Int(x), &Value::Int(y)) => Value::Bool(x != y),
+        ("gt", &Value::Int(x), &Value::Int(y)) => Value::Bool(x > y),
+        ("gteq", &Value::Int(x), &Value::Int(y)) => Value::Bool(x >= y),
+        ("lt", &Value::Int(x), &Value::Int(y)) => Value::Bool(x < y),
+        ("lteq", &Value::Int(x), &Value::Int(y)) => Value::Bool(x <= y),
+        ("eq", &Value::Float(x), &Value::Float(y)) => Value::Bool(x == y),
+        ("neq", &Value::Float(x), &Value::Float(y)) => Value::Bool(x != y),
+        ("gt", &Value::Float(x), &Value::Float(y)) => Value::Bool(x > y),
+        ("gteq", &Value::Float(x), &Value::Float(y)) => Value::Bool(x >= y),
+        ("lt", &Value::Float(x), &Value::Float(y)) => Value::Bool(x < y),
+        ("lteq", &Value::Float(x), &Value::Float(y)) => Value::Bool(x <= y),
+        ("and", &Value::Bool(x), &Value::Bool(y)) => Value::Bool(x && y),
+        ("or", &Value::Bool(x), &Value::Bool(y)) => Value::Bool(x || y),
+        ("xor", &Value::Bool(x), &Value::Bool(y)) => Value::Bool(x != y),
+        _ => pos.error_exit(format!("Invalid operands `{}` and `{}` of `{}`", a, b, op)),
+    }
+}
+
+/// Read a line from stdin and parse it as an integer
+fn read_int<'ast, 'src: 'ast>(pos: &SrcPos) -> Value<'ast, 'src> {
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .unwrap_or_else(|e| pos.error_exit(format!("Failed to read from stdin, {}", e)));
+    match line.trim().parse::<i64>() {
+        Ok(n) => Value::Int(n),
+        Err(_) => Value::Int(0),
+    }
+}
+
+/// Read a line from stdin, without the line break
+fn read_line<'ast, 'src: 'ast>() -> Value<'ast, 'src> {
+    let mut line = String::new();
+    let r = io::stdin().lock().read_line(&mut line);
+    IO_FAILED.with(|f| f.set(r.map(|n| n == 0).unwrap_or(true)));
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Value::Str(Rc::new(line))
+}
+
+/// Read the whole contents of the file at `path`
+fn read_file<'ast, 'src: 'ast>(path: &str) -> Value<'ast, 'src> {
+    let mut contents = Vec::new();
+    let r = File::open(path).and_then(|mut f| f.read_to_end(&mut contents));
+    IO_FAILED.with(|f| f.set(r.is_err()));
+    Value::Str(Rc::new(String::from_utf8_lossy(&contents).into_owned()))
+}
+
+/// Write `contents` to the file at `path`, replacing any previous contents
+fn write_file(path: &str, contents: &str) {
+    let r = File::create(path).and_then(|mut f| f.write_all(contents.as_bytes()));
+    IO_FAILED.with(|f| f.set(r.is_err()));
+}
+
+const LOG_LEVEL_NAMES: &[&str] = &["off", "error", "warn", "info", "debug"];
+
+/// The most detailed level of messages of `target` that is enabled by `KVASIR_LOG`, in the
+/// same way as the core library
+fn max_log_level(target: &str) -> usize {
+    let spec = env::var("KVASIR_LOG").unwrap_or(String::new());
+    let parse = |s: &str| LOG_LEVEL_NAMES.iter().position(|&name| name == s);
+    let (mut default_level, mut target_level) = (2, None);
+    for directive in spec.split(',') {
+        match directive.find('=') {
+            None => default_level = parse(directive).unwrap_or(default_level),
+            Some(i) if &directive[..i] == target => target_level = parse(&directive[i + 1..]),
+            Some(_) => (),
+        }
+    }
+    target_level.unwrap_or(default_level)
+}
+
+/// Log `msg` of `target` at `level`, in the same way as the core library
+fn log_message(level: i64, target: &str, msg: &str) {
+    if level < 1 || level as usize > max_log_level(target) {
+        return;
+    }
+    let name = LOG_LEVEL_NAMES[level as usize];
+    if target.is_empty() {
+        eprintln!("[{}] {}", name, msg)
+    } else {
+        eprintln!("[{} {}] {}", name, target, msg)
+    }
+}
+
+/// Returns the exit code of a process that exited with `status`, or 128 plus the number of the
+/// signal that terminated it, like a shell
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+/// Returns the exit code of a process that exited with `status`
+#[cfg(not(unix))]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(-1)
+}
+
+/// Run the shell command `command` in the directory `dir`, or the current directory if `dir`
+/// is empty, in the same way as the core library
+fn run_command<'ast, 'src: 'ast>(dir: &str, command: &str) -> Value<'ast, 'src> {
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(command);
+    if !dir.is_empty() {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.output();
+    IO_FAILED.with(|f| f.set(output.is_err()));
+    let string = |bytes: &[u8]| Value::Str(Rc::new(String::from_utf8_lossy(bytes).into_owned()));
+    match output {
+        Ok(output) => {
+            cons(
+                Value::Int(exit_code(output.status) as i64),
+                cons(string(&output.stdout), string(&output.stderr)),
+            )
+        }
+        Err(_) => cons(Value::Int(-1), cons(string(&[]), string(&[]))),
+    }
+}
+
+/// The names of the entries of the directory `path`, in the same way as the core library
+fn list_dir<'ast, 'src: 'ast>(path: &str) -> Value<'ast, 'src> {
+    let names = fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    IO_FAILED.with(|f| f.set(names.is_err()));
+    let mut names = names.unwrap_or(Vec::new());
+    names.sort();
+    let listing = names.iter().map(|name| format!("{}\n", name)).collect();
+    Value::Str(Rc::new(listing))
+}
+
+/// Connect to `port` of `host` over TCP. Returns the connection, or -1 on failure
+fn tcp_connect(host: &str, port: i64) -> i64 {
+    let r = if port >= 0 && port <= u16::MAX as i64 {
+        TcpStream::connect((host, port as u16))
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Port out of range"))
+    };
+    IO_FAILED.with(|f| f.set(r.is_err()));
+    match r {
+        Ok(stream) => CONNECTIONS.with(|conns| {
+            let mut conns = conns.borrow_mut();
+            conns.push(Some(stream));
+            conns.len() as i64 - 1
+        }),
+        Err(_) => -1,
+    }
+}
+
+/// Apply `f` to the open connection `conn`, or fail if it's not open
+fn with_connection<T, F>(conn: i64, f: F) -> io::Result<T>
+where
+    F: FnOnce(&mut TcpStream) -> io::Result<T>,
+{
+    CONNECTIONS.with(|conns| {
+        match conns.borrow_mut().get_mut(conn as usize) {
+            Some(&mut Some(ref mut stream)) => f(stream),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "Connection is not open")),
+        }
+    })
+}
+
+/// Read what's available from the connection `conn`
+fn tcp_read<'ast, 'src: 'ast>(conn: i64) -> Value<'ast, 'src> {
+    let mut buf = [0; 4096];
+    let r = with_connection(conn, |stream| stream.read(&mut buf));
+    IO_FAILED.with(|f| f.set(r.is_err()));
+    let n = r.unwrap_or(0);
+    Value::Str(Rc::new(String::from_utf8_lossy(&buf[..n]).into_owned()))
+}
+
+fn tcp_write(conn: i64, data: &str) {
+    let r = with_connection(conn, |stream| stream.write_all(data.as_bytes()));
+    IO_FAILED.with(|f| f.set(r.is_err()));
+}
+
+fn tcp_close(conn: i64) {
+    let stream = CONNECTIONS.with(|conns| {
+        conns
+            .borrow_mut()
+            .get_mut(conn as usize)
+            .and_then(|stream| stream.take())
+    });
+    IO_FAILED.with(|f| f.set(stream.is_none()));
+}
+
+/// Perform a GET request of `url` over HTTP/1.0, in the same way as the core library
+///
+/// Returns the status code and the body of the response
+fn http_get(url: &str) -> io::Result<(i64, String)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    if !url.starts_with("http://") {
+        return Err(invalid("Only plain `http://` URLs are supported"));
+    }
+    let rest = &url["http://".len()..];
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.find(':') {
+        Some(i) => match authority[i + 1..].parse::<u16>() {
+            Ok(port) if port > 0 => (&authority[..i], port),
+            _ => return Err(invalid("Invalid port")),
+        },
+        None => (authority, 80),
+    };
+    let mut stream = TcpStream::connect((host, port))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path,
+        host
+    )?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response).into_owned();
+    // The status line is `HTTP/1.x CODE REASON`, and the body follows the first empty line
+    let status = if response.starts_with("HTTP/1.") {
+        response.split_whitespace().nth(1).and_then(|code| code.parse().ok())
+    } else {
+        None
+    };
+    match (status, response.find("\r\n\r\n")) {
+        (Some(status), Some(i)) => Ok((status, response[i + 4..].to_string())),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid HTTP response")),
+    }
+}
+
+/// The number of days since the Unix epoch of the date `year`-`month`-`day`, in the same way
+/// as the core library
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The date, as year, month, and day, of the number of days since the Unix epoch `days`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format the timestamp `millis` in ISO 8601, in the same way as the core library
+fn format_timestamp(millis: i64) -> String {
+    let days = (if millis >= 0 { millis } else { millis - 86399999 }) / 86400000;
+    let ms = millis - days * 86400000;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        ms / 3600000,
+        ms / 60000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// One step of the SplitMix64 generator. Returns the random number and the next state
+fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (z ^ (z >> 31), state)
+}
+
+/// Parse `s` as a number, in the same way as the core library
+///
+/// Like `strtoll` and `strtod`, a leading `+` is accepted, but unlike them, no leading whitespace
+fn parse_num<T: FromStr>(s: &str) -> Option<T> {
+    if s.starts_with(char::is_whitespace) {
+        None
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Show `x` in the same way as the core library, like `printf` with the format `%.17g`
+///
+/// 17 significant digits are enough for any `f64` to be parsed back to the same value
+fn show_float64(x: f64) -> String {
+    if x.is_nan() {
+        return "nan".to_string();
+    } else if x.is_infinite() {
+        return if x < 0.0 { "-inf" } else { "inf" }.to_string();
+    } else if x == 0.0 {
+        return if x.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    // The exponent of `x` when rounded to 17 significant digits decides the notation
+    let sci = format!("{:.16e}", x);
+    let (mantissa, exp) = sci.split_at(sci.find('e').expect("ICE: no exponent in `{:e}`"));
+    let exp = exp[1..].parse::<i32>().expect("ICE: invalid exponent in `{:e}`");
+    if exp < -4 || exp >= 17 {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_fraction_zeros(mantissa), sign, exp.abs())
+    } else {
+        trim_fraction_zeros(&format!("{:.*}", (16 - exp) as usize, x)).to_string()
+    }
+}
+
+/// Returns the decimal number `s` without trailing zeros of its fraction, and without the
+/// decimal point if the whole fraction is zeros
+fn trim_fraction_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_right_matches('0').trim_right_matches('.')
+    } else {
+        s
+    }
+}
+
+/// Returns the string and the bounds of the argument of `can_slice_string` or `slice_string`
+fn slice_args<'v, 'ast, 'src: 'ast>(
+    arg: &'v Value<'ast, 'src>,
+) -> Option<(&'v str, usize, usize)> {
+    match *arg {
+        Value::Cons(ref c) => match (&c.0, &c.1) {
+            (&Value::Str(ref s), &Value::Cons(ref bounds)) => match **bounds {
+                (Value::Int(start), Value::Int(end)) => {
+                    Some((s.as_str(), start as usize, end as usize))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A compiled POSIX extended regular expression
+///
+/// Compiled and matched by `regcomp` and `regexec` of the C library, like in the core library,
+/// so that interpreted and compiled programs agree on what a regex matches. Only supported
+/// on Unix
+pub struct Regex {
+    #[cfg(unix)]
+    re: Box<libc::regex_t>,
+    group_count: usize,
+}
+
+impl Regex {
+    /// Compile `pattern`, or return the error message of `regcomp`
+    #[cfg(unix)]
+    fn new(pattern: &str) -> Result<Regex, String> {
+        // Like in the core library, the pattern ends at a nul byte
+        let c_pattern = CString::new(pattern.split('\0').next().unwrap_or("")).unwrap();
+        unsafe {
+            let mut re: Box<libc::regex_t> = Box::new(mem::zeroed());
+            let err = libc::regcomp(&mut *re, c_pattern.as_ptr(), libc::REG_EXTENDED);
+            if err != 0 {
+                let mut msg = [0 as libc::c_char; 256];
+                libc::regerror(err, &*re, msg.as_mut_ptr(), msg.len());
+                return Err(CStr::from_ptr(msg.as_ptr()).to_string_lossy().into_owned());
+            }
+            Ok(Regex {
+                re,
+                group_count: regex_group_count(pattern),
+            })
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn new(_pattern: &str) -> Result<Regex, String> {
+        error_exit("Regexes are only supported by the interpreter on Unix")
+    }
+
+    /// Match against `s`, returning the bounds of the first `n_groups` groups of the first
+    /// match, where group 0 is the whole match, or `None` if there's no match
+    #[cfg(unix)]
+    fn exec(&self, s: &[u8], n_groups: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let c_s = CString::new(s.split(|&b| b == 0).next().unwrap_or(&[])).unwrap();
+        let mut groups = vec![libc::regmatch_t { rm_so: -1, rm_eo: -1 }; n_groups];
+        let matched = unsafe {
+            libc::regexec(&*self.re, c_s.as_ptr(), n_groups, groups.as_mut_ptr(), 0) == 0
+        };
+        if matched {
+            Some(
+                groups
+                    .iter()
+                    .map(|g| if g.rm_so >= 0 {
+                        Some((g.rm_so as usize, g.rm_eo as usize))
+                    } else {
+                        None
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn exec(&self, _s: &[u8], _n_groups: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        None
+    }
+
+    /// Returns the bounds of group `n` of the first match in `s`, if there's a match and the
+    /// group took part in it
+    fn group(&self, s: &[u8], n: usize) -> Option<(usize, usize)> {
+        if n > self.group_count {
+            return None;
+        }
+        self.exec(s, n + 1).and_then(|groups| groups[n])
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Regex {
+    fn drop(&mut self) {
+        unsafe { libc::regfree(&mut *self.re) }
+    }
+}
+
+/// Returns the regex and the subject string of the arguments `arg` of a regex operation
+fn regex_args<'v, 'ast, 'src: 'ast>(
+    arg: &'v Value<'ast, 'src>,
+) -> Option<(&'v Regex, &'v Value<'ast, 'src>)> {
+    match *arg {
+        Value::Cons(ref c) => match c.0 {
+            Value::Regex(ref re) => Some((&**re, &c.1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the bytes of the string or byte string `v`
+fn bytes_of<'v, 'ast, 'src: 'ast>(v: &'v Value<'ast, 'src>) -> Option<&'v [u8]> {
+    match *v {
+        Value::Str(ref s) => Some(s.as_bytes()),
+        Value::Bytes(ref bs) => Some(&bs[..]),
+        _ => None,
+    }
+}
+
+/// Returns the value of the byte string `bytes`, a string if it's valid UTF-8
+fn bytes_value<'ast, 'src: 'ast>(bytes: Vec<u8>) -> Value<'ast, 'src> {
+    match String::from_utf8(bytes) {
+        Ok(s) => Value::Str(Rc::new(s)),
+        Err(e) => Value::Bytes(Rc::new(e.into_bytes())),
+    }
+}
+
+/// Returns the bytes of the hexadecimal digits `digits`
+///
+/// Exits with an error if `digits` is not an even number of hexadecimal digits
+fn hex_to_bytes(digits: &str) -> Vec<u8> {
+    if digits.len() % 2 != 0 || !digits.bytes().all(|c| (c as char).is_digit(16)) {
+        error_exit(format!("Invalid hexadecimal byte string `{}`", digits))
+    }
+    let digit_value = |c: u8| (c as char).to_digit(16).unwrap() as u8;
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| digit_value(pair[0]) << 4 | digit_value(pair[1]))
+        .collect()
+}
+
+/// Apply the supported external function `name` to `arg`
+///
+/// `args` are the command line arguments of the program
+fn apply_extern<'ast, 'src: 'ast>(
+    name: &str,
+    arg: &Value<'ast, 'src>,
+    args: &[String],
+    pos: &SrcPos,
+) -> Value<'ast, 'src> {
+    // Pure functions
+    match (name, arg) {
+        ("show_int64", &Value::Int(n)) => return Value::Str(Rc::new(n.to_string())),
+        ("show_uint64", &Value::Int(n)) => return Value::Str(Rc::new((n as u64).to_string())),
+        ("show_float64", &Value::Float(x)) => return Value::Str(Rc::new(show_float64(x))),
+        ("can_parse_int64", &Value::Str(ref s)) => {
+            return Value::Bool(parse_num::<i64>(s).is_some())
+        }
+        ("parse_int64", &Value::Str(ref s)) => return Value::Int(parse_num(s).unwrap_or(0)),
+        ("can_parse_float64", &Value::Str(ref s)) => {
+            return Value::Bool(parse_num::<f64>(s).is_some())
+        }
+        ("parse_float64", &Value::Str(ref s)) => {
+            return Value::Float(parse_num(s).unwrap_or(0.0))
+        }
+        ("panic_at", &Value::Cons(ref c)) => {
+            if let (Value::Str(ref location), Value::Str(ref msg)) = **c {
+                error_exit(format!("Panicked at {}: {}", location, msg))
+            }
+        }
+        ("assert_eq_failed_at", &Value::Cons(ref c)) => {
+            if let (&Value::Cons(ref place), &Value::Cons(ref shown)) = (&c.0, &c.1) {
+                if let (
+                    &Value::Str(ref location),
+                    &Value::Str(ref source),
+                    &Value::Str(ref left),
+                    &Value::Str(ref right),
+                ) = (&place.0, &place.1, &shown.0, &shown.1)
+                {
+                    error_exit(format!(
+                        "Panicked at {}: Assertion failed: {}\n  left: {}\n right: {}",
+                        location, source, left, right
+                    ))
+                }
+            }
+        }
+        ("string_length", _) if bytes_of(arg).is_some() => {
+            return Value::Int(bytes_of(arg).unwrap().len() as i64)
+        }
+        ("hex_to_bytes", &Value::Str(ref digits)) => return bytes_value(hex_to_bytes(digits)),
+        ("byte_at", &Value::Cons(ref c)) => if let Value::Int(i) = c.1 {
+            if let Some(bytes) = bytes_of(&c.0) {
+                return match bytes.get(i as usize) {
+                    Some(&b) => Value::Int(b as i64),
+                    None => error_exit(format!(
+                        "Index {} out of bounds of bytes of length {}",
+                        i as u64,
+                        bytes.len()
+                    )),
+                };
+            }
+        },
+        ("is_valid_regex", &Value::Str(ref pattern)) => {
+            return Value::Bool(Regex::new(pattern).is_ok())
+        }
+        ("compile_regex", &Value::Str(ref pattern)) => match Regex::new(pattern) {
+            Ok(re) => return Value::Regex(Rc::new(re)),
+            Err(msg) => error_exit(format!("Invalid regex `{}`, {}", pattern, msg)),
+        },
+        ("regex_group_count", &Value::Regex(ref re)) => return Value::Int(re.group_count as i64),
+        ("regex_is_match", _) => if let Some((re, s)) = regex_args(arg) {
+            if let Some(s) = bytes_of(s) {
+                return Value::Bool(re.exec(s, 0).is_some());
+            }
+        },
+        ("regex_has_group", _) | ("regex_group", _) => {
+            let group_args = regex_args(arg).and_then(|(re, group_arg)| match *group_arg {
+                Value::Cons(ref c) => match (bytes_of(&c.0), &c.1) {
+                    (Some(s), &Value::Int(n)) => Some((re, s, n)),
+                    _ => None,
+                },
+                _ => None,
+            });
+            if let Some((re, s, n)) = group_args {
+                let group = re.group(s, n as usize);
+                return match (name, group) {
+                    ("regex_has_group", _) => Value::Bool(group.is_some()),
+                    (_, Some((start, end))) => bytes_value(s[start..end].to_vec()),
+                    (_, None) => error_exit(format!("No group {} in match of regex", n)),
+                };
+            }
+        }
+        // Only bytes that are not valid UTF-8 are of the bytes value
+        ("is_utf8", &Value::Str(_)) => return Value::Bool(true),
+        ("is_utf8", &Value::Bytes(_)) => return Value::Bool(false),
+        ("can_slice_string", _) | ("slice_string", _) => {
+            if let Some((s, start, end)) = slice_args(arg) {
+                // Like in the core library, strings are indexed by bytes
+                let valid = start <= end && s.is_char_boundary(start) && s.is_char_boundary(end);
+                return match (name, valid) {
+                    ("can_slice_string", _) => Value::Bool(valid),
+                    (_, true) => Value::Str(Rc::new(s[start..end].to_string())),
+                    (_, false) => pos.error_exit(format!(
+                        "Invalid slice [{}, {}) of string of length {}",
+                        start,
+                        end,
+                        s.len()
+                    )),
+                };
+            }
+        }
+        ("split_line", &Value::Str(ref s)) => {
+            let (line, rest) = match s.find('\n') {
+                Some(i) => (&s[..i], &s[i + 1..]),
+                None => (&s[..], ""),
+            };
+            return cons(
+                Value::Str(Rc::new(line.to_string())),
+                Value::Str(Rc::new(rest.to_string())),
+            );
+        }
+        ("concat_strings", &Value::Cons(ref c)) => {
+            if let (Value::Str(ref a), Value::Str(ref b)) = **c {
+                return Value::Str(Rc::new(format!("{}{}", a, b)));
+            }
+        }
+        ("join_path", &Value::Cons(ref c)) => {
+            if let (Value::Str(ref dir), Value::Str(ref p)) = **c {
+                let joined = if dir.is_empty() || p.starts_with('/') {
+                    p.to_string()
+                } else if dir.ends_with('/') {
+                    format!("{}{}", dir, p)
+                } else {
+                    format!("{}/{}", dir, p)
+                };
+                return Value::Str(Rc::new(joined));
+            }
+        }
+        ("days_from_civil", &Value::Cons(ref c)) => {
+            if let (Value::Int(year), Value::Cons(ref month_day)) = **c {
+                if let (Value::Int(month), Value::Int(day)) = **month_day {
+                    return Value::Int(days_from_civil(year, month, day));
+                }
+            }
+        }
+        ("format_timestamp", &Value::Int(millis)) => {
+            return Value::Str(Rc::new(format_timestamp(millis)))
+        }
+        ("splitmix64", &Value::Int(state)) => {
+            let (value, next) = splitmix64(state as u64);
+            return cons(Value::Int(value as i64), Value::Int(next as i64));
+        }
+        _ => (),
+    }
+    // IO actions
+    let io_arg = match *arg {
+        Value::Cons(ref c) => Some(&c.0),
+        _ => None,
+    };
+    match (name, io_arg) {
+        ("c_display", Some(&Value::Str(ref s))) => println!("{}", s),
+        ("print_int64", Some(&Value::Int(n))) => println!("{}", n),
+        ("print_uint64", Some(&Value::Int(n))) => println!("{}", n as u64),
+        ("print_float64", Some(&Value::Float(x))) => println!("{}", show_float64(x)),
+        ("read_int64", _) | ("read_uint64", _) => return cons(read_int(pos), Value::RealWorld),
+        ("read_line", _) => return cons(read_line(), Value::RealWorld),
+        ("read_file", Some(&Value::Str(ref path))) => {
+            return cons(read_file(path), Value::RealWorld)
+        }
+        ("write_file", Some(&Value::Cons(ref c))) => match **c {
+            (Value::Str(ref path), Value::Str(ref contents)) => write_file(path, contents),
+            _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+        },
+        ("log_message", Some(&Value::Cons(ref c))) => match **c {
+            (Value::Int(level), Value::Cons(ref target_msg)) => match **target_msg {
+                (Value::Str(ref target), Value::Str(ref msg)) => log_message(level, target, msg),
+                _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+            },
+            _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+        },
+        ("run_command", Some(&Value::Cons(ref c))) => match **c {
+            (Value::Str(ref dir), Value::Str(ref command)) => {
+                return cons(run_command(dir, command), Value::RealWorld)
+            }
+            _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+        },
+        ("path_exists", Some(&Value::Str(ref path))) => {
+            return cons(Value::Bool(Path::new(&**path).exists()), Value::RealWorld)
+        }
+        ("is_dir", Some(&Value::Str(ref path))) => {
+            return cons(Value::Bool(Path::new(&**path).is_dir()), Value::RealWorld)
+        }
+        ("list_dir", Some(&Value::Str(ref path))) => {
+            return cons(list_dir(path), Value::RealWorld)
+        }
+        ("create_dir", Some(&Value::Str(ref path))) => {
+            IO_FAILED.with(|f| f.set(fs::create_dir(&**path).is_err()))
+        }
+        ("canonicalize_path", Some(&Value::Str(ref path))) => {
+            let r = fs::canonicalize(&**path);
+            IO_FAILED.with(|f| f.set(r.is_err()));
+            let canonical = r.map(|p| p.to_string_lossy().into_owned()).unwrap_or(String::new());
+            return cons(Value::Str(Rc::new(canonical)), Value::RealWorld);
+        }
+        ("tcp_connect", Some(&Value::Cons(ref c))) => match **c {
+            (Value::Str(ref host), Value::Int(port)) => {
+                return cons(Value::Int(tcp_connect(host, port)), Value::RealWorld)
+            }
+            _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+        },
+        ("tcp_read", Some(&Value::Int(conn))) => return cons(tcp_read(conn), Value::RealWorld),
+        ("tcp_write", Some(&Value::Cons(ref c))) => match **c {
+            (Value::Int(conn), Value::Str(ref data)) => tcp_write(conn, data),
+            _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+        },
+        ("tcp_close", Some(&Value::Int(conn))) => tcp_close(conn),
+        ("http_get", Some(&Value::Str(ref url))) => {
+            let r = http_get(url);
+            IO_FAILED.with(|f| f.set(r.is_err()));
+            let body = match r {
+                Ok((status, body)) => {
+                    HTTP_STATUS.with(|s| s.set(status));
+                    body
+                }
+                Err(_) => String::new(),
+            };
+            return cons(Value::Str(Rc::new(body)), Value::RealWorld);
+        }
+        ("http_status", _) => {
+            return cons(Value::Int(HTTP_STATUS.with(|s| s.get())), Value::RealWorld)
+        }
+        ("program_arg_count", _) => {
+            return cons(Value::Int(args.len() as i64), Value::RealWorld)
+        }
+        ("program_arg", Some(&Value::Int(n))) => {
+            let program_arg = args.get(n as usize).cloned().unwrap_or(String::new());
+            return cons(Value::Str(Rc::new(program_arg)), Value::RealWorld);
+        }
+        ("has_env", Some(&Value::Str(ref name))) => {
+            return cons(Value::Bool(env::var_os(&**name).is_some()), Value::RealWorld)
+        }
+        ("get_env", Some(&Value::Str(ref name))) => {
+            let var = env::var_os(&**name)
+                .map(|v| v.to_string_lossy().into_owned())
+                .unwrap_or(String::new());
+            return cons(Value::Str(Rc::new(var)), Value::RealWorld);
+        }
+        ("now_millis", _) => {
+            let t = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_else(|_| pos.error_exit("System time is before the Unix epoch"));
+            let millis = t.as_secs() * 1000 + t.subsec_nanos() as u64 / 1_000_000;
+            return cons(Value::Int(millis as i64), Value::RealWorld);
+        }
+        ("monotonic_nanos", _) => {
+            let t = MONOTONIC_START.with(|start| start.elapsed());
+            let nanos = t.as_secs() * 1_000_000_000 + t.subsec_nanos() as u64;
+            return cons(Value::Int(nanos as i64), Value::RealWorld);
+        }
+        ("io_failed", _) => {
+            return cons(Value::Bool(IO_FAILED.with(|f| f.get())), Value::RealWorld)
+        }
+        _ => pos.error_exit(format!("Invalid argument `{}` of `{}`", arg, name)),
+    }
+    cons(Value::Nil, Value::RealWorld)
+}
+
+/// Cast the numeric value `v` to the numeric type `type_name`
+fn cast_num<'ast, 'src: 'ast>(
+    v: &Value<'ast, 'src>,
+    type_name: &str,
+    pos: &SrcPos,
+) -> Value<'ast, 'src> {
+    let (n, x) = match *v {
+        Value::Int(n) => (n, n as f64),
+        Value::Float(x) => (x as i64, x),
+        _ => pos.error_exit(format!("Can not cast `{}` to `{}`", v, type_name)),
+    };
+    match type_name {
+        "Int8" => Value::Int(n as i8 as i64),
+        "Int16" => Value::Int(n as i16 as i64),
+        "Int32" => Value::Int(n as i32 as i64),
+        "Int64" | "IntPtr" | "UInt64" | "UIntPtr" => Value::Int(n),
+        "UInt8" => Value::Int(n as u8 as i64),
+        "UInt16" => Value::Int(n as u16 as i64),
+        "UInt32" => Value::Int(n as u32 as i64),
+        "Float32" => Value::Float(x as f32 as f64),
+        "Float64" => Value::Float(x),
+        _ => pos.error_exit(format!("Can not cast `{}` to `{}`", v, type_name)),
+    }
+}
+
+/// A tree-walking interpreter of the global definitions of an AST
+pub struct Interpreter<'ast, 'src: 'ast> {
+    ast: &'ast Ast<'src>,
+    /// Definitions of the global variables
+    global_defs: HashMap<&'src str, &'ast Binding<'src>>,
+    /// Values of the global variables that have been evaluated so far
+    global_vals: HashMap<&'src str, Value<'ast, 'src>>,
+    /// The command line arguments of the program, starting with the name of the program
+    args: Vec<String>,
+    /// The number of evaluations of each expression, if coverage is enabled
+    hits: Option<HashMap<*const Expr<'src>, u64>>,
+}
+
+impl<'ast, 'src: 'ast> Interpreter<'ast, 'src> {
+    pub fn new(ast: &'ast Ast<'src>) -> Self {
+        Interpreter {
+            ast,
+            global_defs: ast.globals.bindings().map(|b| (b.ident.s, b)).collect(),
+            global_vals: HashMap::new(),
+            args: Vec::new(),
+            hits: None,
+        }
+    }
+
+    /// Count the evaluations of each expression from now on, for `write_lcov`
+    pub fn enable_coverage(&mut self) {
+        self.hits = Some(HashMap::new())
+    }
+
+    /// Returns the value of the global variable `name`, evaluating it if not already done
+    pub fn eval_global(&mut self, name: &str, pos: &SrcPos) -> Value<'ast, 'src> {
+        if let Some(v) = self.global_vals.get(name) {
+            return v.clone();
+        }
+        let def = *self.global_defs
+            .get(name)
+            .unwrap_or_else(|| pos.error_exit(format!("Unresolved path `{}`", name)));
+        let v = self.eval(&def.val, &new_frame(None));
+        self.global_vals.insert(def.ident.s, v.clone());
+        v
+    }
+
+    fn eval_variable(&mut self, var: &Variable<'src>, env: &Env<'ast, 'src>) -> Value<'ast, 'src> {
+        let name = var.ident.s;
+        let pos = &var.ident.pos;
+        if let Some(v) = lookup(env, name) {
+            v
+        } else if let Some(&op) = BINOPS.iter().find(|&&op| op == name) {
+            Value::Builtin(Builtin::Binop(op))
+        } else if self.global_defs.contains_key(name) {
+            self.eval_global(name, pos)
+        } else if self.ast.externs.contains_key(name) {
+            EXTERNS
+                .iter()
+                .find(|&&ext| ext == name)
+                .map(|&ext| Value::Builtin(Builtin::Extern(ext)))
+                .unwrap_or_else(|| {
+                    pos.error_exit(format!(
+                        "External variable `{}` is not supported by the interpreter",
+                        name
+                    ))
+                })
+        } else {
+            pos.error_exit(format!("Unresolved path `{}`", name))
+        }
+    }
+
+    /// Apply the function `f` to the argument `arg`
+    pub fn apply(
+        &mut self,
+        f: &Value<'ast, 'src>,
+        arg: Value<'ast, 'src>,
+        pos: &SrcPos,
+    ) -> Value<'ast, 'src> {
+        match *f {
+            Value::Closure(ref clos) => {
+                let frame = new_frame(Some(clos.env.clone()));
+                frame.vars.borrow_mut().insert(clos.lam.param_ident.s, arg);
+                self.eval(&clos.lam.body, &frame)
+            }
+            Value::Builtin(Builtin::Binop(op)) => match arg {
+                Value::Cons(ref c) => apply_binop(op, &c.0, &c.1, pos),
+                _ => pos.error_exit(format!("Invalid operand `{}` of `{}`", arg, op)),
+            },
+            Value::Builtin(Builtin::Extern(name)) => apply_extern(name, &arg, &self.args, pos),
+            _ => pos.error_exit(format!("`{}` is not a function", f)),
+        }
+    }
+
+    fn eval_let(&mut self, l: &'ast Let<'src>, env: &Env<'ast, 'src>) -> Value<'ast, 'src> {
+        let frame = new_frame(Some(env.clone()));
+        // Bind dependencies before dependents
+        for b in l.bindings.bindings().rev() {
+            let v = self.eval(&b.val, &frame);
+            frame.vars.borrow_mut().insert(b.ident.s, v);
+        }
+        self.eval(&l.body, &frame)
+    }
+
+    fn eval_num_lit(&self, lit: &NumLit<'src>) -> Value<'ast, 'src> {
+        let is_float = lit.typ.is_float() || (!lit.typ.is_monomorphic() && lit.lit.contains('.'));
+        if is_float {
+            lit.lit
+                .parse::<f64>()
+                .map(Value::Float)
+                .unwrap_or_else(|_| lit.pos.error_exit("Invalid floating point literal"))
+        } else {
+            lit.lit
+                .parse::<i64>()
+                .or_else(|_| lit.lit.parse::<u64>().map(|n| n as i64))
+                .map(Value::Int)
+                .unwrap_or_else(|_| lit.pos.error_exit("Invalid integer literal"))
+        }
+    }
+
+    /// Evaluate the expression `e` in the environment `env`
+    fn eval(&mut self, e: &'ast Expr<'src>, env: &Env<'ast, 'src>) -> Value<'ast, 'src> {
+        if let Some(ref mut hits) = self.hits {
+            *hits.entry(e as *const _).or_insert(0) += 1
+        }
+        match *e {
+            Expr::Nil(_) => Value::Nil,
+            Expr::NumLit(ref l) => self.eval_num_lit(l),
+            Expr::StrLit(ref l) => Value::Str(Rc::new(l.lit.to_string())),
+            Expr::Bool(ref b) => Value::Bool(b.val),
+            Expr::Variable(ref var) => self.eval_variable(var, env),
+            Expr::App(ref app) => {
+                let f = self.eval(&app.func, env);
+                let arg = self.eval(&app.arg, env);
+                self.apply(&f, arg, &app.pos)
+            }
+            Expr::If(ref cond) => match self.eval(&cond.predicate, env) {
+                Value::Bool(true) => self.eval(&cond.consequent, env),
+                Value::Bool(false) => self.eval(&cond.alternative, env),
+                v => cond.pos.error_exit(format!("Predicate `{}` is not a boolean", v)),
+            },
+            Expr::Lambda(ref lam) => Value::Closure(Closure {
+                lam,
+                env: env.clone(),
+            }),
+            Expr::Let(ref l) => self.eval_let(l, env),
+            Expr::TypeAscript(ref a) => self.eval(&a.expr, env),
+            Expr::Cons(ref c) => {
+                let car = self.eval(&c.car, env);
+                let cdr = self.eval(&c.cdr, env);
+                cons(car, cdr)
+            }
+            Expr::Car(ref c) => match self.eval(&c.expr, env) {
+                Value::Cons(ref p) => p.0.clone(),
+                v => c.pos.error_exit(format!("`{}` is not a pair", v)),
+            },
+            Expr::Cdr(ref c) => match self.eval(&c.expr, env) {
+                Value::Cons(ref p) => p.1.clone(),
+                v => c.pos.error_exit(format!("`{}` is not a pair", v)),
+            },
+            Expr::Cast(ref c) => {
+                let v = self.eval(&c.expr, env);
+                match c.typ.get_const() {
+                    // E.g. the constructor of a newtype, which is represented like the
+                    // underlying type
+                    Some(t) if self.ast.newtypes.contains_key(t) => v,
+                    Some(t) => cast_num(&v, t, &c.pos),
+                    None => v,
+                }
+            }
+            Expr::SizeOf(ref s) => s.pos
+                .error_exit("Type layout queries are not supported by the interpreter"),
+            Expr::AlignOf(ref a) => a.pos
+                .error_exit("Type layout queries are not supported by the interpreter"),
+        }
+    }
+
+    /// Run the program by evaluating `main`, applying it to the real world if it's an IO action
+    ///
+    /// `args` are the command line arguments of the program, starting with the name of
+    /// the program
+    pub fn run_main(&mut self, args: Vec<String>) -> Value<'ast, 'src> {
+        self.args = args;
+        let pos = match self.global_defs.get("main") {
+            Some(def) => def.pos.clone(),
+            None => error_exit("No `main` function found"),
+        };
+        match self.eval_global("main", &pos) {
+            f @ Value::Closure(_) => self.apply(&f, Value::RealWorld, &pos),
+            v => v,
+        }
+    }
+}
+
+/// Add the evaluation counts of `e` and its subexpressions to the lines they start on
+///
+/// The count of a line is the greatest count of the expressions starting on it.
+fn collect_line_hits<'src>(
+    e: &Expr<'src>,
+    hits: &HashMap<*const Expr<'src>, u64>,
+    lines: &mut BTreeMap<&'src Path, BTreeMap<usize, u64>>,
+) {
+    let pos = e.pos();
+    let (_, _, row, _) = pos.line_len_row_col();
+    let n = hits.get(&(e as *const _)).cloned().unwrap_or(0);
+    {
+        let count = lines
+            .entry(pos.filename())
+            .or_insert(BTreeMap::new())
+            .entry(row)
+            .or_insert(0);
+        *count = (*count).max(n);
+    }
+    let mut collect = |e| collect_line_hits(e, hits, lines);
+    match *e {
+        Expr::App(ref app) => {
+            collect(&app.func);
+            collect(&app.arg)
+        }
+        Expr::If(ref cond) => {
+            collect(&cond.predicate);
+            collect(&cond.consequent);
+            collect(&cond.alternative)
+        }
+        Expr::Lambda(ref lam) => collect(&lam.body),
+        Expr::Let(ref l) => {
+            for b in l.bindings.bindings().rev() {
+                collect(&b.val)
+            }
+            collect(&l.body)
+        }
+        Expr::TypeAscript(ref a) => collect(&a.expr),
+        Expr::Cons(ref c) => {
+            collect(&c.car);
+            collect(&c.cdr)
+        }
+        Expr::Car(ref c) => collect(&c.expr),
+        Expr::Cdr(ref c) => collect(&c.expr),
+        Expr::Cast(ref c) => collect(&c.expr),
+        Expr::Variable(_)
+        | Expr::Nil(_)
+        | Expr::NumLit(_)
+        | Expr::StrLit(_)
+        | Expr::Bool(_)
+        | Expr::SizeOf(_)
+        | Expr::AlignOf(_) => (),
+    }
+}
+
+impl<'ast, 'src: 'ast> Interpreter<'ast, 'src> {
+    /// Write the number of evaluations of each line of the program to `w`, in the lcov
+    /// tracefile format
+    ///
+    /// Only lines where an expression starts are included. Does nothing if coverage
+    /// is not enabled.
+    pub fn write_lcov<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let hits = match self.hits {
+            Some(ref hits) => hits,
+            None => return Ok(()),
+        };
+        let mut lines = BTreeMap::new();
+        for b in self.ast.globals.bindings() {
+            collect_line_hits(&b.val, hits, &mut lines)
+        }
+        for (file, counts) in lines {
+            writeln!(w, "TN:")?;
+            writeln!(w, "SF:{}", file.display())?;
+            for (row, count) in &counts {
+                writeln!(w, "DA:{},{}", row, count)?;
+            }
+            writeln!(w, "LH:{}", counts.values().filter(|&&n| n > 0).count())?;
+            writeln!(w, "LF:{}", counts.len())?;
+            writeln!(w, "end_of_record")?;
+        }
+        w.flush()
+    }
+}
+
+/// Returns whether the scalar type `t` is represented exactly by the interpreter, and as such
+/// can be the type of a constant in a `ConstEnv`
+fn is_const_type(t: &Type) -> bool {
+    match t.get_const() {
+        Some("Int64") | Some("Float64") | Some("Bool") => true,
+        _ => false,
+    }
+}
+
+/// Returns whether the operands of a primitive binary operation, the pair `arg`, are of
+/// a type represented exactly by the interpreter
+fn is_const_operands(arg: &Expr) -> bool {
+    match *arg.get_type() {
+        Type::App(box TypeFunc::Const("Cons"), ref ts) => ts.iter().all(is_const_type),
+        _ => false,
+    }
+}
+
+/// Returns whether `e` is a constant expression, given the names of the known constants
+/// and of the local variables in scope, which are all bound to constant expressions
+///
+/// A constant expression is built only from literals, known constants, local bindings of
+/// constant expressions, conditionals, pairs, and applications of primitive binary
+/// operations, and as such is guaranteed to terminate when evaluated. All numbers must be of
+/// the types accepted by `is_const_type`
+fn is_const_expr(e: &Expr, consts: &HashMap<&str, Value>, locals: &[&str]) -> bool {
+    let is_const = |e| is_const_expr(e, consts, locals);
+    match *e {
+        Expr::Nil(_) | Expr::StrLit(_) | Expr::Bool(_) => true,
+        Expr::NumLit(ref l) => is_const_type(&l.typ),
+        Expr::Variable(ref var) => {
+            locals.contains(&var.ident.s) || consts.contains_key(var.ident.s)
+        }
+        Expr::App(ref app) => match app.func {
+            Expr::Variable(ref f) => {
+                BINOPS.iter().any(|&op| op == f.ident.s) && !locals.contains(&f.ident.s)
+                    && is_const_operands(&app.arg) && is_const(&app.arg)
+            }
+            _ => false,
+        },
+        Expr::If(ref cond) => {
+            is_const(&cond.predicate) && is_const(&cond.consequent)
+                && is_const(&cond.alternative)
+        }
+        Expr::Let(ref l) => {
+            let mut locals = locals.to_vec();
+            // Visit dependencies before dependents
+            for b in l.bindings.bindings().rev() {
+                if !is_const_expr(&b.val, consts, &locals) {
+                    return false;
+                }
+                locals.push(b.ident.s)
+            }
+            is_const_expr(&l.body, consts, &locals)
+        }
+        Expr::TypeAscript(ref a) => is_const(&a.expr),
+        Expr::Cons(ref c) => is_const(&c.car) && is_const(&c.cdr),
+        Expr::Car(ref c) => is_const(&c.expr),
+        Expr::Cdr(ref c) => is_const(&c.expr),
+        Expr::Cast(ref c) => {
+            is_const_type(&c.typ) && is_const_type(c.expr.get_type()) && is_const(&c.expr)
+        }
+        _ => false,
+    }
+}
+
+/// The values of the global constants of a program, evaluated at compile time
+///
+/// Only monomorphic globals of type `Int64`, `Float64`, or `Bool`, defined by constant
+/// expressions are included. See `is_const_expr`
+pub struct ConstEnv<'ast, 'src: 'ast> {
+    consts: HashMap<&'src str, Value<'ast, 'src>>,
+}
+
+impl<'ast, 'src: 'ast> ConstEnv<'ast, 'src> {
+    /// Evaluate the global constants of `ast`
+    pub fn new(ast: &'ast Ast<'src>) -> Self {
+        let mut interp = Interpreter::new(ast);
+        let mut consts = HashMap::new();
+        // Visit dependencies before dependents
+        for b in ast.globals.bindings().rev() {
+            let is_const = b.typ.is_monomorphic() && is_const_type(&b.typ)
+                && is_const_expr(&b.val, &consts, &[]);
+            if is_const {
+                let v = interp.eval_global(b.ident.s, &b.pos);
+                consts.insert(b.ident.s, v);
+            }
+        }
+        ConstEnv { consts }
+    }
+
+    /// Returns the value of the global constant `name`, if it is one
+    pub fn get(&self, name: &str) -> Option<&Value<'ast, 'src>> {
+        self.consts.get(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lib::CanonPathBuf;
+    use lib::collections::AddMap;
+    use lib::front::TypeVarGen;
+    use lib::front::ast::Ast;
+    use lib::front::inference::infer_types;
+    use lib::front::parse::lower_src;
+    use super::{show_float64, ConstEnv, Interpreter};
+
+    /// Returns the type checked AST of the program `src`
+    fn parse<'s>(src: &'s str, sources: &'s AddMap<CanonPathBuf, String>) -> Ast<'s> {
+        let mut tvg = TypeVarGen::new(0);
+        let mut ast = lower_src(src, sources, &mut tvg)
+            .unwrap_or_else(|_| panic!("Failed to parse `{}`", src));
+        infer_types(&mut ast, &mut tvg);
+        ast
+    }
+
+    /// Returns the value of the global variable `name` of `ast`, as displayed
+    fn eval_global(ast: &Ast, name: &str) -> String {
+        let mut interp = Interpreter::new(ast);
+        let pos = interp.global_defs[name].pos.clone();
+        interp.eval_global(name, &pos).to_string()
+    }
+
+    #[test]
+    fn test_eval_dependent_let() {
+        let sources = AddMap::new();
+        let ast = parse(
+            "(define x (: (let ((p (cons 1 2)) (q (car p)) (r (cons q (cdr p)))) (cdr r)) Int64))",
+            &sources,
+        );
+        assert_eq!(eval_global(&ast, "x"), "2");
+    }
+
+    #[test]
+    fn test_eval_closures() {
+        let sources = AddMap::new();
+        let ast = parse(
+            "(define (k a b) a)
+             (define x (: (let ((f (k 7)) (v (f 8))) v) Int64))
+             (define y (: (let ((a 3) (g (lambda (b) (cons a b))) (p (g 4))) (car p)) Int64))",
+            &sources,
+        );
+        assert_eq!(eval_global(&ast, "x"), "7");
+        assert_eq!(eval_global(&ast, "y"), "3");
+    }
+
+    #[test]
+    fn test_fold_dependent_let() {
+        let sources = AddMap::new();
+        let ast = parse(
+            "(define x (: (let ((p (cons 2 3)) (a (cdr p))) a) Int64))",
+            &sources,
+        );
+        let consts = ConstEnv::new(&ast);
+        assert_eq!(consts.get("x").map(|v| v.to_string()), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_show_float64() {
+        // As shown by `printf("%.17g", x)`
+        let cases = [
+            (0.1, "0.10000000000000001"),
+            (1.0, "1"),
+            (-2.5, "-2.5"),
+            (1e20, "1e+20"),
+            (1.5e-7, "1.4999999999999999e-07"),
+            (123456789.125, "123456789.125"),
+            (0.0001, "0.0001"),
+            (1e16, "10000000000000000"),
+            (1e17, "1e+17"),
+            (-0.0, "-0"),
+        ];
+        for &(x, shown) in &cases {
+            assert_eq!(show_float64(x), shown);
+            assert_eq!(shown.parse::<f64>(), Ok(x));
+        }
+    }
+
+    #[test]
+    fn test_eval_bytes() {
+        let sources = AddMap::new();
+        let ast = parse(
+            r#"(extern hex_to_bytes (-> (Cons UIntPtr (Ptr UInt8)) (Cons UIntPtr (Ptr UInt8))))
+               (extern byte_at (-> (Cons (Cons UIntPtr (Ptr UInt8)) UIntPtr) UInt8))
+               (extern is_utf8 (-> (Cons UIntPtr (Ptr UInt8)) Bool))
+               (define text (hex_to_bytes "6B7673"))
+               (define binary (hex_to_bytes "DEADBEEF"))
+               (define byte (byte_at (cons binary 1)))
+               (define text-utf8 (is_utf8 text))
+               (define binary-utf8 (is_utf8 binary))"#,
+            &sources,
+        );
+        assert_eq!(eval_global(&ast, "text"), r#""kvs""#);
+        assert_eq!(eval_global(&ast, "binary"), r#"#x"DEADBEEF""#);
+        assert_eq!(eval_global(&ast, "byte"), "173");
+        assert_eq!(eval_global(&ast, "text-utf8"), "true");
+        assert_eq!(eval_global(&ast, "binary-utf8"), "false");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_eval_regex() {
+        let sources = AddMap::new();
+        let ast = parse(
+            r#"(extern compile_regex (-> (Cons UIntPtr (Ptr UInt8)) (Ptr UInt8)))
+               (extern regex_is_match (-> (Cons (Ptr UInt8) (Cons UIntPtr (Ptr UInt8))) Bool))
+               (extern regex_group_count (-> (Ptr UInt8) UIntPtr))
+               (extern regex_has_group
+                       (-> (Cons (Ptr UInt8) (Cons (Cons UIntPtr (Ptr UInt8)) UIntPtr)) Bool))
+               (extern regex_group (-> (Cons (Ptr UInt8) (Cons (Cons UIntPtr (Ptr UInt8)) UIntPtr))
+                                       (Cons UIntPtr (Ptr UInt8))))
+               (define re (compile_regex "([a-z]+)-([0-9]+)|(x)"))
+               (define matches (regex_is_match (cons re "id: abc-123")))
+               (define mismatches (regex_is_match (cons re "ABC")))
+               (define count (regex_group_count re))
+               (define number (regex_group (cons re (cons "id: abc-123" 2))))
+               (define has-x (regex_has_group (cons re (cons "id: abc-123" 3))))"#,
+            &sources,
+        );
+        assert_eq!(eval_global(&ast, "matches"), "true");
+        assert_eq!(eval_global(&ast, "mismatches"), "false");
+        assert_eq!(eval_global(&ast, "count"), "3");
+        assert_eq!(eval_global(&ast, "number"), r#""123""#);
+        assert_eq!(eval_global(&ast, "has-x"), "false");
+    }
+
+    #[test]
+    fn test_run_io_main() {
+        let sources = AddMap::new();
+        let ast = parse(
+            "(extern program_arg_count (-> RealWorld (Cons UInt64 RealWorld)))
+             (define (>>= io f)
+               (lambda (real-world)
+                 (let ((r (io real-world))
+                       (v (car r))
+                       (real-world2 (cdr r)))
+                   (f v real-world2))))
+             (define (return x real-world) (cons x real-world))
+             (define main (>>= program_arg_count return))",
+            &sources,
+        );
+        let mut interp = Interpreter::new(&ast);
+        let args = vec!["test".to_string(), "arg".to_string()];
+        assert_eq!(interp.run_main(args).to_string(), "(cons 2 <real world>)");
+    }
+}
@@ -0,0 +1,425 @@
+//! Project manifests, `kvasir.toml`
+//!
+//! A manifest holds the options of a build, so that a multi-file project can be built by
+//! running the compiler in the project directory, without a long command line. If no source
+//! file is given, the manifest is searched for in the current directory and its ancestors.
+//! Options given on the command line take precedence over those of the manifest.
+//!
+//! Only a subset of TOML is supported: top level keys with string, integer,
+//! or array of strings values, and comments. Relative paths are relative to the manifest.
+//!
+//! ```toml
+//! # The source file of the entry point of the program
+//! main = "src/main.kvs"
+//! emit = "exe"
+//! out = "build/main.bin"
+//! target = "x86_64-unknown-linux-gnu"
+//! libs = ["m"]
+//! lib-paths = ["/usr/local/lib"]
+//! error-limit = 20
+//! features = ["networking"]
+//! plugins = ["plugins/libcheck_names.so"]
+//! ```
+//!
+//! A manifest may instead describe a workspace of multiple packages, each a directory with
+//! a manifest of its own. The members of a workspace are built in order of their dependencies,
+//! such that a package is built after the packages it depends on.
+//!
+//! Workspaces only order the builds. Each member is built as a separate program, and can't
+//! `import` the modules of the packages it depends on, as imports are resolved relative to
+//! the directory of the importing package only.
+//!
+//! TODO: A module search path including the directories of the dependencies of a package,
+//!       so that a package can import from them.
+//!
+//! ```toml
+//! # kvasir.toml
+//! members = ["parser", "app"]
+//!
+//! # app/kvasir.toml
+//! main = "main.kvs"
+//! dependencies = ["../parser"]
+//! ```
+
+use lib::front::error_exit;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The state of a workspace member in the depth first search for a build order
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Unvisited,
+    Visiting,
+    Visited,
+}
+
+/// The filename of manifests
+pub const MANIFEST_FILENAME: &'static str = "kvasir.toml";
+
+/// The value of a key in a manifest
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Array(Vec<String>),
+}
+
+/// The options of a build, as given by a manifest
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    /// The directory of the manifest
+    pub dir: PathBuf,
+    /// The source file of the entry point of the program
+    pub main: Option<PathBuf>,
+    /// The type of output to emit, as given to `--emit`
+    pub emit: Option<String>,
+    /// The output file
+    pub out: Option<PathBuf>,
+    /// The target triple
+    pub target: Option<String>,
+    /// Libraries to link with
+    pub libs: Vec<String>,
+    /// Paths to add to the library search path
+    pub lib_paths: Vec<PathBuf>,
+    /// The maximum number of errors to print
+    pub error_limit: Option<usize>,
+    /// Features to enable, as given to `--features`
+    pub features: Vec<String>,
+    /// Dynamic libraries of compiler plugins to load
+    pub plugins: Vec<PathBuf>,
+    /// The directories of the packages of the workspace, if the manifest is of a workspace
+    pub members: Vec<PathBuf>,
+    /// The directories of the workspace members that this package depends on
+    ///
+    /// Only orders the builds of the members. The modules of the dependencies are not
+    /// importable from this package.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Parse the basic string literal at the start of `s`
+///
+/// Returns the unescaped string and the rest of `s`
+fn parse_str(s: &str) -> Result<(String, &str), String> {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        Some((_, '"')) => (),
+        _ => return Err("Expected string".to_string()),
+    }
+    let mut string = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, &s[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                _ => return Err("Invalid escape sequence in string".to_string()),
+            },
+            _ => string.push(c),
+        }
+    }
+    Err("Unterminated string".to_string())
+}
+
+/// Check that nothing but whitespace and comments remain on the line `rest`
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_left();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("Unexpected `{}` after value", rest))
+    }
+}
+
+/// Parse the value of a key
+fn parse_value(s: &str) -> Result<Value, String> {
+    if s.starts_with('"') {
+        let (string, rest) = parse_str(s)?;
+        expect_end(rest).map(|_| Value::Str(string))
+    } else if s.starts_with('[') {
+        let mut items = Vec::new();
+        let mut rest = s[1..].trim_left();
+        if rest.starts_with(']') {
+            return expect_end(&rest[1..]).map(|_| Value::Array(items));
+        }
+        loop {
+            let (item, after) = parse_str(rest)?;
+            items.push(item);
+            let after = after.trim_left();
+            if after.starts_with(',') {
+                rest = after[1..].trim_left()
+            } else if after.starts_with(']') {
+                return expect_end(&after[1..]).map(|_| Value::Array(items));
+            } else {
+                return Err("Expected `,` or `]` in array".to_string());
+            }
+        }
+    } else {
+        let num = s.split('#').next().unwrap_or("").trim();
+        num.replace('_', "")
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| format!("Invalid value `{}`", num))
+    }
+}
+
+impl Manifest {
+    /// Set the option `key` to `val`
+    fn set(&mut self, key: &str, val: Value) -> Result<(), String> {
+        match (key, val) {
+            ("main", Value::Str(s)) => self.main = Some(self.dir.join(s)),
+            ("emit", Value::Str(s)) => self.emit = Some(s),
+            ("out", Value::Str(s)) => self.out = Some(self.dir.join(s)),
+            ("target", Value::Str(s)) => self.target = Some(s),
+            ("libs", Value::Array(xs)) => self.libs = xs,
+            ("lib-paths", Value::Array(xs)) => {
+                let paths = xs.iter().map(|x| self.dir.join(x)).collect();
+                self.lib_paths = paths
+            }
+            ("error-limit", Value::Int(n)) if n > 0 => self.error_limit = Some(n as usize),
+            ("features", Value::Array(xs)) => self.features = xs,
+            ("plugins", Value::Array(xs)) => {
+                let paths = xs.iter().map(|x| self.dir.join(x)).collect();
+                self.plugins = paths
+            }
+            ("members", Value::Array(xs)) => {
+                let dirs = xs.iter().map(|x| self.dir.join(x)).collect();
+                self.members = dirs
+            }
+            ("dependencies", Value::Array(xs)) => {
+                let dirs = xs.iter().map(|x| self.dir.join(x)).collect();
+                self.dependencies = dirs
+            }
+            ("main", _) | ("emit", _) | ("out", _) | ("target", _) | ("libs", _)
+            | ("lib-paths", _) | ("error-limit", _) | ("features", _) | ("plugins", _)
+            | ("members", _) | ("dependencies", _) => {
+                return Err(format!("Invalid value for key `{}`", key))
+            }
+            _ => return Err(format!("Unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// Parse the source `src` of the manifest in the directory `dir`
+    ///
+    /// Returns the line number and message of the first error, if any
+    fn parse(dir: &Path, src: &str) -> Result<Self, (usize, String)> {
+        let mut manifest = Manifest {
+            dir: dir.to_path_buf(),
+            ..Manifest::default()
+        };
+        let mut keys = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let row = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            } else if line.starts_with('[') {
+                return Err((row, "Tables are not supported in manifests".to_string()));
+            }
+            let eq = line.find('=')
+                .ok_or((row, "Expected `KEY = VALUE`".to_string()))?;
+            let (key, val_src) = (line[..eq].trim(), line[eq + 1..].trim());
+            if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+                return Err((row, format!("Invalid key `{}`", key)));
+            }
+            if keys.contains(&key) {
+                return Err((row, format!("Duplicate key `{}`", key)));
+            }
+            keys.push(key);
+            parse_value(val_src)
+                .and_then(|val| manifest.set(key, val))
+                .map_err(|msg| (row, msg))?;
+        }
+        Ok(manifest)
+    }
+}
+
+/// Returns the path of the manifest in `dir` or the closest of its ancestors, if any
+pub fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    let mut dir = Some(dir);
+    while let Some(d) = dir {
+        let path = d.join(MANIFEST_FILENAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+/// Load the manifest at `path`
+///
+/// Exits with an error if the manifest can't be read or is invalid
+pub fn load_manifest(path: &Path) -> Manifest {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .unwrap_or_else(|e| {
+            error_exit(format!("Failed to read manifest `{}`, {}", path.display(), e))
+        });
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Manifest::parse(dir, &src).unwrap_or_else(|(row, msg)| {
+        error_exit(format!("{}:{}: {}", path.display(), row, msg))
+    })
+}
+
+/// Add the member `i`, after all the members it depends on, to `order`
+fn visit_member(
+    i: usize,
+    members: &[Manifest],
+    dirs: &[PathBuf],
+    states: &mut [Visit],
+    order: &mut Vec<usize>,
+) {
+    match states[i] {
+        Visit::Visited => return,
+        Visit::Visiting => error_exit(format!(
+            "Cyclic dependency between workspace members, involving `{}`",
+            members[i].dir.display()
+        )),
+        Visit::Unvisited => (),
+    }
+    states[i] = Visit::Visiting;
+    for dep in &members[i].dependencies {
+        let dep_dir = dep.canonicalize().unwrap_or_else(|e| {
+            error_exit(format!(
+                "Failed to find dependency `{}` of `{}`, {}",
+                dep.display(),
+                members[i].dir.display(),
+                e
+            ))
+        });
+        match dirs.iter().position(|dir| *dir == dep_dir) {
+            Some(j) => visit_member(j, members, dirs, states, order),
+            None => error_exit(format!(
+                "Dependency `{}` of `{}` is not a member of the workspace",
+                dep.display(),
+                members[i].dir.display()
+            )),
+        }
+    }
+    states[i] = Visit::Visited;
+    order.push(i)
+}
+
+/// Returns the manifests of the members of `workspace`, ordered such that each member
+/// comes after the members it depends on
+///
+/// Exits with an error if a member can't be loaded, or if the dependencies are cyclic
+pub fn workspace_build_order(workspace: &Manifest) -> Vec<Manifest> {
+    let members = workspace
+        .members
+        .iter()
+        .map(|dir| load_manifest(&dir.join(MANIFEST_FILENAME)))
+        .collect::<Vec<_>>();
+    let dirs = members
+        .iter()
+        .map(|m| m.dir.canonicalize().unwrap_or(m.dir.clone()))
+        .collect::<Vec<_>>();
+    let mut states = vec![Visit::Unvisited; members.len()];
+    let mut order = Vec::new();
+    for i in 0..members.len() {
+        visit_member(i, &members, &dirs, &mut states, &mut order)
+    }
+    order.into_iter().map(|i| members[i].clone()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use super::{parse_value, Manifest, Value};
+
+    fn parse_err(src: &str) -> (usize, String) {
+        Manifest::parse(Path::new("proj"), src).unwrap_err()
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value(r#""a b""#), Ok(Value::Str("a b".to_string())));
+        assert_eq!(
+            parse_value(r#""\"\\\n\t" # comment"#),
+            Ok(Value::Str("\"\\\n\t".to_string()))
+        );
+        assert_eq!(parse_value("20"), Ok(Value::Int(20)));
+        assert_eq!(parse_value("-1_000 # comment"), Ok(Value::Int(-1000)));
+        assert_eq!(parse_value("[]"), Ok(Value::Array(vec![])));
+        assert_eq!(
+            parse_value(r##"[ "m" ,"ssl", "#" ] # comment"##),
+            Ok(Value::Array(vec!["m".to_string(), "ssl".to_string(), "#".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_parse_value_errors() {
+        let err = |s| parse_value(s).unwrap_err();
+        assert_eq!(err(r#""a"#), "Unterminated string");
+        assert_eq!(err(r#""\q""#), "Invalid escape sequence in string");
+        assert_eq!(err(r#""a" b"#), "Unexpected `b` after value");
+        assert_eq!(err(r#"[] b"#), "Unexpected `b` after value");
+        assert_eq!(err(r#"[1]"#), "Expected string");
+        assert_eq!(err(r#"["a" "b"]"#), "Expected `,` or `]` in array");
+        assert_eq!(err(r#"["a""#), "Expected `,` or `]` in array");
+        assert_eq!(err("true"), "Invalid value `true`");
+        assert_eq!(err("1.5"), "Invalid value `1.5`");
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let src = r#"
+# The entry point
+main = "src/main.kvs"
+emit = "exe"   # trailing comment
+out = "build/main.bin"
+target = "x86_64-unknown-linux-gnu"
+libs = ["m"]
+lib-paths = ["/usr/local/lib", "lib"]
+error-limit = 20
+features = ["networking"]
+plugins = ["plugins/libcheck_names.so"]
+members = ["parser"]
+dependencies = ["../parser"]
+"#;
+        let m = Manifest::parse(Path::new("proj"), src).unwrap();
+        assert_eq!(m.dir, PathBuf::from("proj"));
+        assert_eq!(m.main, Some(PathBuf::from("proj/src/main.kvs")));
+        assert_eq!(m.emit, Some("exe".to_string()));
+        assert_eq!(m.out, Some(PathBuf::from("proj/build/main.bin")));
+        assert_eq!(m.target, Some("x86_64-unknown-linux-gnu".to_string()));
+        assert_eq!(m.libs, vec!["m".to_string()]);
+        // Absolute paths are kept as is
+        assert_eq!(
+            m.lib_paths,
+            vec![PathBuf::from("/usr/local/lib"), PathBuf::from("proj/lib")]
+        );
+        assert_eq!(m.error_limit, Some(20));
+        assert_eq!(m.features, vec!["networking".to_string()]);
+        assert_eq!(m.plugins, vec![PathBuf::from("proj/plugins/libcheck_names.so")]);
+        assert_eq!(m.members, vec![PathBuf::from("proj/parser")]);
+        assert_eq!(m.dependencies, vec![PathBuf::from("proj/../parser")]);
+    }
+
+    #[test]
+    fn test_parse_manifest_errors() {
+        assert_eq!(
+            parse_err("[package]"),
+            (1, "Tables are not supported in manifests".to_string())
+        );
+        assert_eq!(parse_err("\n\nmain"), (3, "Expected `KEY = VALUE`".to_string()));
+        assert_eq!(parse_err(r#"= "a""#), (1, "Invalid key ``".to_string()));
+        assert_eq!(parse_err(r#"a.b = "a""#), (1, "Invalid key `a.b`".to_string()));
+        assert_eq!(
+            parse_err("libs = []\nlibs = []"),
+            (2, "Duplicate key `libs`".to_string())
+        );
+        assert_eq!(parse_err("main = 1"), (1, "Invalid value for key `main`".to_string()));
+        assert_eq!(
+            parse_err("error-limit = 0"),
+            (1, "Invalid value for key `error-limit`".to_string())
+        );
+        assert_eq!(parse_err(r#"name = "a""#), (1, "Unknown key `name`".to_string()));
+        assert_eq!(parse_err(r#"main = "a"#), (1, "Unterminated string".to_string()));
+    }
+}
@@ -6,6 +6,9 @@ use std::path::{PathBuf, Path};
 pub mod front;
 pub mod back;
 pub mod collections;
+pub mod interp;
+pub mod manifest;
+pub mod plugin;
 
 /// A path-buffer that is guaranteed to be canonical
 #[derive(PartialEq, Clone)]
@@ -0,0 +1,198 @@
+//! Compiler plugins, custom passes run by the driver
+//!
+//! A plugin implements `CompilerPlugin`, in a crate depending on the `kvasir` library, and is
+//! registered with a `Plugins` by the program embedding the compiler. The driver calls the
+//! hooks of all registered plugins, in order of registration, at fixed points of the
+//! compilation: after the program is parsed, after it's type checked, and before code is
+//! generated. A hook may inspect or transform the AST, and aborts the compilation by
+//! returning an error.
+//!
+//! Plugins may also be loaded at runtime from dynamic libraries, listed with the `plugins` key
+//! of the manifest. Loading is only supported on Unix. Such a library depends on the `kvasir`
+//! library, and must export two functions with unmangled names:
+//!
+//! ```rust,ignore
+//! #[no_mangle]
+//! pub extern "C" fn kvasir_plugin_build_id() -> *const c_char {
+//!     PLUGIN_BUILD_ID.as_ptr() as *const c_char
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn kvasir_plugin_register(plugins: *mut Plugins) {
+//!     unsafe { (*plugins).register(Box::new(MyPlugin)) }
+//! }
+//! ```
+//!
+//! As Rust types and trait objects cross the library boundary, the layouts on both sides must
+//! match, which is only guaranteed for identical builds. The build id of the library, naming
+//! the version of the plugin API, of kvasir, and of rustc, is therefore checked to equal that
+//! of the compiler before anything else is called.
+//!
+//! TODO: Once macros are implemented, call `after_parse` after expansion instead.
+
+#[cfg(unix)]
+use libc;
+use lib::front::ast::Ast;
+use lib::front::error_exit;
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+#[cfg(unix)]
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// The build id of the plugin API, nul terminated, that dynamically loaded plugins must match
+///
+/// The number after `abi` must be bumped on every change to the plugin API. The versions of
+/// kvasir and rustc are those that built this crate.
+pub const PLUGIN_BUILD_ID: &'static str = concat!(
+    "kvasir-plugin-abi-1 kvasir-",
+    env!("CARGO_PKG_VERSION"),
+    " ",
+    env!("KVASIR_RUSTC_VERSION"),
+    "\0"
+);
+
+/// The name of the function returning the build id of a plugin library
+#[cfg(unix)]
+const BUILD_ID_SYMBOL: &'static [u8] = b"kvasir_plugin_build_id\0";
+
+/// The name of the function registering the plugins of a plugin library
+#[cfg(unix)]
+const REGISTER_SYMBOL: &'static [u8] = b"kvasir_plugin_register\0";
+
+/// Returns the message of the last error of the dynamic linker
+#[cfg(unix)]
+fn dl_error() -> String {
+    unsafe {
+        let msg = libc::dlerror();
+        if msg.is_null() {
+            "unknown error".to_string()
+        } else {
+            CStr::from_ptr(msg).to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// A compiler plugin. See the module documentation
+pub trait CompilerPlugin {
+    /// The name of the plugin, used in error messages
+    fn name(&self) -> &'static str;
+
+    /// Called with the AST of the program when it's parsed, before linting and type checking
+    fn after_parse<'src>(&self, _ast: &mut Ast<'src>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called with the AST of the program after type inference, when all types are known
+    fn after_typecheck<'src>(&self, _ast: &mut Ast<'src>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called with the final AST of the program, right before code is generated from it
+    fn before_emission<'src>(&self, _ast: &Ast<'src>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The plugins registered with the driver
+pub struct Plugins {
+    plugins: Vec<Box<CompilerPlugin>>,
+}
+
+impl Plugins {
+    /// Returns an empty set of plugins
+    pub fn new() -> Self {
+        Plugins { plugins: Vec::new() }
+    }
+
+    /// Add the plugin `plugin`, to be run after those already registered
+    pub fn register(&mut self, plugin: Box<CompilerPlugin>) {
+        self.plugins.push(plugin)
+    }
+
+    /// Load the dynamic library at `path`, and register the plugins it provides
+    ///
+    /// Returns an error if the library can't be loaded, lacks an entry point, or is of another
+    /// build of the plugin API. The library is never unloaded, as the registered plugins refer
+    /// to its code
+    #[cfg(unix)]
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| "path contains a nul byte".to_string())?;
+        unsafe {
+            let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            if handle.is_null() {
+                return Err(dl_error());
+            }
+            let build_id_fn = libc::dlsym(handle, BUILD_ID_SYMBOL.as_ptr() as *const _);
+            if build_id_fn.is_null() {
+                libc::dlclose(handle);
+                return Err("missing entry point `kvasir_plugin_build_id`".to_string());
+            }
+            let build_id_fn: extern "C" fn() -> *const libc::c_char = mem::transmute(build_id_fn);
+            let build_id = CStr::from_ptr(build_id_fn()).to_string_lossy().into_owned();
+            let own_build_id = &PLUGIN_BUILD_ID[..PLUGIN_BUILD_ID.len() - 1];
+            if build_id != own_build_id {
+                libc::dlclose(handle);
+                return Err(format!(
+                    "built as `{}`, but the compiler is `{}`",
+                    build_id,
+                    own_build_id
+                ));
+            }
+            let register_fn = libc::dlsym(handle, REGISTER_SYMBOL.as_ptr() as *const _);
+            if register_fn.is_null() {
+                libc::dlclose(handle);
+                return Err("missing entry point `kvasir_plugin_register`".to_string());
+            }
+            let register_fn: extern "C" fn(*mut Plugins) = mem::transmute(register_fn);
+            register_fn(self);
+        }
+        Ok(())
+    }
+
+    /// Dynamic loading of plugins is only supported on Unix
+    #[cfg(not(unix))]
+    pub fn load(&mut self, _path: &Path) -> Result<(), String> {
+        Err("loading plugins is only supported on Unix".to_string())
+    }
+
+    /// Run the `after_parse` hook of each plugin over `ast`
+    ///
+    /// Exits with an error if a hook fails
+    pub fn after_parse(&self, ast: &mut Ast) {
+        for plugin in &self.plugins {
+            plugin
+                .after_parse(ast)
+                .unwrap_or_else(|e| plugin_error(&**plugin, e))
+        }
+    }
+
+    /// Run the `after_typecheck` hook of each plugin over `ast`
+    ///
+    /// Exits with an error if a hook fails
+    pub fn after_typecheck(&self, ast: &mut Ast) {
+        for plugin in &self.plugins {
+            plugin
+                .after_typecheck(ast)
+                .unwrap_or_else(|e| plugin_error(&**plugin, e))
+        }
+    }
+
+    /// Run the `before_emission` hook of each plugin over `ast`
+    ///
+    /// Exits with an error if a hook fails
+    pub fn before_emission(&self, ast: &Ast) {
+        for plugin in &self.plugins {
+            plugin
+                .before_emission(ast)
+                .unwrap_or_else(|e| plugin_error(&**plugin, e))
+        }
+    }
+}
+
+fn plugin_error(plugin: &CompilerPlugin, msg: String) -> ! {
+    error_exit(format!("Plugin `{}` failed, {}", plugin.name(), msg))
+}
@@ -75,66 +75,210 @@
 //            `(let-type T (impl Drop (Vec T)))` which comes before
 //            `(let-type [T Iter Extend Clone] (impl Drop T))` which comes before
 //            `(let-type T (impl Drop for T any T))`
+// TODO: Instances with contexts, e.g. `(impl ((Show a)) (Show (List a)) ...)`. When resolving
+//       a method for `(List Int32)`, the context `(Show Int32)` must in turn be resolved,
+//       recursively, and failing that, the error should point at the unsatisfied context.
 // TODO: Base macro system on pure functions that has syntax trees as input and output.
 //       This would require some kind of interpretation in order to execute code at compile time
+//       Templates of expansions would be quasiquoted, with `unquote` evaluating a
+//       subexpression and `unquote-splicing` splicing a list of trees into the enclosing list.
+//       The lexer already reads `` ` ``, `,`, and `,@` as these forms.
+//       During expansion, the special name `$here` should be substituted with the path of the
+//       module defining the macro, and `$args` with the arguments of the invocation as given,
+//       so that a macro can refer to the definitions of its own module unambiguously.
+// TODO: When a macro system exists, accept an optional post-expansion validation callback
+//       that is run on every expansion result. The driver would use it to check
+//       well-formedness, e.g. that a variable definition only appears in statement position,
+//       and report errors at the expansion site instead of in some later phase.
+//       In particular, a macro that expands to a local variable definition, when invoked at
+//       the top level, should be reported as such at the invocation, with help suggesting a
+//       global definition or moving the invocation into a function, instead of the generic
+//       error of an invalid top level item.
+// TODO: Count macro invocations during expansion, and afterwards warn about macros that were
+//       defined but never invoked. Useful for pruning large prelude files.
+//       Similarly, record which rules of each macro were ever matched during a test run,
+//       and report the rules that were never matched, to find untested branches.
+// TODO: When macros are defined by pattern matching rules, and no rule matches an invocation,
+//       report for each rule the index and position of the first element of the arguments
+//       where matching failed, instead of just that no rule matched.
+//       Rules should also accept docstrings, like global definitions do, to be listed
+//       together with the pattern of each rule in generated documentation.
+//       A rule should accept any number of templates after its pattern, all spliced in
+//       order into the expansion, so that one invocation can expand to several items.
+// TODO: Once macros execute code at compile time, limit the resources an expansion may use:
+//       a time budget, a cap on allocated memory by counting allocations in the evaluator,
+//       and a maximum size of the expanded syntax tree. Tools that load untrusted projects,
+//       like an editor integration, could then set strict limits without risking a hang.
+// TODO: A refactoring that inlines a single macro invocation, replacing it in the source with
+//       its expansion, pretty printed. Identifiers introduced by the macro must be renamed
+//       where they would clash with the names at the call site, so that the result means the
+//       same as the invocation did. Useful when migrating away from a macro, or debugging one.
+// TODO: When a macro is invoked with literal arguments, evaluate the parts of its expansion
+//       that only depend on them eagerly, and cache the expansion per distinct arguments.
+//       Programs that instantiate the same macro thousands of times would then only expand it
+//       once per distinct argument list. Requires macros to be pure, see above.
+// TODO: Errors of macro definitions and expansions should be returned as `Result`s, like
+//       the `PErr`s of the parser, rather than printed with `error_exit`, so that tools
+//       embedding the frontend, e.g. a language server, can report them and keep running.
+//       Expansion could be done by a public `MacroExpander`, visiting the syntax trees and
+//       carrying the table of macros, the recursion depth, hygiene scopes, and the collected
+//       diagnostics, so that tools can expand single trees with the same state as the compiler.
+// TODO: Concurrency primitives. `(spawn EXPR)` and `(join HANDLE)`, and channels with
+//       `chan`, `send`, and `recv`. Requires thread support in the runtime, e.g. wrapping
+//       pthreads in core.c, and closures that can be passed across the C ABI. Values sent
+//       between threads must not share mutable state, which could be checked with a
+//       `Send`-like constraint once user defined constraints exist.
+// TODO: `(async ...)` blocks and `(await FUT)` with an `(Async T)` type. Could be implemented
+//       as a CPS transform in the front-end, in the same vein as the `RealWorld` IO threading,
+//       with an event loop provided by the runtime.
+// TODO: `(defer EXPR)` to register cleanup to run at scope exit. Needs a sequencing form,
+//       like `begin`, to have a scope to exit from. Would be lowered to evaluating EXPR after
+//       the last expression of the enclosing sequence, but before returning its value.
+// TODO: A backend that emits Rust source, for targets without an LLVM toolchain. The edition
+//       of the generated code should be selectable with a flag, defaulting to 2021, and the
+//       output must avoid deprecated idioms so that it compiles on current stable rustc.
+//       Once it exists, add a test mode that runs `rustc --edition 2021 --crate-type lib` on
+//       the output for every example, and fails if any of them doesn't compile. That catches
+//       regressions in escaping, generics, and return types that comparing strings would miss.
+// TODO: A source formatter, `kvasir fmt`. When added, test over all examples that formatting
+//       is idempotent, and that the syntax trees of the formatted and unformatted sources are
+//       equal up to positions, i.e. `cst_eq` holds pairwise.
+// TODO: Separate compilation. Compile each module to an object file together with an interface
+//       file of its exported types and symbols, and link the objects in a separate step, so
+//       that only changed modules and their dependents have to be rebuilt. The global symbols
+//       are already mangled stably, see `back::mangle`, but polymorphic definitions are
+//       monomorphized over the whole program, so they'd need to be exported as source, or
+//       in some serialized AST form, in the interface.
+// TODO: A content addressed store of build artifacts. Key the code generated for each
+//       monomorphic instance of a global by a hash of its typed AST, disregarding positions,
+//       and its symbol name, and reuse the stored object code when the key is unchanged.
+//       Builds on separate compilation, but with the granularity of single definitions.
+// TODO: Profile guided optimization. `--profile-generate` to build with a counter at the
+//       entry of every function, written to a profile file at exit by the runtime, and
+//       `--profile-use PROFILE` to mark the hot functions `inlinehint` and the cold ones
+//       `cold` when generating LLVM IR, and to order the functions in the module by heat.
+// TODO: Selectable semantics of integer overflow. Arithmetic currently wraps, both in the
+//       interpreter and in generated code. Add `(wrapping EXPR)`, `(checked EXPR)`, and
+//       `(saturating EXPR)` forms that select the semantics of the arithmetic operations
+//       within EXPR, and a module level default. `checked` would abort with the position of
+//       the operation, and needs the `llvm.*.with.overflow` intrinsics in codegen.
+// TODO: Mutable global variables. `(define-static NAME TYPE VALUE)` for a global of constant
+//       initializer, emitted as an LLVM global, and a lazily initialized variant for other
+//       initializers, evaluated on first access behind a flag. Reads and writes would be IO
+//       actions, in the same vein as other effects, to keep the rest of the language pure.
+//       Once there are threads, access must be synchronized, or the type restricted to ones
+//       that are safe to share.
+// TODO: Dynamically dispatched types, `(Dyn Constraint)`, for heterogeneous collections.
+//       A value of a type that implements the constraint would be boxed where it's coerced
+//       to the `Dyn` type, together with a table of the methods of the constraint for its type.
+//       Requires user defined constraints with methods, see the TODOs on instances above.
+// TODO: Generic data types, e.g. `(data (Option a) None (Some a))`, so that the standard
+//       library can define `Option`, `Result`, and collections like growable arrays and hash
+//       maps as proper types with type checked operations. Currently `right` and `left` are
+//       pairs of a flag and a value, which requires a value of the same type in both cases.
+// TODO: `Eq`, `Ord`, and `Hash` constraints, derivable for data types with
+//       `(data NAME (deriving Eq Ord Hash) VARIANTS...)`, so that user types can be keys of
+//       maps. Derived instances would be generated functions comparing discriminants first,
+//       then members in order. Requires user defined constraints, see the TODOs on instances.
+//       Newtypes can already derive `Num` from their underlying type, which should extend to
+//       the new constraints.
+// TODO: A derivable `Show` constraint, `(data NAME (deriving Show) VARIANTS...)`, printing a
+//       value in the syntax that constructs it, e.g. `(Point 1 2)`, so that `print` works on
+//       user types. The interpreter's printing of values in `interp` should then agree with
+//       the generated functions of the compiled code, which the examples could be tested on.
+// TODO: JSON in the standard library. A `Json` data type, parsing and printing of it, and
+//       derivable `ToJson` and `FromJson` constraints to convert user types, like `Show`
+//       above. Needs generic data types for arrays and objects, and user defined constraints.
+// TODO: Comprehensions over iterators, e.g. `(collect (for x xs (when (> x 0)) (* x x)))`,
+//       desugared to the iterator functions of the standard library, `filter` and `for`.
+//       Mapping needs a proper sum type for the items of iterators, see generic data types
+//       above, and collecting needs a list type.
+// TODO: A REPL. Line editing with persistent history, and tab completion over the names of
+//       the globals, externs, and data types defined in the session.
+//       `:load PATH` to load a file into the session, keeping track of which definitions came
+//       from which file, and `:reload` to reparse a changed file while keeping the rest of
+//       the session.
+// TODO: A lint for a suspicious `=` where an assignment with `set` was likely meant, e.g. an
+//       `(= x 1)` whose value is discarded. There is no `set` form yet, as variables are
+//       immutable, so the lint is deferred until mutable variables, see mutable globals above.
 
 #![feature(non_ascii_idents, box_syntax, box_patterns, conservative_impl_trait)]
 
 #![deny(missing_docs)]
 
-#[macro_use]
-extern crate lazy_static;
 extern crate getopts;
-extern crate bitflags;
-extern crate term;
-extern crate llvm_sys;
-extern crate itertools;
-extern crate libc;
-extern crate cbox;
-#[macro_use]
-extern crate maplit;
+extern crate kvasir;
 
 use getopts::Options;
+use kvasir::lib;
 use lib::CanonPathBuf;
 use lib::collections::AddMap;
-use lib::back::compile;
-use lib::front::inference::infer_types;
+use lib::back::{compile, Emission};
+use lib::back::target::TargetConfig;
+use lib::front::{error_exit, exit, set_error_limit};
+use lib::front::inference::{check_types, infer_types, write_inferred_types};
 use lib::front::parse::parse_program;
-use std::{env, fmt, time};
-
-mod lib;
-
-/// Enum of the different output formats of the compiler
-pub enum Emission {
-    /// Human readable LLVM assembly language code
-    LlvmAsm,
-    /// LLVM bitcode
-    LlvmBc,
-    /// Linkable object code
-    Obj,
-    /// An executable binary
-    Exe,
-}
-impl<S: AsRef<str> + fmt::Display> From<S> for Emission {
-    fn from(s: S) -> Emission {
-        match s.as_ref() {
-            "llvm-ir" => Emission::LlvmAsm,
-            "llvm-bc" => Emission::LlvmBc,
-            "obj" => Emission::Obj,
-            "exe" => Emission::Exe,
-            _ => panic!("Unknown emission type `{}`", s),
-        }
-    }
-}
+use lib::front::symbols::{index_symbols, write_edits_json};
+use lib::front::callgraph::write_callgraph_dot;
+use lib::front::cst_diff::{diff_csts, write_edit_script};
+use lib::front::lex::lex_file;
+use lib::front::grammar::write_ebnf;
+use lib::front::interface::write_interface;
+use lib::front::lint::{Level, Linter};
+use lib::front::reduce::{reduce, write_program, Predicate};
+use lib::front::termination::check_termination;
+use lib::interp::Interpreter;
+use lib::plugin::Plugins;
+use lib::manifest::{find_manifest, load_manifest, workspace_build_order, MANIFEST_FILENAME};
+use std::{env, fs, io, iter, time};
+use std::process::Command;
 
 #[cfg(windows)]
 const BIN_EXT: &'static str = "exe";
 #[cfg(not(windows))]
 const BIN_EXT: &'static str = "bin";
 
+/// Write the output of a front-end emission to `out_filename`,
+/// or to the same path with extension `ext` if the filename was not explicitly given
+fn write_front_emission<F>(out_filename: &CanonPathBuf, explicit_filename: bool, ext: &str, write: F)
+where
+    F: FnOnce(&mut fs::File) -> io::Result<()>,
+{
+    let filename = if explicit_filename {
+        out_filename.clone()
+    } else {
+        out_filename.with_extension(ext)
+    };
+    let mut file = fs::File::create(filename.path()).unwrap_or_else(|e| {
+        panic!("Failed to open file `{}`, {}", filename.path().display(), e)
+    });
+    write(&mut file).unwrap_or_else(|e| {
+        panic!("Failed to write to `{}`, {}", filename.path().display(), e)
+    })
+}
+
+/// Parse an argument of a refactoring, like `--rename`, of the form `LINE:COLUMN=NAME`
+fn parse_refactor_arg(arg: &str) -> Option<(usize, usize, &str)> {
+    let eq = match arg.find('=') {
+        Some(i) => i,
+        None => return None,
+    };
+    let (pos, new_name) = (&arg[..eq], &arg[eq + 1..]);
+    let mut line_col = pos.splitn(2, ':').map(|n| n.parse::<usize>().ok());
+    match (line_col.next(), line_col.next()) {
+        (Some(Some(line)), Some(Some(column))) => Some((line, column, new_name)),
+        _ => None,
+    }
+}
+
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [options] SOURCE-FILE", program);
+    let brief = format!("Usage: {} [options] [SOURCE-FILE]", program);
     print!("{}", opts.usage(&brief));
+    println!(
+        "\nIf SOURCE-FILE is not given, options are read from the project manifest `{}` \
+         in the current directory or its closest ancestor",
+        MANIFEST_FILENAME
+    );
 }
 
 fn main() {
@@ -147,10 +291,97 @@ fn main() {
             "",
             "emit",
             "Specify the type of output for the compiler to emit",
-            "llvm-ir|llvm-bc|obj|exe",
+            "llvm-ir|llvm-bc|obj|exe|symbols|callgraph|types|interface",
+        )
+        .optopt("", "target", "Compile for the target <TRIPLE>", "TRIPLE")
+        .optopt(
+            "",
+            "error-limit",
+            "Print at most <N> errors, and only count the rest. Defaults to 100",
+            "N",
+        )
+        .optopt(
+            "",
+            "diff",
+            "Print a structural diff of the syntax trees of <OLD-FILE> and the source file, \
+             and exit",
+            "OLD-FILE",
+        )
+        .optopt(
+            "",
+            "reduce",
+            "Reduce the source file to a minimal program that still makes <COMMAND> fail, \
+             print it, and exit. The path of each candidate program is appended to <COMMAND>",
+            "COMMAND",
+        )
+        .optopt(
+            "",
+            "reduce-exit-code",
+            "With --reduce, only count it as a failure if <COMMAND> exits with <CODE>",
+            "CODE",
+        )
+        .optopt(
+            "",
+            "reduce-message",
+            "With --reduce, only count it as a failure if the output of <COMMAND> \
+             contains <MESSAGE>",
+            "MESSAGE",
+        )
+        .optopt(
+            "",
+            "rename",
+            "Write the edits that rename the symbol at <LINE>:<COLUMN> of the source file, \
+             and all references to it, to <NEW-NAME> as JSON, and exit",
+            "LINE:COLUMN=NEW-NAME",
+        )
+        .optopt(
+            "",
+            "extract",
+            "Write the edits that extract the expression at <LINE>:<COLUMN> of the source file \
+             into a new global definition <NAME> as JSON, and exit",
+            "LINE:COLUMN=NAME",
+        )
+        .optflag(
+            "",
+            "interpret",
+            "Run the program with the interpreter instead of compiling it",
+        )
+        .optopt(
+            "",
+            "coverage",
+            "When interpreting, write the number of evaluations of each line of the program \
+             to <FILE>, in the lcov format",
+            "FILE",
+        )
+        .optflag(
+            "",
+            "grammar",
+            "Print the grammar of the language in EBNF, and exit",
+        )
+        .optmulti("A", "allow", "Allow the lint <LINT>", "LINT")
+        .optmulti("W", "warn", "Warn about the lint <LINT>", "LINT")
+        .optmulti("D", "deny", "Deny the lint <LINT>, making its findings errors", "LINT")
+        .optflag("", "lints", "List the available lints, and exit")
+        .optmulti(
+            "",
+            "features",
+            "Enable the comma separated features <FEATURES>",
+            "FEATURES",
         )
         .optmulti("l", "", "Link with <LIBRARY>", "LIBRARY")
         .optmulti("L", "", "Add <PATH> to the library search path", "PATH")
+        .optmulti(
+            "",
+            "sanitize",
+            "Link the executable with the runtime of the clang sanitizer <SANITIZER>, \
+             e.g. `address`",
+            "SANITIZER",
+        )
+        .optflag(
+            "v",
+            "verbose",
+            "Report statistics of the work done, e.g. the number of tests of --reduce",
+        )
         .optflag("h", "help", "Display this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -160,14 +391,108 @@ fn main() {
         print_usage(&bin_name, opts);
         return;
     }
+    let mut linter = Linter::new();
+    if matches.opt_present("lints") {
+        for (name, description) in linter.lints() {
+            println!("    {:20} {}", name, description);
+        }
+        return;
+    }
+    let levels = [("A", Level::Allow), ("W", Level::Warn), ("D", Level::Deny)];
+    for &(opt, level) in &levels {
+        for name in matches.opt_strs(opt) {
+            linter
+                .set_level(&name, level)
+                .unwrap_or_else(|e| error_exit(e));
+        }
+    }
+    if matches.opt_present("grammar") {
+        write_ebnf(&mut io::stdout()).expect("Failed to write grammar");
+        return;
+    }
+    // Without a source file given, look for a project manifest to get the options from
+    let manifest = if matches.free.is_empty() {
+        env::current_dir()
+            .ok()
+            .and_then(|dir| find_manifest(&dir))
+            .map(|path| load_manifest(&path))
+    } else {
+        None
+    }.unwrap_or_default();
+    if !manifest.members.is_empty() {
+        // Build each package of the workspace with a separate invocation of the compiler.
+        // Dependencies only order the builds, they are not made importable to the dependents
+        let compiler = env::current_exe().expect("Failed to get path of the compiler");
+        for member in workspace_build_order(&manifest) {
+            println!("    Building workspace member {}", member.dir.display());
+            let status = Command::new(&compiler)
+                .args(&args[1..])
+                .current_dir(&member.dir)
+                .status()
+                .expect("Failed to run the compiler for a workspace member");
+            if !status.success() {
+                error_exit(format!(
+                    "Failed to build workspace member `{}`",
+                    member.dir.display()
+                ))
+            }
+        }
+        return;
+    }
     let inp_filename = if !matches.free.is_empty() {
         CanonPathBuf::new(&matches.free[0]).expect("Failed to canonicalize input filename")
+    } else if let Some(ref main) = manifest.main {
+        CanonPathBuf::new(&main.to_string_lossy()).expect("Failed to canonicalize input filename")
     } else {
         print_usage(&bin_name, opts);
         return;
     };
-    let out_filename = matches
+    if let Some(limit) = matches.opt_str("error-limit") {
+        match limit.parse() {
+            Ok(n) if n > 0 => set_error_limit(n),
+            _ => error_exit(format!("Invalid error limit `{}`", limit)),
+        }
+    } else if let Some(n) = manifest.error_limit {
+        set_error_limit(n)
+    }
+    // TODO: Instrument emitted code with counters as well, so that coverage can be measured
+    //       of compiled programs
+    if matches.opt_present("coverage") && !matches.opt_present("interpret") {
+        error_exit("`--coverage` is only supported together with `--interpret`")
+    }
+    if let Some(old) = matches.opt_str("diff") {
+        let old_filename = CanonPathBuf::new(&old).expect("Failed to canonicalize old filename");
+        let sources = AddMap::new();
+        let old_csts = lex_file(old_filename, &sources);
+        let new_csts = lex_file(inp_filename, &sources);
+        let edits = diff_csts(&old_csts, &new_csts);
+        write_edit_script(&edits, &mut io::stdout()).expect("Failed to write edit script");
+        return;
+    }
+    if let Some(command) = matches.opt_str("reduce") {
+        let predicate = Predicate {
+            command: command.split_whitespace().map(|s| s.to_string()).collect(),
+            exit_code: matches.opt_str("reduce-exit-code").map(|c| {
+                c.parse::<i32>()
+                    .unwrap_or_else(|_| error_exit(format!("Invalid exit code `{}`", c)))
+            }),
+            message: matches.opt_str("reduce-message"),
+            candidate_path: inp_filename.with_extension("reduced.kvs").path().to_path_buf(),
+        };
+        let sources = AddMap::new();
+        let csts = lex_file(inp_filename, &sources);
+        let (reduced, n_tests) = reduce(csts, &predicate);
+        if matches.opt_present("verbose") {
+            eprintln!("Reduced program in {} tests", n_tests);
+        }
+        write_program(&reduced, &mut io::stdout()).expect("Failed to write reduced program");
+        return;
+    }
+    let out_opt = matches
         .opt_str("o")
+        .or(manifest.out.map(|p| p.to_string_lossy().into_owned()));
+    let out_filename = out_opt
+        .as_ref()
         .map(|p| {
             CanonPathBuf::new(&p).expect("Failed to canonicalize output filename")
         })
@@ -179,28 +504,170 @@ fn main() {
         env::set_current_dir(inp_file_dir).expect("Failed to change dir to dir of input file")
     }
 
-    let explicit_out_filename = matches.opt_str("o").is_some();
-    let emission = matches.opt_str("emit").map(|s| s.into()).unwrap_or(
-        Emission::Exe,
-    );
-    let link_libs = matches.opt_strs("l");
-    let lib_paths = matches.opt_strs("L");
+    let explicit_out_filename = out_opt.is_some();
+    let emission = matches
+        .opt_str("emit")
+        .or(manifest.emit)
+        .map(|s| s.into())
+        .unwrap_or(Emission::Exe);
+    let target = matches
+        .opt_str("target")
+        .or(manifest.target)
+        .map(|t| TargetConfig::from_triple(&t))
+        .unwrap_or_else(TargetConfig::host);
+    let mut link_libs = manifest.libs;
+    link_libs.extend(matches.opt_strs("l"));
+    let mut lib_paths = manifest
+        .lib_paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    lib_paths.extend(matches.opt_strs("L"));
+    let mut features = manifest.features;
+    for fs in matches.opt_strs("features") {
+        features.extend(fs.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()))
+    }
 
     println!("    Compiling {}", inp_filename.path().display());
 
     let mut type_var_generator = lib::front::TypeVarGen::new(0);
     let sources = AddMap::new();
-    let mut ast = parse_program(inp_filename, &sources, &mut type_var_generator);
-    infer_types(&mut ast, &mut type_var_generator);
-    //println!("inferred: {:#?}", ast);
-    compile(
-        &ast,
-        out_filename,
-        explicit_out_filename,
-        emission,
-        &link_libs,
-        &lib_paths,
-    );
+    let mut ast = parse_program(inp_filename.clone(), &sources, &mut type_var_generator);
+    {
+        let mut disabled = ast.features
+            .iter()
+            .filter(|&(name, _)| !features.iter().any(|f| f == name))
+            .peekable();
+        if disabled.peek().is_some() {
+            for (name, pos) in disabled {
+                pos.print_error(format!("Feature `{}` is not enabled", name));
+                pos.print_help(format!("Enable it with `--features {}`", name));
+            }
+            exit()
+        }
+    }
+    if let Some(arg) = matches.opt_str("rename") {
+        let (line, column, new_name) = parse_refactor_arg(&arg).unwrap_or_else(|| {
+            error_exit(format!(
+                "Invalid rename `{}`. Expected `LINE:COLUMN=NEW-NAME`",
+                arg
+            ))
+        });
+        let index = index_symbols(&ast);
+        let def = index
+            .definition_at(inp_filename.path(), line, column)
+            .unwrap_or_else(|| error_exit(format!("No symbol to rename at {}:{}", line, column)));
+        let edits = index
+            .rename(def, new_name)
+            .unwrap_or_else(|e| error_exit(e));
+        write_front_emission(&out_filename, explicit_out_filename, "edits.json", |f| {
+            write_edits_json(&edits, f)
+        });
+        return;
+    }
+    if let Some(arg) = matches.opt_str("extract") {
+        let (line, column, name) = parse_refactor_arg(&arg).unwrap_or_else(|| {
+            error_exit(format!(
+                "Invalid extraction `{}`. Expected `LINE:COLUMN=NAME`",
+                arg
+            ))
+        });
+        let extract_sources = AddMap::new();
+        let csts = lex_file(inp_filename.clone(), &extract_sources);
+        let edits = index_symbols(&ast)
+            .extract_definition(&csts, line, column, name)
+            .unwrap_or_else(|e| error_exit(e));
+        write_front_emission(&out_filename, explicit_out_filename, "edits.json", |f| {
+            write_edits_json(&edits, f)
+        });
+        return;
+    }
+    // No plugins are built into the driver. Programs embedding the compiler through the
+    // `kvasir` library register their own in the same way
+    let mut plugins = Plugins::new();
+    for path in &manifest.plugins {
+        plugins.load(path).unwrap_or_else(|e| {
+            error_exit(format!("Failed to load plugin `{}`, {}", path.display(), e))
+        })
+    }
+    plugins.after_parse(&mut ast);
+    {
+        // The lints of syntax trees only check the main module
+        let lint_sources = AddMap::new();
+        let csts = lex_file(inp_filename.clone(), &lint_sources);
+        if !linter.run(&csts, &ast) {
+            exit()
+        }
+    }
+    if !check_termination(&ast) {
+        exit()
+    }
+    if matches.opt_present("interpret") {
+        infer_types(&mut ast, &mut type_var_generator);
+        plugins.after_typecheck(&mut ast);
+        let mut interpreter = Interpreter::new(&ast);
+        let coverage = matches.opt_str("coverage");
+        if coverage.is_some() {
+            interpreter.enable_coverage()
+        }
+        // As for a compiled program, the first argument is the name of the program, here the
+        // path of the source file of the entry point, followed by the arguments after it
+        let program_name = inp_filename.path().to_string_lossy().into_owned();
+        let program_args = iter::once(program_name)
+            .chain(matches.free.iter().skip(1).cloned())
+            .collect();
+        interpreter.run_main(program_args);
+        if let Some(path) = coverage {
+            fs::File::create(&path)
+                .and_then(|mut f| interpreter.write_lcov(&mut f))
+                .unwrap_or_else(|e| {
+                    error_exit(format!("Failed to write coverage report `{}`, {}", path, e))
+                });
+        }
+        return;
+    }
+    match emission {
+        Emission::Symbols => {
+            write_front_emission(&out_filename, explicit_out_filename, "json", |f| {
+                index_symbols(&ast).write_json(f)
+            })
+        }
+        Emission::CallGraph => {
+            write_front_emission(&out_filename, explicit_out_filename, "dot", |f| {
+                write_callgraph_dot(&ast, f)
+            })
+        }
+        Emission::Types => {
+            check_types(&mut ast, &mut type_var_generator);
+            plugins.after_typecheck(&mut ast);
+            write_front_emission(&out_filename, explicit_out_filename, "types", |f| {
+                write_inferred_types(&ast, f)
+            })
+        }
+        Emission::Interface => {
+            check_types(&mut ast, &mut type_var_generator);
+            plugins.after_typecheck(&mut ast);
+            write_front_emission(&out_filename, explicit_out_filename, "kvi", |f| {
+                write_interface(&ast, inp_filename.path(), f)
+            })
+        }
+        _ => {
+            infer_types(&mut ast, &mut type_var_generator);
+            plugins.after_typecheck(&mut ast);
+            plugins.before_emission(&ast);
+            //println!("inferred: {:#?}", ast);
+            compile(
+                &ast,
+                out_filename,
+                explicit_out_filename,
+                emission,
+                &target,
+                &link_libs,
+                &lib_paths,
+                &matches.opt_strs("sanitize"),
+            );
+        }
+    }
 
     println!(
         "    Finished building target in {} secs",